@@ -0,0 +1,858 @@
+//! Non-blocking mirror of [`RIPTables`](../struct.RIPTables.html) built on tokio.
+//!
+//! Enabled with the `async` cargo feature. Every method spawns iptables through
+//! `tokio::process` and waits for the xtables lock with `tokio::time::sleep`, so
+//! no runtime thread is blocked while iptables runs. What to run is planned by the same code as
+//! for `RIPTables`, only the I/O differs.
+//!
+//! `AsyncRIPTables` has the methods of `RIPTables` defined in the crate root and those reading
+//! the tables: `loaded_tables`, `supports_match`, `builtin_chains`, `chain_graph`, `lint`,
+//! `query`, `trace` and `check_lockout`. The helpers built on top of them are sync-only: scoped
+//! changes (`guard`, which undoes them in `Drop`), `apply_pending` and `apply_confirmed`
+//! (`confirm`), `apply_safe` (`lockout`), owned rules (`owner`), port forwards (`forward`),
+//! gateways (`gateway`), zones (`zone`), templates (`template`) and `dualstack`.
+
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::process::Stdio;
+use std::time::Instant;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::graph::ChainGraph;
+use crate::iptparser;
+use crate::lint::{self, Lint};
+use crate::lockout::{self, LockoutReport, Management};
+use crate::packet::{self, Packet, Path, Trace};
+use crate::plan;
+use crate::query::{self, Found, Query};
+use crate::rule::{Archive, RIPTRule};
+use crate::ruleset::{Change, Ruleset};
+use crate::table::{self, ChainName, Policy, Table};
+
+pub struct AsyncRIPTables {
+  /// The utility command which must be 'iptables' or 'ip6tables'.
+  pub cmd: &'static str,
+
+  /// Indicates if iptables has -C (--check) option
+  pub has_check: bool,
+
+  /// Indicates if iptables has -w (--wait) option
+  pub has_wait: bool,
+}
+
+#[cfg(target_os = "linux")]
+pub async fn new(ipv6: bool) -> RIPTResult<AsyncRIPTables> {
+  let cmd = if ipv6 { "ip6tables" } else { "iptables" };
+  let version_output = Command::new(cmd).arg("--version").output().await?;
+  let (has_check, has_wait) = crate::capabilities(&version_output)?;
+
+  Ok(AsyncRIPTables {
+    cmd,
+    has_check,
+    has_wait,
+  })
+}
+
+impl AsyncRIPTables {
+  /// Execute iptables command
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.execute(|iptables| iptables.args(&["-t", "nat", "-A", "TESTNAT", "-j", "ACCEPT"])).await.is_ok();
+  /// ```
+  pub async fn execute<T>(&self, caller: T) -> RIPTResult<(i32, String)> where T: Fn(&mut Command) -> &mut Command {
+    let mut command = Command::new(self.cmd);
+    caller(&mut command);
+
+    let mut file_lock = None;
+
+    if self.has_wait {
+      command.arg("--wait");
+    } else {
+      let file = tokio::fs::File::create(crate::XTABLES_OLD_LOCK).await?.into_std().await;
+      self::lock(&file).await?;
+      file_lock = Some(file);
+    }
+
+    let output = command.output().await?;
+    drop(file_lock);
+
+    crate::output_result(output)
+  }
+
+  /// Get the default policy for a table/chain.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.get_policy("filter", "INPUT").await.is_ok();
+  /// ```
+  pub async fn get_policy<T, C>(&self, table: T, chain: C) -> RIPTResult<Option<String>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    crate::check_builtin_chain(&table, &chain)?;
+
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S").arg(&chain)).await?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    crate::find_policy(table, &chain, output)
+  }
+
+  /// Set the default policy for a table/chain.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.set_policy("mangle", "FORWARD", "DROP").await.unwrap();
+  /// ```
+  pub async fn set_policy<T, C, P>(&self, table: T, chain: C, policy: P) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, P: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let policy = Policy::try_from(policy.as_ref())?;
+    crate::check_builtin_chain(&table, &chain)?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-P").arg(&chain).arg(policy)).await?;
+    Ok(code == 0)
+  }
+
+  /// Inserts `rule` in the `position` to the table/chain.
+  /// Returns `true` if the rule is inserted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.insert("nat", "TESTNAT", "-j ACCEPT", 1).await.unwrap();
+  /// ```
+  pub async fn insert<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = crate::rule_args(&table, "-I", &chain, Some(position), rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args)).await?;
+    Ok(code == 0)
+  }
+
+  /// Inserts `rule` in the `position` to the table/chain if it does not exist.
+  /// Returns `true` if the rule is inserted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.insert_unique("nat", "TESTNAT", "-j ACCEPT", 1).await.unwrap();
+  /// ```
+  pub async fn insert_unique<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(&table, &chain, rule.clone()).await? {
+      return Ok(true);
+    }
+    self.insert(table, &chain, rule, position).await
+  }
+
+  /// Replaces `rule` in the `position` to the table/chain.
+  /// Returns `true` if the rule is replaced.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.replace("nat", "TESTNAT", "-j ACCEPT", 1).await.unwrap();
+  /// ```
+  pub async fn replace<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = crate::rule_args(&table, "-R", &chain, Some(position), rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args)).await?;
+    Ok(code == 0)
+  }
+
+  /// Appends `rule` to the table/chain.
+  /// Returns `true` if the rule is appended.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.append("nat", "TESTNAT", "-m comment --comment \"double-quoted comment\" -j ACCEPT").await.unwrap();
+  /// ```
+  pub async fn append<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = crate::rule_args(&table, "-A", &chain, None, rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args)).await?;
+    Ok(code == 0)
+  }
+
+  /// Appends `rule` to the table/chain if it does not exist.
+  /// Returns `true` if the rule is appended.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.append_unique("nat", "TESTNAT", "-j ACCEPT").await.unwrap();
+  /// ```
+  pub async fn append_unique<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(&table, &chain, rule.clone()).await? {
+      return Ok(true);
+    }
+    self.append(table, &chain, rule).await
+  }
+
+  /// Appends or replaces `rule` to the table/chain if it does not exist.
+  /// Returns `true` if the rule is appended or replaced.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.append_replace("nat", "TESTNAT", "-j ACCEPT").await.unwrap();
+  /// ```
+  pub async fn append_replace<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(&table, &chain, rule.clone()).await?
+      && !self.delete(&table, &chain, rule.clone()).await? {
+      return Ok(false);
+    }
+    self.append(table, &chain, rule).await
+  }
+
+  /// Deletes `rule` from the table/chain.
+  /// Returns `true` if the rule is deleted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.delete("nat", "TESTNAT", "-j ACCEPT").await.unwrap();
+  /// ```
+  pub async fn delete<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = crate::rule_args(&table, "-D", &chain, None, rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args)).await?;
+    Ok(code == 0)
+  }
+
+  /// Deletes all repetition of the `rule` from the table/chain.
+  /// Returns `true` if the rules are deleted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.delete_all("nat", "TESTNAT", "-j ACCEPT").await.unwrap();
+  /// ```
+  pub async fn delete_all<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    while self.exists(&table, &chain, rule.clone()).await? {
+      self.delete(&table, &chain, rule.clone()).await?;
+    }
+    Ok(true)
+  }
+
+  /// Lists rules in the table/chain.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::rule::RIPTRule;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let rules: Vec<RIPTRule> = iptables.list("nat").await.unwrap();
+  /// ```
+  pub async fn list<T>(&self, table: T) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S")).await?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(iptparser::parse_rules(table.to_string(), output)?)
+  }
+
+  /// Lists the name of each chain in the table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let names = iptables.chain_names("nat").await;
+  /// ```
+  pub async fn chain_names<T>(&self, table: T) -> RIPTResult<Vec<String>> where T: AsRef<OsStr> {
+    Ok(self.list(table).await?.iter()
+      .filter(|item| item.archive == Archive::Policy || item.archive == Archive::NewChain)
+      .map(|item| item.chain.clone())
+      .collect::<Vec<String>>())
+  }
+
+  /// Lists rules in the table/chain.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::rule::RIPTRule;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let rules: Vec<RIPTRule> = iptables.list_chains("nat", "INPUT").await.unwrap();
+  /// ```
+  pub async fn list_chains<T, C>(&self, table: T, chain: C) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S").arg(&chain)).await?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(iptparser::parse_rules(table.to_string(), output)?)
+  }
+
+  /// Creates a new user-defined chain.
+  /// Returns `true` if the chain is created.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.new_chain("nat", "TESTNAT").await;
+  /// ```
+  pub async fn new_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-N").arg(&chain)).await?;
+    Ok(code == 0)
+  }
+
+  /// Deletes a user-defined chain in the table.
+  /// Returns `true` if the chain is deleted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.delete_chain("nat", "TESTNAT").await;
+  /// ```
+  pub async fn delete_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-X").arg(&chain)).await?;
+    Ok(code == 0)
+  }
+
+  /// Renames a chain in the table.
+  /// Returns `true` if the chain is renamed.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.rename_chain("nat", "TESTNAT", "OTHERNAME").await;
+  /// ```
+  pub async fn rename_chain<T, C, D>(&self, table: T, old_chain: C, new_chain: D) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, D: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let old_chain = ChainName::try_from(old_chain.as_ref())?;
+    let new_chain = ChainName::try_from(new_chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-E").arg(&old_chain).arg(&new_chain)).await?;
+    Ok(code == 0)
+  }
+
+  /// Flushes (deletes all rules) a chain.
+  /// Returns `true` if the chain is flushed.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.flush_chain("nat", "TESTNAT").await;
+  /// ```
+  pub async fn flush_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-F").arg(&chain)).await?;
+    Ok(code == 0)
+  }
+
+  /// Checks for the existence of the `chain` in the table.
+  /// Returns true if the chain exists.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.exists_chain("nat", "TESTNAT").await;
+  /// ```
+  pub async fn exists_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-L").arg(&chain)).await?;
+    Ok(code == 0)
+  }
+
+  /// Flushes all chains in a table.
+  /// Returns `true` if the chains are flushed.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.flush_table("nat").await;
+  /// ```
+  pub async fn flush_table<T>(&self, table: T) -> RIPTResult<bool> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-F")).await?;
+    Ok(code == 0)
+  }
+
+  /// Lists rules in the table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::rule::RIPTRule;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let rule: Vec<RIPTRule> = iptables.list_tables("nat").await.unwrap();
+  /// ```
+  pub async fn list_tables<T>(&self, table: T) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr> {
+    self.list(table).await
+  }
+
+  /// Checks for the existence of the `rule` in the table/chain.
+  /// Returns true if the rule exists.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.exists("nat", "TESTNAT", "-j ACCEPT").await.unwrap();
+  /// ```
+  pub async fn exists<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if !self.has_check {
+      return self.exists_old_version(&table, &chain, rule).await;
+    }
+
+    let args = crate::rule_args(&table, "-C", &chain, None, rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args)).await?;
+    Ok(code == 0)
+  }
+
+  /// Creates a user-defined chain unless it already exists.
+  /// Returns `true` if the chain exists afterwards.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.ensure_chain("filter", "MYSERVICE").await.unwrap();
+  /// ```
+  pub async fn ensure_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists_chain(&table, &chain).await? || self.new_chain(&table, &chain).await? {
+      return Ok(true);
+    }
+    // someone else may have created it in the meantime
+    self.exists_chain(&table, &chain).await
+  }
+
+  /// Makes sure `from_chain` jumps to `to_chain` exactly once, at `position`, for packets matching
+  /// `extra_match` (which may be empty), see `RIPTables::ensure_jump`.
+  /// Returns `true` if the jump is in place.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.ensure_chain("filter", "MYSERVICE").await.unwrap();
+  /// iptables.ensure_jump("filter", "INPUT", "MYSERVICE", 1, "-p tcp --dport 8080").await.unwrap();
+  /// ```
+  pub async fn ensure_jump<T, C, D, S>(&self, table: T, from_chain: C, to_chain: D, position: i32, extra_match: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, D: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let from_chain = ChainName::try_from(from_chain.as_ref())?;
+    let to_chain = ChainName::try_from(to_chain.as_ref())?;
+    let spec = plan::jump_spec(&crate::to_string(extra_match), to_chain.as_str());
+    let position = plan::jump_position(position);
+    let found = match plan::misplaced_jumps(&self.list_chains(&table, &from_chain).await?, &spec, position) {
+      Some(found) => found,
+      None => return Ok(true),
+    };
+
+    for index in &found {
+      let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-D").arg(&from_chain).arg(index.to_string())).await?;
+      if code != 0 {
+        return Err(RIPTError::Stderr(output));
+      }
+    }
+    // the jump may be written in a way `normalize` does not recognize
+    if found.is_empty() {
+      self.delete_all(&table, &from_chain, &spec).await?;
+    }
+
+    if plan::appends_at(&self.list_chains(&table, &from_chain).await?, position) {
+      self.append(&table, &from_chain, &spec).await
+    } else {
+      self.insert(&table, &from_chain, &spec, position as i32).await
+    }
+  }
+
+  /// Deletes a user-defined chain together with every rule jumping or going to it, see
+  /// `RIPTables::delete_chain_recursive`.
+  /// Returns `true` if the chains are deleted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.delete_chain_recursive("filter", "SERVICES", true).await.unwrap();
+  /// ```
+  pub async fn delete_chain_recursive<T, C>(&self, table: T, chain: C, children: bool) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?.to_string();
+    let chain = ChainName::try_from(chain.as_ref())?.to_string();
+    let removal = match plan::ChainRemoval::new(&table, &chain, &self.list(&table).await?, children)? {
+      Some(removal) => removal,
+      None => return Ok(false),
+    };
+
+    match self.execute_restore(&removal.script(), true).await {
+      Ok((code, _output)) => Ok(code == 0),
+      Err(RIPTError::Io(ref err)) if err.kind() == ErrorKind::NotFound => {
+        let mut deleted = true;
+        for rule in &removal.references {
+          deleted &= self.delete(&table, &rule.chain, &rule.spec()).await?;
+        }
+        for name in &removal.doomed {
+          deleted &= self.flush_chain(&table, name).await?;
+        }
+        for name in &removal.doomed {
+          deleted &= self.delete_chain(&table, name).await?;
+        }
+        Ok(deleted)
+      }
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Returns `true` if the match extension `name`, as given to `-m`, can be used, see
+  /// `RIPTables::supports_match`. Results are shared with `RIPTables`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.supports_match("hashlimit").await.unwrap();
+  /// ```
+  pub async fn supports_match(&self, name: &str) -> RIPTResult<bool> {
+    self.supports('m', name).await
+  }
+
+  /// Returns `true` if the target extension `name`, as given to `-j`, can be used, see
+  /// `RIPTables::supports_match`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// iptables.supports_target("TPROXY").await.unwrap();
+  /// ```
+  pub async fn supports_target(&self, name: &str) -> RIPTResult<bool> {
+    self.supports('j', name).await
+  }
+
+  /// Clears the cached results of `supports_match` and `supports_target`, shared with
+  /// `RIPTables`.
+  pub fn forget_supported(&self) {
+    table::forget_support(self.cmd)
+  }
+
+  /// The tables loaded in the kernel for this family, see `table::loaded_tables`.
+  pub fn loaded_tables(&self) -> RIPTResult<Vec<Table>> {
+    // reading /proc does not block
+    table::loaded_tables(self.is_ipv6())
+  }
+
+  /// The match extensions loaded in the kernel for this family, see `table::loaded_matches`.
+  pub fn loaded_matches(&self) -> RIPTResult<Vec<String>> {
+    table::loaded_matches(self.is_ipv6())
+  }
+
+  /// The target extensions loaded in the kernel for this family, see `table::loaded_targets`.
+  pub fn loaded_targets(&self) -> RIPTResult<Vec<String>> {
+    table::loaded_targets(self.is_ipv6())
+  }
+
+  /// The built-in chains of a table, see `RIPTables::builtin_chains`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// println!("{:?}", iptables.builtin_chains("nat").await.unwrap());
+  /// ```
+  pub async fn builtin_chains<T>(&self, table: T) -> RIPTResult<Vec<String>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    if let Some(chains) = table.builtin_chains() {
+      return Ok(chains.iter().map(|chain| chain.to_string()).collect());
+    }
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S")).await?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(plan::policy_chains(iptparser::parse_rules(table.to_string(), output)?))
+  }
+
+  /// Builds the chain graph of a table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// println!("{}", iptables.chain_graph("filter").await.unwrap().to_dot());
+  /// ```
+  pub async fn chain_graph<T>(&self, table: T) -> RIPTResult<ChainGraph> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let rules = self.list(&table).await?;
+    Ok(ChainGraph::from_rules(&table.to_string(), &rules))
+  }
+
+  /// Lints the chains of a table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// for lint in iptables.lint("filter").await.unwrap() {
+  ///   println!("{}", lint);
+  /// }
+  /// ```
+  pub async fn lint<T>(&self, table: T) -> RIPTResult<Vec<Lint>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let ruleset = Ruleset::from_rules(self.list(&table).await?);
+    Ok(lint::lint_ruleset(&ruleset))
+  }
+
+  /// Returns the current rules meeting every criterion of `query`, see `RIPTables::query`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::query::Query;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let found = iptables.query(&Query::new().target("DROP")).await.unwrap();
+  /// ```
+  pub async fn query(&self, query: &Query) -> RIPTResult<Vec<Found>> {
+    let rules = match query.table {
+      Some(ref table) => self.list(table).await?,
+      None => self.list_loaded(query::QUERIED_TABLES).await?,
+    };
+    Ok(query.select(&Ruleset::from_rules(rules)))
+  }
+
+  /// Traces `packet` through the current tables, see `RIPTables::trace`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::packet::{Packet, Path};
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let ssh = Packet::new("tcp").dport(22).state("NEW");
+  /// println!("{}", iptables.trace(Path::Input, &ssh).await.unwrap());
+  /// ```
+  pub async fn trace(&self, path: Path, packet: &Packet) -> RIPTResult<Trace> {
+    let rules = self.list_loaded(packet::TRACED_TABLES).await?;
+    Ok(packet::trace(&Ruleset::from_rules(rules), path, packet))
+  }
+
+  /// Checks what `desired` would do to the management connection once applied, see
+  /// `RIPTables::check_lockout`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::lockout::Management;
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let desired = Ruleset::parse("*filter\n:INPUT DROP\nCOMMIT\n").unwrap();
+  /// let report = iptables.check_lockout(&desired, &Management::new("10.0.0.1".parse().unwrap(), 22)).await.unwrap();
+  /// ```
+  pub async fn check_lockout(&self, desired: &Ruleset, management: &Management) -> RIPTResult<LockoutReport> {
+    Ok(lockout::check_with(desired, &self.save(&["filter"]).await?, management))
+  }
+
+  /// Lists the rules of `tables` the kernel has loaded, see `plan::loaded`.
+  async fn list_loaded(&self, tables: &[&str]) -> RIPTResult<Vec<RIPTRule>> {
+    let mut rules = vec![];
+    for table in plan::loaded(tables, &self.loaded_tables()?) {
+      rules.extend(self.list(table).await?);
+    }
+    Ok(rules)
+  }
+
+  fn is_ipv6(&self) -> bool {
+    self.cmd == "ip6tables"
+  }
+
+  async fn supports(&self, kind: char, name: &str) -> RIPTResult<bool> {
+    if let Some(known) = table::known_support(self.cmd, kind, name) {
+      return Ok(known);
+    }
+
+    // reading /proc does not block
+    let known = table::is_loaded(self.is_ipv6(), kind, name)? || {
+      let (code, _output) = self.execute(|iptables| iptables.args(table::help_args(kind, name))).await?;
+      code == 0
+    };
+    table::remember_support(self.cmd, kind, name, known);
+    Ok(known)
+  }
+
+  /// Takes a snapshot of the given tables.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let ruleset = iptables.save(&["filter", "nat"]).await.unwrap();
+  /// println!("{}", ruleset);
+  /// ```
+  pub async fn save<T>(&self, tables: &[T]) -> RIPTResult<Ruleset> where T: AsRef<OsStr> {
+    let mut rules = vec![];
+    for table in tables {
+      rules.extend(self.list(table).await?);
+    }
+    Ok(Ruleset::from_rules(rules))
+  }
+
+  /// Replaces every table of `ruleset` with its content in a single `iptables-restore` call.
+  /// Tables not mentioned in `ruleset` are left untouched.
+  /// Returns `true` if the ruleset is restored.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let ruleset = iptables.save(&["filter"]).await.unwrap();
+  /// iptables.restore(&ruleset).await.unwrap();
+  /// ```
+  pub async fn restore(&self, ruleset: &Ruleset) -> RIPTResult<bool> {
+    let (code, _output) = self.execute_restore(&ruleset.to_string(), false).await?;
+    Ok(code == 0)
+  }
+
+  /// Brings the tables of `desired` to the exact content of `desired`.
+  /// Returns the changes between the current and the desired state; nothing is changed when
+  /// `dry_run` is set or the tables are already up to date.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let desired = Ruleset::parse("*filter\n:INPUT ACCEPT\nCOMMIT\n").unwrap();
+  /// for change in iptables.apply(&desired, true).await.unwrap() {
+  ///   println!("{}", change);
+  /// }
+  /// ```
+  pub async fn apply(&self, desired: &Ruleset, dry_run: bool) -> RIPTResult<Vec<Change>> {
+    let current = self.save(&plan::applied_tables(desired)).await?;
+    let (changes, script) = plan::apply(&current, desired, dry_run);
+    if let Some(script) = script {
+      let (code, output) = self.execute_restore(&script, false).await?;
+      if code != 0 {
+        return Err(RIPTError::Stderr(output));
+      }
+    }
+    Ok(changes)
+  }
+
+  /// Adds the content of `ruleset` to the current tables without removing anything:
+  /// missing chains are created, policies are set and rules that do not exist yet are appended.
+  /// Returns `true` if every change succeeded.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::asynchronous::new(false).await.unwrap();
+  /// let ruleset = Ruleset::parse("*filter\n-A INPUT -i lo -j ACCEPT\nCOMMIT\n").unwrap();
+  /// iptables.append_ruleset(&ruleset).await.unwrap();
+  /// ```
+  pub async fn append_ruleset(&self, ruleset: &Ruleset) -> RIPTResult<bool> {
+    let mut ok = true;
+    for table in &ruleset.tables {
+      for chain in plan::user_chains(table) {
+        if !self.exists_chain(&table.name, &chain.name).await? {
+          ok &= self.new_chain(&table.name, &chain.name).await?;
+        }
+      }
+      for chain in &table.chains {
+        if let Some(ref policy) = chain.policy {
+          ok &= self.set_policy(&table.name, &chain.name, policy).await?;
+        }
+        for rule in &chain.rules {
+          ok &= self.append_unique(&table.name, &chain.name, &rule.spec()).await?;
+        }
+      }
+    }
+    Ok(ok)
+  }
+
+  /// Feeds `input` to `iptables-restore` (or `ip6tables-restore`).
+  async fn execute_restore(&self, input: &str, noflush: bool) -> RIPTResult<(i32, String)> {
+    let mut command = Command::new(format!("{}-restore", self.cmd));
+    command.args(plan::restore_args(noflush, self.has_wait));
+
+    let mut file_lock = None;
+    if !self.has_wait {
+      let file = tokio::fs::File::create(crate::XTABLES_OLD_LOCK).await?.into_std().await;
+      self::lock(&file).await?;
+      file_lock = Some(file);
+    }
+
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin.write_all(input.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    drop(file_lock);
+
+    crate::output_result(output)
+  }
+
+  async fn exists_old_version<S>(&self, table: &Table, chain: &ChainName, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table).arg("-S")).await?;
+    if code != 0 {
+      return Ok(false);
+    }
+    Ok(crate::contains_rule(&output, chain, rule))
+  }
+}
+
+/// Takes the xtables lock like `crate::lock`, without blocking the runtime between attempts.
+async fn lock(file: &std::fs::File) -> RIPTResult<()> {
+  let start = Instant::now();
+  while !crate::try_lock(file)? {
+    if start.elapsed() >= crate::LOCK_TIMEOUT {
+      return Err(RIPTError::Other("timed out waiting for the xtables lock"));
+    }
+    tokio::time::sleep(crate::LOCK_RETRY_INTERVAL).await;
+  }
+  Ok(())
+}
@@ -0,0 +1,306 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::process;
+use std::time::Duration;
+
+use riptables::dualstack;
+use riptables::error::{RIPTError, RIPTResult};
+use riptables::lint::{self, Lint};
+use riptables::lockout::Management;
+use riptables::packet::{self, Packet, Path, Verdict};
+use riptables::query::Query;
+use riptables::ruleset::{self, Ruleset};
+use riptables::RIPTables;
+
+const USAGE: &str = "usage: riptables [-6] <command> [options]
+
+commands:
+  list [-t <table>] [--json]             list the rules of a table (default: filter)
+  save [-t <table>]...                   print tables in the canonical save format
+  restore [<file>]                       replace tables with a saved ruleset (default: stdin)
+  diff <a> <b>                           print the changes turning ruleset <a> into <b>
+  apply <desired> [--dry-run]            bring tables to the content of <desired>
+  apply <desired> --confirm <seconds>    roll back unless confirmed on stdin within <seconds>
+  apply <desired> --management <address>:<port> [--force]
+                                         refuse to cut the given management connection
+  check [-t <table>] <chain> <rule>...   exit with 0 if the rule exists in the chain
+  trace [-f <ruleset>] <input|forward|output> [-p <protocol>] [-s <address>] [-d <address>]
+        [--sport <port>] [--dport <port>] [-i <interface>] [-o <interface>] [--state <state>]
+                                         follow a packet through the tables, exit with 0 if accepted
+  query [-f <ruleset>] <key>=<value>...  print the rules matching every pair, exit with 0 if some do
+                                         keys: table chain target proto port addr iface module comment
+  parity [-t <table>]...                 compare the IPv4 and IPv6 tables, exit with 0 if they agree
+  lint [-t <table>] [-f <ruleset>]       report duplicate, shadowed and empty-chain rules, exit with 0 if none
+  policy get [-t <table>] <chain>        print the policy of a built-in chain
+  policy set [-t <table>] <chain> <policy>
+
+rulesets are read in the save format, or as configuration files when their name ends with .toml, .yaml or .yml
+
+options:
+  -6                                     use ip6tables instead of iptables";
+
+/// Tables saved when `save` is called without `-t`.
+const DEFAULT_TABLES: &[&str] = &["filter", "nat", "mangle", "raw"];
+
+fn main() {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  let ipv6 = take_flag(&mut args, "-6");
+
+  match run(ipv6, args) {
+    Ok(code) => process::exit(code),
+    Err(err) => {
+      eprintln!("riptables: {}", err);
+      process::exit(2);
+    }
+  }
+}
+
+fn run(ipv6: bool, mut args: Vec<String>) -> RIPTResult<i32> {
+  if args.is_empty() {
+    return usage();
+  }
+  let command = args.remove(0);
+  match &command[..] {
+    "list" => {
+      let json = take_flag(&mut args, "--json");
+      let table = take_table(&mut args)?.unwrap_or_else(|| "filter".to_string());
+      let rules = riptables(ipv6)?.list(&table)?;
+      if json {
+        println!("{}", serde_json::to_string_pretty(&rules).map_err(|err| RIPTError::Stderr(err.to_string()))?);
+      } else {
+        rules.iter().for_each(|rule| println!("{}", rule.origin));
+      }
+      Ok(0)
+    }
+    "save" => {
+      let mut tables = vec![];
+      while let Some(table) = take_table(&mut args)? {
+        tables.push(table);
+      }
+      if tables.is_empty() {
+        tables = DEFAULT_TABLES.iter().map(|table| table.to_string()).collect();
+      }
+      print!("{}", riptables(ipv6)?.save(&tables)?);
+      Ok(0)
+    }
+    "restore" => {
+      let ruleset = read_ruleset(args.first().map(|path| &path[..]))?;
+      Ok(if riptables(ipv6)?.restore(&ruleset)? { 0 } else { 1 })
+    }
+    "diff" => {
+      if args.len() != 2 {
+        return usage();
+      }
+      let changes = ruleset::diff(&read_ruleset(Some(&args[0]))?, &read_ruleset(Some(&args[1]))?);
+      changes.iter().for_each(|change| println!("{}", change));
+      Ok(if changes.is_empty() { 0 } else { 1 })
+    }
+    "apply" => {
+      let dry_run = take_flag(&mut args, "--dry-run");
+      let confirm = take_option(&mut args, "--confirm")?;
+      let management = take_option(&mut args, "--management")?;
+      let force = take_flag(&mut args, "--force");
+      if args.len() != 1 {
+        return usage();
+      }
+      if dry_run && confirm.is_some() {
+        return Err(RIPTError::Other("--dry-run and --confirm cannot be used together"));
+      }
+      let desired = read_ruleset(Some(&args[0]))?;
+      if let Some(management) = management {
+        let address: SocketAddr = management.parse().map_err(|_| RIPTError::Other("--management expects <address>:<port>"))?;
+        let report = riptables(ipv6)?.check_lockout(&desired, &Management::new(address.ip(), address.port()))?;
+        if !report.is_safe() && !force {
+          return Err(RIPTError::Lockout(format!("refusing to apply, the management connection may be cut (use --force to apply anyway)\n{}", report)));
+        }
+      }
+      if let Some(seconds) = confirm {
+        let timeout = Duration::from_secs(seconds.parse()?);
+        eprint!("ruleset applied, keep it? [y/N] ");
+        let kept = riptables(ipv6)?.apply_confirmed(&desired, timeout, || {
+          let mut answer = String::new();
+          io::stdin().read_line(&mut answer).is_ok() && ["y", "yes"].contains(&answer.trim())
+        })?;
+        if !kept {
+          eprintln!("\nnot confirmed, previous ruleset restored");
+        }
+        return Ok(if kept { 0 } else { 1 });
+      }
+      for change in riptables(ipv6)?.apply(&desired, dry_run)? {
+        println!("{}", change);
+      }
+      Ok(0)
+    }
+    "check" => {
+      let table = take_table(&mut args)?.unwrap_or_else(|| "filter".to_string());
+      if args.len() < 2 {
+        return usage();
+      }
+      let chain = args.remove(0);
+      let rule = join_rule(&args);
+      let exists = riptables(ipv6)?.exists(&table, &chain, &rule)?;
+      Ok(if exists { 0 } else { 1 })
+    }
+    "trace" => {
+      let file = take_option(&mut args, "-f")?;
+      let mut packet = Packet::new(take_option(&mut args, "-p")?.unwrap_or_else(|| "tcp".to_string()));
+      packet.source = take_option(&mut args, "-s")?.map(|address| address.parse()).transpose().map_err(|_| RIPTError::Other("invalid address"))?;
+      packet.destination = take_option(&mut args, "-d")?.map(|address| address.parse()).transpose().map_err(|_| RIPTError::Other("invalid address"))?;
+      packet.sport = take_option(&mut args, "--sport")?.map(|port| port.parse()).transpose()?;
+      packet.dport = take_option(&mut args, "--dport")?.map(|port| port.parse()).transpose()?;
+      packet.in_interface = take_option(&mut args, "-i")?;
+      packet.out_interface = take_option(&mut args, "-o")?;
+      packet.state = take_option(&mut args, "--state")?.map(|state| state.to_uppercase());
+      let path = match args.first().map(|path| &path[..]) {
+        Some("input") if args.len() == 1 => Path::Input,
+        Some("forward") if args.len() == 1 => Path::Forward,
+        Some("output") if args.len() == 1 => Path::Output,
+        _ => return usage(),
+      };
+      let trace = match file {
+        Some(file) => packet::trace(&read_ruleset(Some(&file))?, path, &packet),
+        None => riptables(ipv6)?.trace(path, &packet)?,
+      };
+      println!("{}", trace);
+      Ok(if trace.verdict == Verdict::Accept { 0 } else { 1 })
+    }
+    "query" => {
+      let file = take_option(&mut args, "-f")?;
+      let query = Query::parse(args.join(" "))?;
+      let found = match file {
+        Some(file) => query.select(&read_ruleset(Some(&file))?),
+        None => riptables(ipv6)?.query(&query)?,
+      };
+      found.iter().for_each(|found| println!("{}", found));
+      Ok(if found.is_empty() { 1 } else { 0 })
+    }
+    "parity" => {
+      let mut tables = vec![];
+      while let Some(table) = take_table(&mut args)? {
+        tables.push(table);
+      }
+      if !args.is_empty() {
+        return usage();
+      }
+      if tables.is_empty() {
+        tables = DEFAULT_TABLES.iter().map(|table| table.to_string()).collect();
+      }
+      let differences = dualstack::new()?.parity(&tables)?;
+      differences.iter().for_each(|difference| println!("{}", difference));
+      Ok(if differences.is_empty() { 0 } else { 1 })
+    }
+    "lint" => {
+      let file = take_option(&mut args, "-f")?;
+      let table = take_table(&mut args)?;
+      if !args.is_empty() {
+        return usage();
+      }
+      let ruleset = match file {
+        Some(file) => read_ruleset(Some(&file))?,
+        None => riptables(ipv6)?.save(&[table.clone().unwrap_or_else(|| "filter".to_string())])?,
+      };
+      let lints: Vec<Lint> = match table {
+        Some(table) => ruleset.table(&table).map(lint::lint_table).unwrap_or_default(),
+        None => lint::lint_ruleset(&ruleset),
+      };
+      lints.iter().for_each(|lint| println!("{}", lint));
+      Ok(if lints.is_empty() { 0 } else { 1 })
+    }
+    "policy" => {
+      if args.is_empty() {
+        return usage();
+      }
+      let action = args.remove(0);
+      let table = take_table(&mut args)?.unwrap_or_else(|| "filter".to_string());
+      match (&action[..], args.len()) {
+        ("get", 1) => {
+          match riptables(ipv6)?.get_policy(&table, &args[0])? {
+            Some(policy) => println!("{}", policy),
+            None => println!("-"),
+          }
+          Ok(0)
+        }
+        ("set", 2) => Ok(if riptables(ipv6)?.set_policy(&table, &args[0], &args[1])? { 0 } else { 1 }),
+        _ => usage(),
+      }
+    }
+    "help" | "-h" | "--help" => {
+      println!("{}", USAGE);
+      Ok(0)
+    }
+    _ => usage(),
+  }
+}
+
+fn riptables(ipv6: bool) -> RIPTResult<RIPTables> {
+  riptables::new(ipv6)
+}
+
+fn usage() -> RIPTResult<i32> {
+  eprintln!("{}", USAGE);
+  Ok(2)
+}
+
+/// Removes `flag` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+  match args.iter().position(|arg| arg == flag) {
+    Some(index) => {
+      args.remove(index);
+      true
+    }
+    None => false,
+  }
+}
+
+/// Removes the first `<option> <value>` pair from `args`.
+fn take_option(args: &mut Vec<String>, option: &'static str) -> RIPTResult<Option<String>> {
+  match args.iter().position(|arg| arg == option) {
+    Some(index) if index + 1 < args.len() => {
+      args.remove(index);
+      Ok(Some(args.remove(index)))
+    }
+    Some(_) => Err(RIPTError::Other("option requires a value")),
+    None => Ok(None),
+  }
+}
+
+/// Removes the first `-t <table>` pair from `args`.
+fn take_table(args: &mut Vec<String>) -> RIPTResult<Option<String>> {
+  match args.iter().position(|arg| arg == "-t" || arg == "--table") {
+    Some(index) if index + 1 < args.len() => {
+      args.remove(index);
+      Ok(Some(args.remove(index)))
+    }
+    Some(_) => Err(RIPTError::Other("option -t requires a table name")),
+    None => Ok(None),
+  }
+}
+
+/// Joins shell arguments back into a rule, quoting the ones containing spaces.
+fn join_rule(args: &[String]) -> String {
+  args.iter()
+    .map(|arg| if arg.contains(' ') { format!("\"{}\"", arg) } else { arg.clone() })
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+/// Reads a ruleset from `path`, or from stdin when `path` is `None` or `-`.
+/// Files ending with `.toml`, `.yaml` or `.yml` are read as configuration files, anything else in
+/// the save format.
+fn read_ruleset(path: Option<&str>) -> RIPTResult<Ruleset> {
+  let config = |path: &&str| [".toml", ".yaml", ".yml"].iter().any(|extension| path.ends_with(extension));
+  if let Some(path) = path.filter(config) {
+    return riptables::config::load_file(path);
+  }
+  let text = match path {
+    Some(path) if path != "-" => fs::read_to_string(path)?,
+    _ => {
+      let mut text = String::new();
+      io::stdin().read_to_string(&mut text)?;
+      text
+    }
+  };
+  Ruleset::parse(text)
+}
@@ -0,0 +1,199 @@
+//! Assembles rule specifications from typed parts.
+//!
+//! Values starting with `!` are negated, e.g. `.source("!10.0.0.0/8")` builds `! -s 10.0.0.0/8`.
+//! Parts and conntrack states are written in the order `iptables -S` prints them, and service and
+//! protocol names as numbers and names the way it does (see `services`), so built rules compare
+//! equal to the rules read back from `list` after `rule::normalize`, which also adds the prefix
+//! length of host addresses.
+
+use crate::services::Services;
+
+/// Conntrack states in the order `iptables -S` prints them.
+const STATE_ORDER: &[&str] = &["INVALID", "NEW", "RELATED", "ESTABLISHED", "UNTRACKED", "SNAT", "DNAT"];
+
+/// Builder for a rule specification to pass to `append`, `insert`, `delete`...
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::builder::RuleBuilder;
+///
+/// let rule = RuleBuilder::new()
+///   .in_interface("eth0")
+///   .protocol("tcp")
+///   .dport(22)
+///   .comment("ssh")
+///   .jump("ACCEPT")
+///   .build();
+/// assert_eq!(rule, "-i eth0 -p tcp -m tcp --dport 22 -m comment --comment ssh -j ACCEPT");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RuleBuilder {
+  source: Option<String>,
+  destination: Option<String>,
+  in_interface: Option<String>,
+  out_interface: Option<String>,
+  protocol: Option<String>,
+  sport: Option<String>,
+  dport: Option<String>,
+  state: Vec<String>,
+  matches: Vec<String>,
+  comment: Option<String>,
+  target: Option<(&'static str, String)>,
+}
+
+impl RuleBuilder {
+  pub fn new() -> RuleBuilder {
+    RuleBuilder::default()
+  }
+
+  /// `-s`, a source address or network.
+  pub fn source<S: ToString>(mut self, source: S) -> RuleBuilder {
+    self.source = Some(source.to_string());
+    self
+  }
+
+  /// `-d`, a destination address or network.
+  pub fn destination<S: ToString>(mut self, destination: S) -> RuleBuilder {
+    self.destination = Some(destination.to_string());
+    self
+  }
+
+  /// `-i`, the interface a packet was received on.
+  pub fn in_interface<S: ToString>(mut self, interface: S) -> RuleBuilder {
+    self.in_interface = Some(interface.to_string());
+    self
+  }
+
+  /// `-o`, the interface a packet is going to be sent on.
+  pub fn out_interface<S: ToString>(mut self, interface: S) -> RuleBuilder {
+    self.out_interface = Some(interface.to_string());
+    self
+  }
+
+  /// `-p`, the protocol of the packet.
+  pub fn protocol<S: ToString>(mut self, protocol: S) -> RuleBuilder {
+    self.protocol = Some(protocol.to_string());
+    self
+  }
+
+  /// `--sport`, a source port or `first:last` range. Requires a protocol.
+  pub fn sport<S: ToString>(mut self, port: S) -> RuleBuilder {
+    self.sport = Some(port.to_string());
+    self
+  }
+
+  /// `--dport`, a destination port or `first:last` range. Requires a protocol.
+  pub fn dport<S: ToString>(mut self, port: S) -> RuleBuilder {
+    self.dport = Some(port.to_string());
+    self
+  }
+
+  /// `-m conntrack --ctstate`, e.g. `&["RELATED", "ESTABLISHED"]`. States are written in the
+  /// order iptables prints them.
+  pub fn state<S: ToString>(mut self, states: &[S]) -> RuleBuilder {
+    self.state = states.iter().map(|state| state.to_string()).collect();
+    self.state.sort_by_key(|state| STATE_ORDER.iter().position(|known| *known == state).unwrap_or(STATE_ORDER.len()));
+    self
+  }
+
+  /// Any other match, appended as is, e.g. `"-m limit --limit 5/min"`.
+  pub fn matches<S: ToString>(mut self, matches: S) -> RuleBuilder {
+    self.matches.push(matches.to_string());
+    self
+  }
+
+  /// `-m set --match-set`, matching the packets whose addresses or ports are in the ipset `name`.
+  /// `flags` tell which ones, e.g. `src` or `dst,dst` for a `hash:ip,port` set.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::builder::RuleBuilder;
+  ///
+  /// let rule = RuleBuilder::new().match_set("!allowlist", "src").jump("DROP").build();
+  /// assert_eq!(rule, "-m set ! --match-set allowlist src -j DROP");
+  /// ```
+  pub fn match_set<S: ToString>(mut self, name: S, flags: S) -> RuleBuilder {
+    let name = name.to_string();
+    let matches = match name.strip_prefix('!') {
+      Some(name) => format!("-m set ! --match-set {} {}", name.trim(), flags.to_string()),
+      None => format!("-m set --match-set {} {}", name, flags.to_string()),
+    };
+    self.matches.push(matches);
+    self
+  }
+
+  /// `-m comment --comment`.
+  pub fn comment<S: ToString>(mut self, comment: S) -> RuleBuilder {
+    self.comment = Some(comment.to_string());
+    self
+  }
+
+  /// `-j`, the target of the rule.
+  pub fn jump<S: ToString>(mut self, target: S) -> RuleBuilder {
+    self.target = Some(("-j", target.to_string()));
+    self
+  }
+
+  /// `-g`, continue processing in a user-defined chain without returning.
+  pub fn goto<S: ToString>(mut self, chain: S) -> RuleBuilder {
+    self.target = Some(("-g", chain.to_string()));
+    self
+  }
+
+  /// Builds the rule specification.
+  pub fn build(&self) -> String {
+    let mut parts: Vec<String> = vec![];
+    self::push_option(&mut parts, "-s", &self.source);
+    self::push_option(&mut parts, "-d", &self.destination);
+    self::push_option(&mut parts, "-i", &self.in_interface);
+    self::push_option(&mut parts, "-o", &self.out_interface);
+    let services = Services::system();
+    // template variables are left for `expand`
+    let name = |protocol: &str| if protocol.starts_with('$') { protocol.to_string() } else { services.protocol_name(protocol) };
+    let protocol = self.protocol.as_ref().map(|protocol| match protocol.strip_prefix('!') {
+      Some(protocol) => format!("!{}", name(protocol.trim())),
+      None => name(protocol),
+    });
+    self::push_option(&mut parts, "-p", &protocol);
+    if self.sport.is_some() || self.dport.is_some() {
+      let protocol = protocol.as_ref().map(|protocol| protocol.trim_start_matches('!').to_string());
+      if let Some(ref protocol) = protocol {
+        parts.push(format!("-m {}", protocol));
+      }
+      let ports = |ports: &Option<String>| ports.as_ref().map(|ports| services.ports(ports, protocol.as_deref()));
+      self::push_option(&mut parts, "--sport", &ports(&self.sport));
+      self::push_option(&mut parts, "--dport", &ports(&self.dport));
+    }
+    if !self.state.is_empty() {
+      parts.push(format!("-m conntrack --ctstate {}", self.state.join(",")));
+    }
+    parts.extend(self.matches.iter().cloned());
+    if let Some(ref comment) = self.comment {
+      parts.push(format!("-m comment --comment {}", self::quote(comment)));
+    }
+    if let Some((flag, ref target)) = self.target {
+      parts.push(format!("{} {}", flag, target));
+    }
+    parts.join(" ")
+  }
+}
+
+fn push_option(parts: &mut Vec<String>, flag: &str, value: &Option<String>) {
+  if let Some(ref value) = *value {
+    match value.strip_prefix('!') {
+      Some(value) => parts.push(format!("! {} {}", flag, value.trim())),
+      None => parts.push(format!("{} {}", flag, value)),
+    }
+  }
+}
+
+/// Quotes `text` the way `iptables -S` does when it contains spaces.
+fn quote(text: &str) -> String {
+  if text.is_empty() || text.contains(' ') {
+    format!("\"{}\"", text)
+  } else {
+    text.to_string()
+  }
+}
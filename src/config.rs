@@ -0,0 +1,462 @@
+//! Declarative ruleset files.
+//!
+//! Enabled with the `config` cargo feature. A configuration file is TOML or YAML listing chains,
+//! their policies and their rules with typed fields:
+//!
+//! ```toml
+//! [[chains]]
+//! table = "filter"              # optional, defaults to "filter"
+//! name = "INPUT"
+//! policy = "DROP"               # only for built-in chains, ACCEPT or DROP
+//!
+//! [[chains.rules]]
+//! in_interface = "lo"
+//! jump = "ACCEPT"
+//!
+//! [[chains.rules]]
+//! state = ["RELATED", "ESTABLISHED"]
+//! jump = "ACCEPT"
+//!
+//! [[chains.rules]]
+//! protocol = "tcp"
+//! source = "!10.0.0.0/8"        # a leading ! negates the match
+//! dport = 22                    # a port, a service name or a "first:last" range
+//! comment = "ssh"
+//! jump = "SSH"
+//!
+//! [[chains]]
+//! name = "SSH"
+//!
+//! [[chains.rules]]
+//! jump = "ACCEPT"
+//! ```
+//!
+//! Rule fields are `protocol`, `source`, `destination`, `in_interface`, `out_interface`, `sport`,
+//! `dport`, `state`, `comment`, `matches` (any other match, written as for iptables), `jump`
+//! (target, optionally followed by its options) and `goto`. The loader validates every value and
+//! reports the line and column of the first invalid one, then returns a `Ruleset` which can be
+//! passed to `RIPTables::apply`, `RIPTables::append_ruleset` or `RIPTables::restore`.
+//!
+//! The same file in YAML, read by `load_yaml` or `load_file` for names ending with `.yaml` or
+//! `.yml`:
+//!
+//! ```yaml
+//! chains:
+//!   - name: INPUT
+//!     policy: DROP
+//!     rules:
+//!       - in_interface: lo
+//!         jump: ACCEPT
+//!       - protocol: tcp
+//!         dport: 22
+//!         jump: SSH
+//! ```
+//!
+//! YAML keeps no position for parsed values, so only its syntax errors have a line and column;
+//! other errors name the invalid field instead, e.g. `chains[0].rules[1].dport`.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use toml::{Spanned, Value};
+
+use crate::builder::RuleBuilder;
+use crate::error::{RIPTConfigError, RIPTResult};
+use crate::iptparser;
+use crate::ruleset::Ruleset;
+use crate::services::Services;
+use crate::table::{ChainName, Table};
+
+/// Longest interface name accepted by the kernel.
+const INTERFACE_NAME_MAX_LEN: usize = 15;
+const CONNTRACK_STATES: &[&str] = &["INVALID", "NEW", "ESTABLISHED", "RELATED", "UNTRACKED", "SNAT", "DNAT"];
+
+/// A value of a configuration file, with its position when the format keeps it.
+trait Field<T> {
+  fn get_ref(&self) -> &T;
+  fn span(&self) -> Option<(usize, usize)>;
+
+  /// Where the value is in the file, for formats without positions.
+  fn path(&self) -> Option<&str> {
+    None
+  }
+
+  fn set_path(&mut self, _path: String) {}
+}
+
+impl<T> Field<T> for Spanned<T> {
+  fn get_ref(&self) -> &T {
+    Spanned::get_ref(self)
+  }
+
+  fn span(&self) -> Option<(usize, usize)> {
+    Some(Spanned::span(self))
+  }
+}
+
+/// A value of a YAML file, whose position serde_yaml does not keep, with its path instead.
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct Plain<T> {
+  value: T,
+  #[serde(skip)]
+  path: String,
+}
+
+impl<T> Field<T> for Plain<T> {
+  fn get_ref(&self) -> &T {
+    &self.value
+  }
+
+  fn span(&self) -> Option<(usize, usize)> {
+    None
+  }
+
+  fn path(&self) -> Option<&str> {
+    Some(&self.path)
+  }
+
+  fn set_path(&mut self, path: String) {
+    self.path = path;
+  }
+}
+
+/// How the values of a configuration file format are read.
+trait Format {
+  type Text: Field<String> + DeserializeOwned;
+  type Port: Field<Value> + DeserializeOwned;
+  type List: Field<Vec<String>> + DeserializeOwned;
+}
+
+struct TomlFormat;
+
+impl Format for TomlFormat {
+  type Text = Spanned<String>;
+  type Port = Spanned<Value>;
+  type List = Spanned<Vec<String>>;
+}
+
+struct YamlFormat;
+
+impl Format for YamlFormat {
+  type Text = Plain<String>;
+  type Port = Plain<Value>;
+  type List = Plain<Vec<String>>;
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, bound = "")]
+struct ConfigFile<F: Format> {
+  #[serde(default)]
+  chains: Vec<ChainConfig<F>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, bound = "")]
+struct ChainConfig<F: Format> {
+  table: Option<F::Text>,
+  name: F::Text,
+  policy: Option<F::Text>,
+  #[serde(default)]
+  rules: Vec<RuleConfig<F>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, bound = "")]
+struct RuleConfig<F: Format> {
+  protocol: Option<F::Text>,
+  source: Option<F::Text>,
+  destination: Option<F::Text>,
+  in_interface: Option<F::Text>,
+  out_interface: Option<F::Text>,
+  sport: Option<F::Port>,
+  dport: Option<F::Port>,
+  state: Option<F::List>,
+  comment: Option<F::Text>,
+  matches: Option<F::Text>,
+  jump: Option<F::Text>,
+  goto: Option<F::Text>,
+}
+
+/// Loads a ruleset from a configuration file, read as YAML when its name ends with `.yaml` or
+/// `.yml` and as TOML otherwise.
+///
+/// # Example
+///
+/// ```rust
+/// let ruleset = riptables::config::load_file("firewall.toml").unwrap();
+/// let iptables = riptables::new(false).unwrap();
+/// iptables.apply(&ruleset, false).unwrap();
+/// ```
+pub fn load_file<P: AsRef<Path>>(path: P) -> RIPTResult<Ruleset> {
+  let yaml = path.as_ref().extension().is_some_and(|extension| extension == "yaml" || extension == "yml");
+  let text = fs::read_to_string(path)?;
+  if yaml {
+    self::load_yaml(text)
+  } else {
+    self::load(text)
+  }
+}
+
+/// Loads a ruleset from the text of a TOML configuration file.
+///
+/// # Example
+///
+/// ```rust
+/// let ruleset = riptables::config::load("[[chains]]\nname = \"INPUT\"\npolicy = \"DROP\"\n").unwrap();
+/// ```
+pub fn load<S: AsRef<str>>(text: S) -> RIPTResult<Ruleset> {
+  let text = text.as_ref();
+  let file: ConfigFile<TomlFormat> = toml::from_str(text).map_err(|err| {
+    let (line, column) = err.line_col().unwrap_or((0, 0));
+    let message = err.to_string();
+    let message = match message.find(" at line ") {
+      Some(index) => message[..index].to_string(),
+      None => message,
+    };
+    RIPTConfigError { line: line + 1, column: column + 1, path: None, message }
+  })?;
+  Ok(Loader { text, format: PhantomData }.ruleset(file)?)
+}
+
+/// Loads a ruleset from the text of a YAML configuration file.
+///
+/// # Example
+///
+/// ```rust
+/// let ruleset = riptables::config::load_yaml("chains:\n  - name: INPUT\n    policy: DROP\n").unwrap();
+/// ```
+pub fn load_yaml<S: AsRef<str>>(text: S) -> RIPTResult<Ruleset> {
+  let text = text.as_ref();
+  let mut file: ConfigFile<YamlFormat> = serde_yaml::from_str(text).map_err(|err| {
+    let (line, column) = err.location().map(|location| (location.line(), location.column())).unwrap_or((0, 0));
+    let message = err.to_string();
+    let message = match message.find(" at line ") {
+      Some(index) => message[..index].to_string(),
+      None => message,
+    };
+    RIPTConfigError { line, column, path: None, message }
+  })?;
+  file.set_paths();
+  Ok(Loader { text, format: PhantomData }.ruleset(file)?)
+}
+
+impl<F: Format> ConfigFile<F> {
+  /// Records where each value is, see `Field::path`.
+  fn set_paths(&mut self) {
+    fn set<T>(field: &mut Option<impl Field<T>>, path: String) {
+      if let Some(field) = field {
+        field.set_path(path);
+      }
+    }
+    for (index, chain) in self.chains.iter_mut().enumerate() {
+      let at = format!("chains[{}]", index);
+      set(&mut chain.table, format!("{}.table", at));
+      chain.name.set_path(format!("{}.name", at));
+      set(&mut chain.policy, format!("{}.policy", at));
+      for (index, rule) in chain.rules.iter_mut().enumerate() {
+        let at = format!("{}.rules[{}]", at, index);
+        set(&mut rule.protocol, format!("{}.protocol", at));
+        set(&mut rule.source, format!("{}.source", at));
+        set(&mut rule.destination, format!("{}.destination", at));
+        set(&mut rule.in_interface, format!("{}.in_interface", at));
+        set(&mut rule.out_interface, format!("{}.out_interface", at));
+        set(&mut rule.sport, format!("{}.sport", at));
+        set(&mut rule.dport, format!("{}.dport", at));
+        set(&mut rule.state, format!("{}.state", at));
+        set(&mut rule.comment, format!("{}.comment", at));
+        set(&mut rule.matches, format!("{}.matches", at));
+        set(&mut rule.jump, format!("{}.jump", at));
+        set(&mut rule.goto, format!("{}.goto", at));
+      }
+    }
+  }
+}
+
+struct Loader<'a, F: Format> {
+  text: &'a str,
+  format: PhantomData<F>,
+}
+
+impl<'a, F: Format> Loader<'a, F> {
+  fn ruleset(&self, file: ConfigFile<F>) -> Result<Ruleset, RIPTConfigError> {
+    let mut ruleset = Ruleset::new();
+    let mut gotos = vec![];
+    for chain in &file.chains {
+      let table = match chain.table {
+        Some(ref table) => Table::try_from(&table.get_ref()[..]).map_err(|err| self.error(table, err.to_string()))?,
+        None => Table::Filter,
+      };
+      self.chain_name(&chain.name)?;
+      let builtin = table.builtin_chains().unwrap_or(&[]).contains(&&chain.name.get_ref()[..]);
+      let table = table.to_string();
+      if ruleset.table(&table).and_then(|entry| entry.chain(chain.name.get_ref())).is_some() {
+        return Err(self.error(&chain.name, format!("chain {} is declared twice in table {}", chain.name.get_ref(), table)));
+      }
+      let entry = ruleset.table_entry(&table).chain_entry(chain.name.get_ref());
+      if let Some(ref policy) = chain.policy {
+        if !builtin {
+          return Err(self.error(policy, format!("only built-in chains have a policy, {} is user-defined", chain.name.get_ref())));
+        }
+        if policy.get_ref() != "ACCEPT" && policy.get_ref() != "DROP" {
+          return Err(self.error(policy, format!("policy must be ACCEPT or DROP, not {}", policy.get_ref())));
+        }
+        entry.policy = Some(policy.get_ref().clone());
+      }
+      for rule in &chain.rules {
+        if let Some(ref goto) = rule.goto {
+          gotos.push((table.clone(), goto));
+        }
+        let spec = self.rule(rule)?;
+        let line = if spec.is_empty() { format!("-A {}", chain.name.get_ref()) } else { format!("-A {} {}", chain.name.get_ref(), spec) };
+        let parsed = iptparser::to_rule(table.clone(), line)
+          .map_err(|err| self.error(&chain.name, err.to_string()))?;
+        entry.rules.push(parsed);
+      }
+    }
+
+    for (table, goto) in gotos {
+      if ruleset.table(&table).and_then(|entry| entry.chain(goto.get_ref())).is_none() {
+        return Err(self.error(goto, format!("goto target {} is not a chain of table {}", goto.get_ref(), table)));
+      }
+    }
+    Ok(ruleset)
+  }
+
+  fn rule(&self, rule: &RuleConfig<F>) -> Result<String, RIPTConfigError> {
+    let mut builder = RuleBuilder::new();
+    if let Some(ref value) = rule.source {
+      builder = builder.source(self.address(value)?);
+    }
+    if let Some(ref value) = rule.destination {
+      builder = builder.destination(self.address(value)?);
+    }
+    if let Some(ref value) = rule.in_interface {
+      builder = builder.in_interface(self.interface(value)?);
+    }
+    if let Some(ref value) = rule.out_interface {
+      builder = builder.out_interface(self.interface(value)?);
+    }
+    if let Some(ref value) = rule.protocol {
+      let protocol = value.get_ref().trim_start_matches('!').trim();
+      if protocol.is_empty() || !protocol.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+        return Err(self.error(value, format!("invalid protocol {}", value.get_ref())));
+      }
+      builder = builder.protocol(value.get_ref());
+    }
+    for (port, sport) in &[(&rule.sport, true), (&rule.dport, false)] {
+      if let Some(ref value) = **port {
+        let protocol = rule.protocol.as_ref().map(|protocol| &protocol.get_ref()[..]).unwrap_or("");
+        if !["tcp", "udp", "udplite", "sctp", "dccp"].contains(&protocol) {
+          return Err(self.error(value, "ports require protocol tcp, udp, udplite, sctp or dccp".to_string()));
+        }
+        let port = self.port(value, protocol)?;
+        builder = if *sport { builder.sport(port) } else { builder.dport(port) };
+      }
+    }
+    if let Some(ref value) = rule.state {
+      if value.get_ref().is_empty() {
+        return Err(self.error(value, "state must not be empty".to_string()));
+      }
+      if let Some(state) = value.get_ref().iter().find(|state| !CONNTRACK_STATES.contains(&&state[..])) {
+        return Err(self.error(value, format!("unknown conntrack state {}", state)));
+      }
+      builder = builder.state(value.get_ref());
+    }
+    if let Some(ref value) = rule.matches {
+      if !value.get_ref().trim_start().starts_with('-') && !value.get_ref().trim_start().starts_with('!') {
+        return Err(self.error(value, format!("matches must be iptables options, not {}", value.get_ref())));
+      }
+      builder = builder.matches(value.get_ref().trim());
+    }
+    if let Some(ref value) = rule.comment {
+      if value.get_ref().contains('"') || value.get_ref().len() > 255 {
+        return Err(self.error(value, "comment must be at most 255 characters without double quotes".to_string()));
+      }
+      builder = builder.comment(value.get_ref());
+    }
+    match (&rule.jump, &rule.goto) {
+      (Some(_), Some(goto)) => return Err(self.error(goto, "a rule cannot have both jump and goto".to_string())),
+      (Some(jump), None) => {
+        if jump.get_ref().trim().is_empty() {
+          return Err(self.error(jump, "jump must not be empty".to_string()));
+        }
+        builder = builder.jump(jump.get_ref().trim());
+      }
+      (None, Some(goto)) => {
+        self.chain_name(goto)?;
+        builder = builder.goto(goto.get_ref());
+      }
+      (None, None) => {}
+    }
+    Ok(builder.build())
+  }
+
+  fn chain_name(&self, name: &F::Text) -> Result<(), RIPTConfigError> {
+    ChainName::try_from(&name.get_ref()[..]).map_err(|err| self.error(name, err.to_string()))?;
+    Ok(())
+  }
+
+  fn address(&self, value: &F::Text) -> Result<String, RIPTConfigError> {
+    let text = value.get_ref();
+    let (negate, address) = match text.strip_prefix('!') {
+      Some(address) => (true, address.trim()),
+      None => (false, &text[..]),
+    };
+    let mut parts = address.splitn(2, '/');
+    let ip = parts.next().unwrap_or("").parse::<IpAddr>()
+      .map_err(|_| self.error(value, format!("invalid address {}", text)))?;
+    if let Some(prefix) = parts.next() {
+      let max = if ip.is_ipv4() { 32 } else { 128 };
+      match prefix.parse::<u8>() {
+        Ok(prefix) if prefix <= max => {}
+        _ => return Err(self.error(value, format!("invalid prefix length in {}", text))),
+      }
+    }
+    Ok(if negate { format!("!{}", address) } else { address.to_string() })
+  }
+
+  fn interface(&self, value: &F::Text) -> Result<String, RIPTConfigError> {
+    let text = value.get_ref();
+    let name = text.trim_start_matches('!').trim();
+    if name.is_empty() || name.len() > INTERFACE_NAME_MAX_LEN || name.chars().any(|ch| ch.is_whitespace() || ch == '/') {
+      return Err(self.error(value, format!("invalid interface name {:?}", text)));
+    }
+    Ok(text.clone())
+  }
+
+  fn port(&self, value: &F::Port, protocol: &str) -> Result<String, RIPTConfigError> {
+    let invalid = || self.error(value, format!("invalid port {}, expected a port, a service name or a \"first:last\" range", value.get_ref()));
+    match *value.get_ref() {
+      Value::Integer(port) if (0..=65535).contains(&port) => Ok(port.to_string()),
+      Value::String(ref text) => {
+        let text = Services::system().ports(text, Some(protocol));
+        let ports = text.split(':')
+          .map(|port| port.parse::<u16>().map_err(|_| invalid()))
+          .collect::<Result<Vec<u16>, RIPTConfigError>>()?;
+        match ports.len() {
+          1 => Ok(text),
+          2 if ports[0] <= ports[1] => Ok(text),
+          _ => Err(invalid()),
+        }
+      }
+      _ => Err(invalid()),
+    }
+  }
+
+  fn error<T>(&self, value: &impl Field<T>, message: String) -> RIPTConfigError {
+    let (start, _end) = match value.span() {
+      Some(span) => span,
+      None => return RIPTConfigError { line: 0, column: 0, path: value.path().map(String::from), message },
+    };
+    let before = &self.text[..start.min(self.text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|last| last.chars().count()).unwrap_or(0) + 1;
+    RIPTConfigError { line, column, path: None, message }
+  }
+}
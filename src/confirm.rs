@@ -0,0 +1,167 @@
+//! Applying a ruleset with automatic rollback, in the spirit of `iptables-apply`.
+//!
+//! `apply_pending` snapshots the tables of the new ruleset, applies it and returns a `PendingApply`.
+//! Unless one of its `ConfirmToken`s is confirmed before the timeout, the snapshot is restored.
+//! A change locking the caller out of a remote host is thus reverted by itself:
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use riptables::ruleset::Ruleset;
+//!
+//! let iptables = riptables::new(false).unwrap();
+//! let desired = Ruleset::parse("*filter\n:INPUT DROP\n-A INPUT -p tcp --dport 22 -j ACCEPT\nCOMMIT\n").unwrap();
+//! let pending = iptables.apply_pending(&desired, Duration::from_secs(30)).unwrap();
+//! let token = pending.token();
+//! // hand `token` to whatever proves the host is still reachable, e.g. a new SSH session or an
+//! // HTTP health check, which calls `token.confirm()`
+//! if !pending.wait().unwrap() {
+//!   eprintln!("not confirmed, previous rules restored");
+//! }
+//! ```
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::ruleset::{self, Change, Ruleset};
+use crate::RIPTables;
+
+/// Confirms a `PendingApply`, from any thread.
+#[derive(Debug, Clone)]
+pub struct ConfirmToken {
+  sender: Sender<()>,
+}
+
+impl ConfirmToken {
+  /// Keeps the applied ruleset. Confirming after the timeout has no effect.
+  pub fn confirm(&self) {
+    let _ = self.sender.send(());
+  }
+}
+
+/// A ruleset applied until confirmed, see the module documentation.
+/// Dropping it without calling `wait` rolls back.
+#[must_use = "the applied ruleset is rolled back as soon as it is dropped"]
+pub struct PendingApply<'a> {
+  iptables: &'a RIPTables,
+  snapshot: Ruleset,
+  changes: Vec<Change>,
+  deadline: Instant,
+  sender: Option<Sender<()>>,
+  receiver: Receiver<()>,
+  done: bool,
+}
+
+impl<'a> PendingApply<'a> {
+  /// Returns a token confirming the applied ruleset.
+  pub fn token(&self) -> ConfirmToken {
+    match self.sender {
+      Some(ref sender) => ConfirmToken { sender: sender.clone() },
+      // `wait` consumes `self`, so the sender is always there while tokens can be handed out
+      None => unreachable!(),
+    }
+  }
+
+  /// The changes between the snapshot and the applied ruleset.
+  pub fn changes(&self) -> &[Change] {
+    &self.changes
+  }
+
+  /// The tables as they were before applying.
+  pub fn snapshot(&self) -> &Ruleset {
+    &self.snapshot
+  }
+
+  /// Blocks until a token is confirmed, the timeout expires or every token is dropped unconfirmed.
+  /// Returns `true` if the ruleset is kept, `false` if the snapshot was restored.
+  pub fn wait(mut self) -> RIPTResult<bool> {
+    // tokens handed out keep the channel open, ours must not
+    self.sender = None;
+    let timeout = self.deadline.saturating_duration_since(Instant::now());
+    let confirmed = match self.receiver.recv_timeout(timeout) {
+      Ok(()) => true,
+      Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => false,
+    };
+    self.done = true;
+    if confirmed {
+      return Ok(true);
+    }
+    self.rollback()?;
+    Ok(false)
+  }
+
+  fn rollback(&self) -> RIPTResult<()> {
+    let (code, output) = self.iptables.execute_restore(&self.snapshot.to_string(), false)?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(())
+  }
+}
+
+impl<'a> Drop for PendingApply<'a> {
+  fn drop(&mut self) {
+    if !self.done {
+      let _ = self.rollback();
+    }
+  }
+}
+
+impl RIPTables {
+  /// Snapshots the tables of `desired`, brings them to the content of `desired` and returns the
+  /// pending change, rolled back unless confirmed within `timeout`.
+  /// If applying fails the snapshot is restored right away and the error returned.
+  pub fn apply_pending(&self, desired: &Ruleset, timeout: Duration) -> RIPTResult<PendingApply<'_>> {
+    let tables: Vec<&str> = desired.tables.iter().map(|table| &table.name[..]).collect();
+    let snapshot = self.save(&tables)?;
+    let changes = ruleset::diff(&snapshot, desired);
+    let (sender, receiver) = mpsc::channel();
+    let pending = PendingApply {
+      iptables: self,
+      snapshot,
+      changes,
+      deadline: Instant::now() + timeout,
+      sender: Some(sender),
+      receiver,
+      done: false,
+    };
+
+    // iptables-restore commits table by table, an error may leave the first tables applied
+    let (code, output) = self.execute_restore(&desired.to_string(), false)?;
+    if code != 0 {
+      drop(pending);
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(pending)
+  }
+
+  /// Applies `desired` and runs `confirm` on its own thread, keeping the ruleset only if `confirm`
+  /// returns `true` within `timeout`. A `confirm` still running after the timeout is left to finish
+  /// in the background.
+  /// Returns `true` if the ruleset is kept, `false` if the previous tables were restored.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use std::net::TcpStream;
+  /// use std::time::Duration;
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let desired = Ruleset::parse("*filter\n:INPUT DROP\n-A INPUT -p tcp --dport 22 -j ACCEPT\nCOMMIT\n").unwrap();
+  /// let kept = iptables.apply_confirmed(&desired, Duration::from_secs(10), || {
+  ///   TcpStream::connect("127.0.0.1:22").is_ok()
+  /// }).unwrap();
+  /// ```
+  pub fn apply_confirmed<F>(&self, desired: &Ruleset, timeout: Duration, confirm: F) -> RIPTResult<bool> where F: FnOnce() -> bool + Send + 'static {
+    let pending = self.apply_pending(desired, timeout)?;
+    let token = pending.token();
+    thread::spawn(move || {
+      if confirm() {
+        token.confirm();
+      }
+    });
+    pending.wait()
+  }
+}
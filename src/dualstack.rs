@@ -0,0 +1,289 @@
+//! Managing the IPv4 and IPv6 tables together.
+//!
+//! `DualStack` holds an `iptables` and an `ip6tables` handle. Chain and policy operations go to both
+//! families. Rules go to both unless they only make sense in one: a rule with IPv4 addresses,
+//! `-p icmp` or an ICMP reject type only goes to `iptables`, their IPv6 counterparts only to
+//! `ip6tables`. Invalid table, chain or policy names fail rule operations before either family is
+//! touched, and are reported by both families for chain and policy operations. Every call reports
+//! what each family did:
+//!
+//! ```rust
+//! let dual = riptables::dualstack::new().unwrap();
+//! let result = dual.append("filter", "INPUT", "-p tcp --dport 22 -j ACCEPT").unwrap();
+//! assert!(result.v4.is_some() && result.v6.is_some());
+//! let result = dual.append("filter", "INPUT", "-s 10.0.0.0/8 -j ACCEPT").unwrap();
+//! assert!(result.v6.is_none());
+//! ```
+
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::rule::RIPTRule;
+use crate::table::{ChainName, Table};
+use crate::RIPTables;
+
+/// An address family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Family {
+  V4,
+  V6,
+}
+
+impl Family {
+  fn of(address: IpAddr) -> Family {
+    match address {
+      IpAddr::V4(_) => Family::V4,
+      IpAddr::V6(_) => Family::V6,
+    }
+  }
+}
+
+impl fmt::Display for Family {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Family::V4 => write!(f, "IPv4"),
+      Family::V6 => write!(f, "IPv6"),
+    }
+  }
+}
+
+/// What a `DualStack` call did in each family. A family the call was not sent to is `None`.
+#[derive(Debug)]
+pub struct DualResult<T> {
+  pub v4: Option<RIPTResult<T>>,
+  pub v6: Option<RIPTResult<T>>,
+}
+
+impl<T> DualResult<T> {
+  /// The result of `family`.
+  pub fn get(&self, family: Family) -> Option<&RIPTResult<T>> {
+    match family {
+      Family::V4 => self.v4.as_ref(),
+      Family::V6 => self.v6.as_ref(),
+    }
+  }
+
+  /// Returns `true` if no family failed.
+  pub fn is_ok(&self) -> bool {
+    [&self.v4, &self.v6].iter().all(|result| !matches!(result, Some(Err(_))))
+  }
+
+  /// Returns the first error, or the results of the families the call was sent to.
+  pub fn into_result(self) -> RIPTResult<(Option<T>, Option<T>)> {
+    Ok((self.v4.transpose()?, self.v6.transpose()?))
+  }
+}
+
+impl DualResult<bool> {
+  /// Returns `true` if every family the call was sent to succeeded and returned `true`.
+  pub fn all(&self) -> bool {
+    [&self.v4, &self.v6].iter().all(|result| matches!(result, None | Some(Ok(true))))
+  }
+}
+
+/// Both families, see the module documentation.
+pub struct DualStack {
+  pub v4: RIPTables,
+  pub v6: RIPTables,
+}
+
+/// Opens `iptables` and `ip6tables`.
+#[cfg(target_os = "linux")]
+pub fn new() -> RIPTResult<DualStack> {
+  Ok(DualStack { v4: crate::new(false)?, v6: crate::new(true)? })
+}
+
+/// Returns the only family `spec` applies to, or `None` for a rule valid in both.
+/// Fails for a rule mixing IPv4 and IPv6.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::dualstack::{self, Family};
+///
+/// assert_eq!(dualstack::family("-p tcp --dport 22 -j ACCEPT").unwrap(), None);
+/// assert_eq!(dualstack::family("-s 2001:db8::/32 -j DROP").unwrap(), Some(Family::V6));
+/// assert_eq!(dualstack::family("-p tcp -j DNAT --to-destination 192.168.1.10:80").unwrap(), Some(Family::V4));
+/// assert!(dualstack::family("-s 10.0.0.1 -d ::1 -j DROP").is_err());
+/// ```
+pub fn family<S: AsRef<str>>(spec: S) -> RIPTResult<Option<Family>> {
+  let mut found = None;
+  for group in iptparser::spec_groups(spec.as_ref()) {
+    let families: Vec<Family> = match &group.option[..] {
+      "-p" => group.values.iter().filter_map(|protocol| match &protocol.to_lowercase()[..] {
+        "icmp" | "1" => Some(Family::V4),
+        "icmpv6" | "ipv6-icmp" | "58" => Some(Family::V6),
+        _ => None,
+      }).collect(),
+      // free text
+      "--comment" | "--log-prefix" | "--nflog-prefix" => vec![],
+      "--icmp-type" => vec![Family::V4],
+      "--icmpv6-type" => vec![Family::V6],
+      "--reject-with" => group.values.iter().filter_map(|reject| {
+        if reject.starts_with("icmp6-") {
+          Some(Family::V6)
+        } else if reject.starts_with("icmp-") {
+          Some(Family::V4)
+        } else {
+          None
+        }
+      }).collect(),
+      _ => group.values.iter()
+        .flat_map(|value| value.split(','))
+        .flat_map(|value| value.split('-'))
+        .filter_map(self::address)
+        .map(Family::of)
+        .collect(),
+    };
+    for family in families {
+      match found {
+        Some(other) if other != family => return Err(RIPTError::Other("the rule mixes IPv4 and IPv6")),
+        _ => found = Some(family),
+      }
+    }
+  }
+  Ok(found)
+}
+
+/// Reads the address of `192.168.1.1`, `10.0.0.0/8`, `192.168.1.1:80` or `[2001:db8::1]:80`.
+pub(crate) fn address(value: &str) -> Option<IpAddr> {
+  let value = value.split('/').next().unwrap_or(value);
+  if let Ok(address) = value.parse() {
+    return Some(address);
+  }
+  if let Some(rest) = value.strip_prefix('[') {
+    return rest.split(']').next().and_then(|address| address.parse().ok());
+  }
+  match value.split_once(':') {
+    Some((address, _)) => address.parse().ok(),
+    None => None,
+  }
+}
+
+impl DualStack {
+  /// The handle of `family`.
+  pub fn family(&self, family: Family) -> &RIPTables {
+    match family {
+      Family::V4 => &self.v4,
+      Family::V6 => &self.v6,
+    }
+  }
+
+  /// Runs `call` in both families.
+  pub fn both<T, F>(&self, call: F) -> DualResult<T> where F: Fn(&RIPTables) -> RIPTResult<T> {
+    DualResult { v4: Some(call(&self.v4)), v6: Some(call(&self.v6)) }
+  }
+
+  /// Runs `call` in the families `rule` applies to.
+  pub fn for_rule<S, T, F>(&self, rule: S, call: F) -> RIPTResult<DualResult<T>> where S: AsRef<OsStr>, F: Fn(&RIPTables) -> RIPTResult<T> {
+    let rule = crate::to_string(rule);
+    Ok(match self::family(&rule)? {
+      None => self.both(call),
+      Some(Family::V4) => DualResult { v4: Some(call(&self.v4)), v6: None },
+      Some(Family::V6) => DualResult { v4: None, v6: Some(call(&self.v6)) },
+    })
+  }
+
+  /// Appends `rule` to the table/chain of the families it applies to.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let dual = riptables::dualstack::new().unwrap();
+  /// let result = dual.append("filter", "INPUT", "-p tcp --dport 443 -j ACCEPT").unwrap();
+  /// assert!(result.all());
+  /// ```
+  pub fn append<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.append(&table, &chain, rule.as_ref()))
+  }
+
+  /// Appends `rule` to the families it applies to where it does not exist yet.
+  pub fn append_unique<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.append_unique(&table, &chain, rule.as_ref()))
+  }
+
+  /// Inserts `rule` in the `position` to the table/chain of the families it applies to.
+  pub fn insert<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.insert(&table, &chain, rule.as_ref(), position))
+  }
+
+  /// Inserts `rule` in the `position` of the families it applies to where it does not exist yet.
+  pub fn insert_unique<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.insert_unique(&table, &chain, rule.as_ref(), position))
+  }
+
+  /// Deletes `rule` from the table/chain of the families it applies to.
+  pub fn delete<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.delete(&table, &chain, rule.as_ref()))
+  }
+
+  /// Deletes every copy of `rule` from the table/chain of the families it applies to.
+  pub fn delete_all<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.delete_all(&table, &chain, rule.as_ref()))
+  }
+
+  /// Checks whether `rule` exists in the families it applies to.
+  pub fn exists<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<DualResult<bool>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self.for_rule(rule.as_ref(), |iptables| iptables.exists(&table, &chain, rule.as_ref()))
+  }
+
+  /// Lists the rules of a table in both families.
+  pub fn list<T>(&self, table: T) -> DualResult<Vec<RIPTRule>> where T: AsRef<OsStr> {
+    self.both(|iptables| iptables.list(table.as_ref()))
+  }
+
+  /// Creates a user-defined chain in both families.
+  pub fn new_chain<T, C>(&self, table: T, chain: C) -> DualResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    self.both(|iptables| iptables.new_chain(table.as_ref(), chain.as_ref()))
+  }
+
+  /// Creates a user-defined chain in the families missing it.
+  pub fn ensure_chain<T, C>(&self, table: T, chain: C) -> DualResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    self.both(|iptables| iptables.ensure_chain(table.as_ref(), chain.as_ref()))
+  }
+
+  /// Flushes a chain in both families.
+  pub fn flush_chain<T, C>(&self, table: T, chain: C) -> DualResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    self.both(|iptables| iptables.flush_chain(table.as_ref(), chain.as_ref()))
+  }
+
+  /// Deletes an empty user-defined chain in both families.
+  pub fn delete_chain<T, C>(&self, table: T, chain: C) -> DualResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    self.both(|iptables| iptables.delete_chain(table.as_ref(), chain.as_ref()))
+  }
+
+  /// Gets the policy of a built-in chain in both families.
+  pub fn get_policy<T, C>(&self, table: T, chain: C) -> DualResult<Option<String>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    self.both(|iptables| iptables.get_policy(table.as_ref(), chain.as_ref()))
+  }
+
+  /// Sets the policy of a built-in chain in both families.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let dual = riptables::dualstack::new().unwrap();
+  /// dual.set_policy("filter", "FORWARD", "DROP").into_result().unwrap();
+  /// ```
+  pub fn set_policy<T, C, P>(&self, table: T, chain: C, policy: P) -> DualResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, P: AsRef<OsStr> {
+    self.both(|iptables| iptables.set_policy(table.as_ref(), chain.as_ref(), policy.as_ref()))
+  }
+}
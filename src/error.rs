@@ -8,6 +8,11 @@ pub enum RIPTError {
   Nix(nix::Error),
   Parse(num::ParseIntError),
   Analysis(RIPTAnalysisError),
+  Config(RIPTConfigError),
+  Template(String),
+  Lockout(String),
+  Query(String),
+  Invalid(String),
   Stderr(String),
   Other(&'static str),
 }
@@ -22,6 +27,11 @@ impl fmt::Display for RIPTError {
       RIPTError::Nix(ref err) => write!(f, "{}", err),
       RIPTError::Parse(ref err) => write!(f, "{}", err),
       RIPTError::Analysis(ref err) => write!(f, "{}", err),
+      RIPTError::Config(ref err) => write!(f, "{}", err),
+      RIPTError::Template(ref message) => write!(f, "{}", message),
+      RIPTError::Lockout(ref message) => write!(f, "{}", message),
+      RIPTError::Query(ref message) => write!(f, "{}", message),
+      RIPTError::Invalid(ref message) => write!(f, "{}", message),
       RIPTError::Stderr(ref message) => write!(f, "{}", message),
       RIPTError::Other(ref message) => write!(f, "{}", message)
     }
@@ -35,6 +45,11 @@ impl error::Error for RIPTError {
       RIPTError::Nix(ref err) => err.description(),
       RIPTError::Parse(ref err) => err.description(),
       RIPTError::Analysis(ref err) => err.description(),
+      RIPTError::Config(ref err) => &err.message,
+      RIPTError::Template(ref message) => message,
+      RIPTError::Lockout(ref message) => message,
+      RIPTError::Query(ref message) => message,
+      RIPTError::Invalid(ref message) => message,
       RIPTError::Stderr(ref message) => message,
       RIPTError::Other(ref message) => message,
     }
@@ -46,6 +61,7 @@ impl error::Error for RIPTError {
       RIPTError::Nix(ref err) => Some(err),
       RIPTError::Parse(ref err) => Some(err),
       RIPTError::Analysis(ref err) => Some(err),
+      RIPTError::Config(ref err) => Some(err),
       _ => Some(self),
     }
   }
@@ -75,6 +91,12 @@ impl convert::From<&'static str> for RIPTError {
   }
 }
 
+impl convert::From<RIPTConfigError> for RIPTError {
+  fn from(err: RIPTConfigError) -> Self {
+    RIPTError::Config(err)
+  }
+}
+
 impl convert::From<RIPTAnalysisError> for RIPTError {
   fn from(err: RIPTAnalysisError) -> Self {
     RIPTError::Analysis(err)
@@ -121,3 +143,28 @@ impl convert::From<FromUtf8Error> for RIPTAnalysisError {
   }
 }
 
+
+
+/// An invalid ruleset configuration file, positions are 1-based and 0 when unknown.
+#[derive(Debug)]
+pub struct RIPTConfigError {
+  pub line: usize,
+  pub column: usize,
+  /// The invalid field, e.g. `chains[0].rules[1].dport`, when the position is unknown.
+  pub path: Option<String>,
+  pub message: String,
+}
+
+impl fmt::Display for RIPTConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    if self.line == 0 {
+      return match self.path {
+        Some(ref path) => write!(f, "{}: {}", path, self.message),
+        None => write!(f, "{}", self.message),
+      };
+    }
+    write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+  }
+}
+
+impl error::Error for RIPTConfigError {}
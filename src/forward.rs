@@ -0,0 +1,298 @@
+//! Port forwarding with DNAT.
+//!
+//! A `PortForward` sends the connections reaching an external port to an internal address and
+//! port. It takes several rules, all tagged for the `riptables-forward` owner (see `owner`) with an
+//! id naming the forward:
+//!
+//! | table    | chain         | rule                                                          |
+//! |----------|---------------|---------------------------------------------------------------|
+//! | `nat`    | `PREROUTING`  | DNAT of the connections coming from the network               |
+//! | `nat`    | `OUTPUT`      | DNAT of the connections opened by the host, with `local`      |
+//! | `nat`    | `POSTROUTING` | masquerading of the connections from `hairpin` to the target  |
+//! | `filter` | `FORWARD`     | accepting the forwarded connections, first in the chain       |
+//!
+//! Without an external address, the forward applies to the connections addressed to any address
+//! of the host.
+//!
+//! ```rust
+//! use riptables::forward::PortForward;
+//!
+//! let forward = PortForward::new("tcp", 8080, "192.168.1.10".parse().unwrap(), 80).interface("eth0");
+//! let rules = forward.rules().unwrap();
+//! assert_eq!(rules[0].chain, "PREROUTING");
+//! assert_eq!(
+//!   rules[0].spec,
+//!   "-i eth0 -p tcp -m tcp --dport 8080 -m addrtype --dst-type LOCAL \
+//!    -m comment --comment riptables-forward:tcp/eth0/8080 -j DNAT --to-destination 192.168.1.10:80",
+//! );
+//! ```
+
+use std::net::IpAddr;
+
+use crate::builder::RuleBuilder;
+use crate::dualstack;
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::owner::{OwnedRule, OwnerTag};
+use crate::rule::RIPTRule;
+use crate::RIPTables;
+
+/// Owner of the rules of every port forward.
+pub const FORWARD_OWNER: &str = "riptables-forward";
+
+/// Protocols with ports.
+const PROTOCOLS: &[&str] = &["tcp", "udp", "udplite", "sctp", "dccp"];
+
+/// A port forward, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortForward {
+  pub protocol: String,
+  /// Interface the connections come in from, any when `None`.
+  pub interface: Option<String>,
+  /// Address the connections are addressed to, any address of the host when `None`.
+  pub address: Option<IpAddr>,
+  pub port: u16,
+  pub to_address: IpAddr,
+  pub to_port: u16,
+  /// Forwards the connections opened by the host too.
+  pub local: bool,
+  /// Network whose connections to the forward are masqueraded, so that hosts of the internal
+  /// network reach the target through the external address as well.
+  pub hairpin: Option<String>,
+}
+
+impl PortForward {
+  /// Forwards `port` of the host to `to_address`:`to_port`.
+  pub fn new<S: ToString>(protocol: S, port: u16, to_address: IpAddr, to_port: u16) -> PortForward {
+    PortForward {
+      protocol: protocol.to_string().to_lowercase(),
+      interface: None,
+      address: None,
+      port,
+      to_address,
+      to_port,
+      local: false,
+      hairpin: None,
+    }
+  }
+
+  pub fn interface<S: ToString>(mut self, interface: S) -> PortForward {
+    self.interface = Some(interface.to_string());
+    self
+  }
+
+  pub fn address(mut self, address: IpAddr) -> PortForward {
+    self.address = Some(address);
+    self
+  }
+
+  pub fn local(mut self, local: bool) -> PortForward {
+    self.local = local;
+    self
+  }
+
+  pub fn hairpin<S: ToString>(mut self, network: S) -> PortForward {
+    self.hairpin = Some(network.to_string());
+    self
+  }
+
+  /// Names the forward by what selects its connections: protocol, interface, address and port.
+  /// Two forwards with the same id cannot coexist, adding one replaces the other.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::forward::PortForward;
+  ///
+  /// let forward = PortForward::new("udp", 53, "10.0.0.2".parse().unwrap(), 53).address("203.0.113.1".parse().unwrap());
+  /// assert_eq!(forward.id(), "udp/203.0.113.1/53");
+  /// ```
+  pub fn id(&self) -> String {
+    let mut parts = vec![self.protocol.clone()];
+    parts.extend(self.interface.clone());
+    parts.extend(self.address.map(|address| address.to_string()));
+    parts.push(self.port.to_string());
+    parts.join("/")
+  }
+
+  /// Returns the rules making the forward, in the order they are added.
+  /// Fails for a protocol without ports, a port 0, addresses of both families or a hairpin
+  /// forward restricted to an interface, whose internal connections would not come in from it.
+  pub fn rules(&self) -> RIPTResult<Vec<OwnedRule>> {
+    self.validate()?;
+    let tag = self.tag()?;
+    let destination = match self.to_address {
+      IpAddr::V4(address) => format!("{}:{}", address, self.to_port),
+      IpAddr::V6(address) => format!("[{}]:{}", address, self.to_port),
+    };
+    let dnat = |interface: Option<&String>| {
+      let builder = RuleBuilder::new().protocol(&self.protocol).dport(self.port);
+      let builder = match self.address {
+        Some(address) => builder.destination(self::host(address)),
+        None => builder.matches("-m addrtype --dst-type LOCAL"),
+      };
+      let builder = match interface {
+        Some(interface) => builder.in_interface(interface),
+        None => builder,
+      };
+      builder.comment(&tag).jump(format!("DNAT --to-destination {}", destination)).build()
+    };
+    let internal = || RuleBuilder::new().destination(self::host(self.to_address)).protocol(&self.protocol).dport(self.to_port);
+
+    let mut rules = vec![OwnedRule { table: "nat", chain: "PREROUTING", spec: dnat(self.interface.as_ref()) }];
+    if self.local {
+      rules.push(OwnedRule { table: "nat", chain: "OUTPUT", spec: dnat(None) });
+    }
+    if let Some(ref network) = self.hairpin {
+      let spec = internal().source(network).comment(&tag).jump("MASQUERADE").build();
+      rules.push(OwnedRule { table: "nat", chain: "POSTROUTING", spec });
+    }
+    let accept = internal().state(&["DNAT"]).comment(&tag).jump("ACCEPT");
+    let accept = match self.interface {
+      Some(ref interface) => accept.in_interface(interface),
+      None => accept,
+    };
+    rules.push(OwnedRule { table: "filter", chain: "FORWARD", spec: accept.build() });
+    Ok(rules)
+  }
+
+  fn tag(&self) -> RIPTResult<OwnerTag> {
+    OwnerTag::new(FORWARD_OWNER, &self.id()[..]).map_err(|_| RIPTError::Invalid(format!("invalid port forward {}", self.id())))
+  }
+
+  fn validate(&self) -> RIPTResult<()> {
+    let invalid = |reason: &str| Err(RIPTError::Invalid(format!("port forward {}: {}", self.id(), reason)));
+    if !PROTOCOLS.contains(&&self.protocol[..]) {
+      return invalid("the protocol has no ports");
+    }
+    if self.port == 0 || self.to_port == 0 {
+      return invalid("port 0 cannot be forwarded");
+    }
+    let hairpin = self.hairpin.as_ref().map(|network| dualstack::address(network));
+    if hairpin == Some(None) {
+      return invalid("the hairpin network is not an address");
+    }
+    let families = [self.address, hairpin.flatten()].iter().flatten().all(|address| address.is_ipv4() == self.to_address.is_ipv4());
+    if !families {
+      return invalid("the addresses mix IPv4 and IPv6");
+    }
+    if self.hairpin.is_some() && self.interface.is_some() {
+      return invalid("a hairpin forward cannot be restricted to an interface");
+    }
+    Ok(())
+  }
+
+  /// Reads the port forwards back from the owned rules of the `nat` table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::forward::PortForward;
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let forward = PortForward::new("tcp", 2222, "10.0.0.5".parse().unwrap(), 22).local(true);
+  /// let mut text = "*nat\n".to_string();
+  /// for rule in forward.rules().unwrap().iter().filter(|rule| rule.table == "nat") {
+  ///   text.push_str(&format!("-A {} {}\n", rule.chain, rule.spec));
+  /// }
+  /// text.push_str("COMMIT\n");
+  /// let rules = &Ruleset::parse(text).unwrap().tables[0].chains;
+  /// let rules: Vec<_> = rules.iter().flat_map(|chain| chain.rules.clone()).collect();
+  /// assert_eq!(PortForward::from_rules(&rules), vec![forward]);
+  /// ```
+  pub fn from_rules(rules: &[RIPTRule]) -> Vec<PortForward> {
+    let tagged = |chain: &'static str| rules.iter()
+      .filter(move |rule| rule.table == "nat" && rule.chain == chain)
+      .filter_map(|rule| Some((rule.owner_tag().filter(|tag| tag.owner == FORWARD_OWNER)?, rule)));
+
+    let mut forwards = vec![];
+    for (tag, rule) in tagged("PREROUTING") {
+      let groups = iptparser::spec_groups(rule.spec());
+      let value = |option: &str| groups.iter()
+        .find(|group| group.option == option && !group.negate)
+        .and_then(|group| group.values.first())
+        .cloned();
+      let port = value("--dport").and_then(|port| port.parse().ok());
+      let destination = value("--to-destination").and_then(|destination| self::destination(&destination));
+      let (protocol, port, (to_address, to_port)) = match (value("-p"), port, destination) {
+        (Some(protocol), Some(port), Some(destination)) => (protocol, port, destination),
+        _ => continue,
+      };
+      let mut forward = PortForward::new(protocol, port, to_address, to_port);
+      forward.interface = value("-i");
+      forward.address = value("-d").and_then(|address| dualstack::address(&address));
+      forward.local = tagged("OUTPUT").any(|(other, _)| other == tag);
+      forward.hairpin = tagged("POSTROUTING")
+        .find(|(other, _)| *other == tag)
+        .and_then(|(_, rule)| iptparser::spec_groups(rule.spec()).into_iter().find(|group| group.option == "-s"))
+        .and_then(|group| group.values.first().cloned());
+      forwards.push(forward);
+    }
+    forwards
+  }
+}
+
+/// Writes `address` the way `iptables -S` prints a single host.
+fn host(address: IpAddr) -> String {
+  match address {
+    IpAddr::V4(_) => format!("{}/32", address),
+    IpAddr::V6(_) => format!("{}/128", address),
+  }
+}
+
+/// Reads the address and port of a `--to-destination` value.
+fn destination(value: &str) -> Option<(IpAddr, u16)> {
+  let (_, port) = value.rsplit_once(':')?;
+  Some((dualstack::address(value)?, port.parse().ok()?))
+}
+
+impl RIPTables {
+  /// Adds the rules of `forward` that are missing, and deletes the rules of an earlier forward
+  /// with the same id that it no longer needs. The `FORWARD` rule is inserted first in the chain,
+  /// the `nat` rules are appended.
+  /// Returns `true` if every rule is in place.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::forward::PortForward;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let forward = PortForward::new("tcp", 8080, "192.168.1.10".parse().unwrap(), 80).hairpin("192.168.1.0/24");
+  /// iptables.add_port_forward(&forward).unwrap();
+  /// ```
+  pub fn add_port_forward(&self, forward: &PortForward) -> RIPTResult<bool> {
+    self.reconcile_owned(&forward.tag()?, &["nat", "filter"], &forward.rules()?)
+  }
+
+  /// Deletes every rule of the forward with the id of `forward`.
+  /// Returns the number of deleted rules.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::forward::PortForward;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let forward = PortForward::new("tcp", 8080, "192.168.1.10".parse().unwrap(), 80);
+  /// iptables.remove_port_forward(&forward).unwrap();
+  /// ```
+  pub fn remove_port_forward(&self, forward: &PortForward) -> RIPTResult<usize> {
+    let id = forward.id();
+    Ok(self.delete_owned_id("nat", FORWARD_OWNER, &id)? + self.delete_owned_id("filter", FORWARD_OWNER, &id)?)
+  }
+
+  /// Lists the port forwards added with `add_port_forward`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// for forward in iptables.list_port_forwards().unwrap() {
+  ///   println!("{} -> {}:{}", forward.id(), forward.to_address, forward.to_port);
+  /// }
+  /// ```
+  pub fn list_port_forwards(&self) -> RIPTResult<Vec<PortForward>> {
+    Ok(PortForward::from_rules(&self.list_owned("nat", FORWARD_OWNER)?))
+  }
+}
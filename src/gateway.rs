@@ -0,0 +1,191 @@
+//! NAT gateways for small routers.
+//!
+//! A `NatGateway` lets a LAN subnet out through a WAN interface. Its rules are tagged for the
+//! `riptables-gateway` owner (see `owner`) with an id naming the gateway:
+//!
+//! | table    | chain         | rule                                                          |
+//! |----------|---------------|---------------------------------------------------------------|
+//! | `nat`    | `POSTROUTING` | MASQUERADE of the subnet out of the WAN, or SNAT with `snat`  |
+//! | `filter` | `FORWARD`     | accepting the connections from the subnet to the WAN          |
+//! | `filter` | `FORWARD`     | accepting the established and related traffic back            |
+//!
+//! ```rust
+//! use riptables::gateway::NatGateway;
+//!
+//! let gateway = NatGateway::new("192.168.1.0/24", "eth0").lan_interface("br0");
+//! let rules = gateway.rules().unwrap();
+//! assert_eq!(
+//!   rules[0].spec,
+//!   "-s 192.168.1.0/24 -o eth0 -m comment --comment riptables-gateway:192.168.1.0/24@eth0 -j MASQUERADE",
+//! );
+//! assert_eq!(rules[1].chain, "FORWARD");
+//! ```
+
+use std::net::IpAddr;
+
+use crate::builder::RuleBuilder;
+use crate::dualstack;
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::owner::{OwnedRule, OwnerTag};
+use crate::rule::RIPTRule;
+use crate::RIPTables;
+
+/// Owner of the rules of every NAT gateway.
+pub const GATEWAY_OWNER: &str = "riptables-gateway";
+
+/// A NAT gateway, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NatGateway {
+  /// Subnet of the LAN, e.g. `192.168.1.0/24`.
+  pub lan: String,
+  /// Interface of the LAN, any when `None`.
+  pub lan_interface: Option<String>,
+  pub wan_interface: String,
+  /// Source address of the translated connections, the address of the WAN interface at the time
+  /// of each connection when `None`.
+  pub snat: Option<IpAddr>,
+}
+
+impl NatGateway {
+  /// Masquerades `lan` out of `wan_interface`.
+  pub fn new<S: ToString>(lan: S, wan_interface: S) -> NatGateway {
+    NatGateway { lan: lan.to_string(), lan_interface: None, wan_interface: wan_interface.to_string(), snat: None }
+  }
+
+  pub fn lan_interface<S: ToString>(mut self, interface: S) -> NatGateway {
+    self.lan_interface = Some(interface.to_string());
+    self
+  }
+
+  /// Uses SNAT to `address` instead of MASQUERADE, for a WAN with a static address.
+  pub fn snat(mut self, address: IpAddr) -> NatGateway {
+    self.snat = Some(address);
+    self
+  }
+
+  /// Names the gateway by its subnet and WAN interface, e.g. `192.168.1.0/24@eth0`.
+  /// Two gateways with the same id cannot coexist, applying one replaces the other.
+  pub fn id(&self) -> String {
+    format!("{}@{}", self.lan, self.wan_interface)
+  }
+
+  /// Returns the rules making the gateway, in the order they are added.
+  /// Fails if the subnet is not an address, the WAN interface is empty or the SNAT address is of
+  /// the other family.
+  pub fn rules(&self) -> RIPTResult<Vec<OwnedRule>> {
+    self.validate()?;
+    let tag = self.tag()?;
+    let target = match self.snat {
+      Some(address) => format!("SNAT --to-source {}", address),
+      None => "MASQUERADE".to_string(),
+    };
+    let nat = RuleBuilder::new().source(&self.lan).out_interface(&self.wan_interface).comment(&tag).jump(target);
+    let out = RuleBuilder::new().source(&self.lan).out_interface(&self.wan_interface);
+    let back = RuleBuilder::new().destination(&self.lan).in_interface(&self.wan_interface);
+    let (out, back) = match self.lan_interface {
+      Some(ref interface) => (out.in_interface(interface), back.out_interface(interface)),
+      None => (out, back),
+    };
+    Ok(vec![
+      OwnedRule { table: "nat", chain: "POSTROUTING", spec: nat.build() },
+      OwnedRule { table: "filter", chain: "FORWARD", spec: out.comment(&tag).jump("ACCEPT").build() },
+      OwnedRule { table: "filter", chain: "FORWARD", spec: back.state(&["RELATED", "ESTABLISHED"]).comment(&tag).jump("ACCEPT").build() },
+    ])
+  }
+
+  fn tag(&self) -> RIPTResult<OwnerTag> {
+    OwnerTag::new(GATEWAY_OWNER, &self.id()[..]).map_err(|_| RIPTError::Invalid(format!("invalid NAT gateway {}", self.id())))
+  }
+
+  fn validate(&self) -> RIPTResult<()> {
+    let invalid = |reason: &str| Err(RIPTError::Invalid(format!("NAT gateway {}: {}", self.id(), reason)));
+    let lan = match dualstack::address(&self.lan) {
+      Some(lan) => lan,
+      None => return invalid("the LAN is not a subnet"),
+    };
+    if self.wan_interface.is_empty() || self.lan_interface.as_ref().is_some_and(|interface| interface.is_empty()) {
+      return invalid("an interface is empty");
+    }
+    if self.snat.is_some_and(|address| address.is_ipv4() != lan.is_ipv4()) {
+      return invalid("the addresses mix IPv4 and IPv6");
+    }
+    Ok(())
+  }
+
+  /// Reads the NAT gateways back from their owned rules, taken from the `nat` and `filter` tables.
+  pub fn from_rules(rules: &[RIPTRule]) -> Vec<NatGateway> {
+    let tagged = |table: &'static str, chain: &'static str| rules.iter()
+      .filter(move |rule| rule.table == table && rule.chain == chain)
+      .filter_map(|rule| Some((rule.owner_tag().filter(|tag| tag.owner == GATEWAY_OWNER)?, rule)));
+    let value = |rule: &RIPTRule, option: &str| iptparser::spec_groups(rule.spec()).into_iter()
+      .find(|group| group.option == option && !group.negate)
+      .and_then(|group| group.values.first().cloned());
+
+    let mut gateways = vec![];
+    for (tag, rule) in tagged("nat", "POSTROUTING") {
+      let (lan, wan_interface) = match (value(rule, "-s"), value(rule, "-o")) {
+        (Some(lan), Some(wan_interface)) => (lan, wan_interface),
+        _ => continue,
+      };
+      let mut gateway = NatGateway::new(lan, wan_interface);
+      gateway.snat = value(rule, "--to-source").and_then(|address| address.parse().ok());
+      gateway.lan_interface = tagged("filter", "FORWARD")
+        .filter(|(other, rule)| *other == tag && value(rule, "-s").is_some())
+        .find_map(|(_, rule)| value(rule, "-i"));
+      gateways.push(gateway);
+    }
+    gateways
+  }
+}
+
+impl RIPTables {
+  /// Adds the rules of `gateway` that are missing, and deletes the rules of an earlier gateway
+  /// with the same id that it no longer needs. The `FORWARD` rules are inserted first in the
+  /// chain, the `POSTROUTING` rule is appended.
+  /// Returns `true` if every rule is in place.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::gateway::NatGateway;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.apply_nat_gateway(&NatGateway::new("192.168.1.0/24", "eth0")).unwrap();
+  /// ```
+  pub fn apply_nat_gateway(&self, gateway: &NatGateway) -> RIPTResult<bool> {
+    self.reconcile_owned(&gateway.tag()?, &["nat", "filter"], &gateway.rules()?)
+  }
+
+  /// Deletes every rule of the gateway with the id of `gateway`.
+  /// Returns the number of deleted rules.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::gateway::NatGateway;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.remove_nat_gateway(&NatGateway::new("192.168.1.0/24", "eth0")).unwrap();
+  /// ```
+  pub fn remove_nat_gateway(&self, gateway: &NatGateway) -> RIPTResult<usize> {
+    let id = gateway.id();
+    Ok(self.delete_owned_id("nat", GATEWAY_OWNER, &id)? + self.delete_owned_id("filter", GATEWAY_OWNER, &id)?)
+  }
+
+  /// Lists the NAT gateways applied with `apply_nat_gateway`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// for gateway in iptables.list_nat_gateways().unwrap() {
+  ///   println!("{}", gateway.id());
+  /// }
+  /// ```
+  pub fn list_nat_gateways(&self) -> RIPTResult<Vec<NatGateway>> {
+    let mut rules = self.list_owned("nat", GATEWAY_OWNER)?;
+    rules.extend(self.list_owned("filter", GATEWAY_OWNER)?);
+    Ok(NatGateway::from_rules(&rules))
+  }
+}
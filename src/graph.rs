@@ -0,0 +1,236 @@
+//! Jump analysis between the chains of a table.
+//!
+//! Every rule whose `-j` or `-g` target is a chain of the same table adds an edge from its chain
+//! to the target chain.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+
+use crate::error::RIPTResult;
+use crate::rule::{Archive, RIPTRule};
+use crate::ruleset::RulesetTable;
+use crate::table::Table;
+use crate::RIPTables;
+
+/// A rule of `from` sending packets to the chain `to`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainEdge {
+  pub from: String,
+  pub to: String,
+  /// 1-based position of the rule in `from`.
+  pub position: usize,
+  /// `true` for `-g`, `false` for `-j`.
+  pub goto: bool,
+}
+
+/// Chains of a table and the jumps between them.
+#[derive(Debug, Clone)]
+pub struct ChainGraph {
+  pub table: String,
+  chains: Vec<String>,
+  builtins: Vec<String>,
+  edges: Vec<ChainEdge>,
+}
+
+impl ChainGraph {
+  /// Builds the graph from the rules of a single table, as returned by `list`.
+  pub fn from_rules(table: &str, rules: &[RIPTRule]) -> ChainGraph {
+    let mut chains: Vec<String> = vec![];
+    let mut builtins: Vec<String> = vec![];
+    for rule in rules {
+      if !chains.contains(&rule.chain) {
+        chains.push(rule.chain.clone());
+      }
+      if rule.archive == Archive::Policy && !builtins.contains(&rule.chain) {
+        builtins.push(rule.chain.clone());
+      }
+    }
+    let appended = rules.iter().filter(|rule| rule.archive == Archive::Append);
+    ChainGraph::new(table, chains, builtins, appended)
+  }
+
+  /// Builds the graph from a table of a `Ruleset`.
+  pub fn from_table(table: &RulesetTable) -> ChainGraph {
+    let chains = table.chains.iter().map(|chain| chain.name.clone()).collect();
+    let builtins = table.chains.iter()
+      .filter(|chain| chain.policy.is_some())
+      .map(|chain| chain.name.clone())
+      .collect();
+    ChainGraph::new(&table.name, chains, builtins, table.rules())
+  }
+
+  fn new<'a, I>(table: &str, chains: Vec<String>, mut builtins: Vec<String>, rules: I) -> ChainGraph where I: Iterator<Item=&'a RIPTRule> {
+    for builtin in crate::builtin_chains(table).unwrap_or(&[]) {
+      if chains.iter().any(|chain| chain == builtin) && !builtins.iter().any(|chain| chain == builtin) {
+        builtins.push(builtin.to_string());
+      }
+    }
+
+    let mut edges = vec![];
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for rule in rules {
+      let position = positions.entry(&rule.chain).or_insert(0);
+      *position += 1;
+      let (to, goto) = if rule.goto.is_empty() { (&rule.jump, false) } else { (&rule.goto, true) };
+      if chains.contains(to) {
+        edges.push(ChainEdge { from: rule.chain.clone(), to: to.clone(), position: *position, goto });
+      }
+    }
+    ChainGraph { table: table.to_string(), chains, builtins, edges }
+  }
+
+  /// Every chain of the table, in listing order.
+  pub fn chains(&self) -> &[String] {
+    &self.chains
+  }
+
+  pub fn edges(&self) -> &[ChainEdge] {
+    &self.edges
+  }
+
+  pub fn is_builtin(&self, chain: &str) -> bool {
+    self.builtins.iter().any(|builtin| builtin == chain)
+  }
+
+  /// Rules of other chains jumping or going to `chain`.
+  pub fn referrers(&self, chain: &str) -> Vec<&ChainEdge> {
+    self.edges.iter().filter(|edge| edge.to == chain).collect()
+  }
+
+  /// Chains `chain` jumps or goes to directly.
+  pub fn targets(&self, chain: &str) -> Vec<&str> {
+    let mut targets: Vec<&str> = vec![];
+    for edge in self.edges.iter().filter(|edge| edge.from == chain) {
+      if !targets.contains(&&edge.to[..]) {
+        targets.push(&edge.to);
+      }
+    }
+    targets
+  }
+
+  /// Chains a packet entering `chain` (usually a built-in hook like `INPUT`) may traverse,
+  /// in breadth-first order, `chain` excluded.
+  pub fn reachable(&self, chain: &str) -> Vec<String> {
+    let mut reached: Vec<String> = vec![];
+    let mut queue = VecDeque::new();
+    queue.push_back(chain);
+    while let Some(current) = queue.pop_front() {
+      for target in self.targets(current) {
+        if target != chain && !reached.iter().any(|name| name == target) {
+          reached.push(target.to_string());
+          queue.push_back(target);
+        }
+      }
+    }
+    reached
+  }
+
+  /// User-defined chains no rule jumps or goes to.
+  pub fn orphans(&self) -> Vec<&str> {
+    self.chains.iter()
+      .filter(|chain| !self.is_builtin(chain) && self.referrers(chain).is_empty())
+      .map(|chain| &chain[..])
+      .collect()
+  }
+
+  /// Groups of chains jumping to each other in a loop, including chains jumping to themselves.
+  /// iptables refuses such rulesets, so this is mostly useful to check a ruleset before restoring it.
+  pub fn cycles(&self) -> Vec<Vec<String>> {
+    Tarjan::new(self).run()
+  }
+
+  /// Exports the graph in Graphviz DOT format. Built-in chains are drawn as boxes, `-g` edges dashed
+  /// and edges are labelled with the position of the rule.
+  pub fn to_dot(&self) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", self.table);
+    for chain in &self.chains {
+      if self.is_builtin(chain) {
+        dot.push_str(&format!("  \"{}\" [shape=box];\n", chain));
+      } else {
+        dot.push_str(&format!("  \"{}\";\n", chain));
+      }
+    }
+    for edge in &self.edges {
+      let style = if edge.goto { ", style=dashed" } else { "" };
+      dot.push_str(&format!("  \"{}\" -> \"{}\" [label=\"{}\"{}];\n", edge.from, edge.to, edge.position, style));
+    }
+    dot.push_str("}\n");
+    dot
+  }
+}
+
+/// Tarjan's strongly connected components algorithm.
+struct Tarjan<'a> {
+  graph: &'a ChainGraph,
+  index: usize,
+  indexes: HashMap<&'a str, usize>,
+  lowlinks: HashMap<&'a str, usize>,
+  stack: Vec<&'a str>,
+  cycles: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+  fn new(graph: &'a ChainGraph) -> Tarjan<'a> {
+    Tarjan { graph, index: 0, indexes: HashMap::new(), lowlinks: HashMap::new(), stack: vec![], cycles: vec![] }
+  }
+
+  fn run(mut self) -> Vec<Vec<String>> {
+    for chain in &self.graph.chains {
+      if !self.indexes.contains_key(&chain[..]) {
+        self.connect(chain);
+      }
+    }
+    self.cycles
+  }
+
+  fn connect(&mut self, chain: &'a str) {
+    self.indexes.insert(chain, self.index);
+    self.lowlinks.insert(chain, self.index);
+    self.index += 1;
+    self.stack.push(chain);
+
+    for target in self.graph.targets(chain) {
+      if !self.indexes.contains_key(target) {
+        self.connect(target);
+        let lowlink = self.lowlinks[chain].min(self.lowlinks[target]);
+        self.lowlinks.insert(chain, lowlink);
+      } else if self.stack.contains(&target) {
+        let lowlink = self.lowlinks[chain].min(self.indexes[target]);
+        self.lowlinks.insert(chain, lowlink);
+      }
+    }
+
+    if self.lowlinks[chain] == self.indexes[chain] {
+      let mut component = vec![];
+      while let Some(member) = self.stack.pop() {
+        component.push(member.to_string());
+        if member == chain {
+          break;
+        }
+      }
+      component.reverse();
+      if component.len() > 1 || self.graph.targets(chain).contains(&chain) {
+        self.cycles.push(component);
+      }
+    }
+  }
+}
+
+impl RIPTables {
+  /// Builds the chain graph of a table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let graph = iptables.chain_graph("filter").unwrap();
+  /// println!("{:?}", graph.orphans());
+  /// println!("{}", graph.to_dot());
+  /// ```
+  pub fn chain_graph<T>(&self, table: T) -> RIPTResult<ChainGraph> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let rules = self.list(&table)?;
+    Ok(ChainGraph::from_rules(&table.to_string(), &rules))
+  }
+}
@@ -0,0 +1,178 @@
+//! Changes undone when they go out of scope.
+//!
+//! The `*_scoped` methods of `RIPTables` apply a change and return a `Guard` reverting it when
+//! dropped, including while unwinding from a panic. Call `commit` to keep the change instead:
+//!
+//! ```rust
+//! let iptables = riptables::new(false).unwrap();
+//! {
+//!   let _chain = iptables.new_chain_scoped("filter", "TESTCHAIN").unwrap();
+//!   let _rule = iptables.append_scoped("filter", "TESTCHAIN", "-j ACCEPT").unwrap();
+//!   let _policy = iptables.set_policy_scoped("filter", "FORWARD", "DROP").unwrap();
+//!   // ...
+//! } // the policy is reset, the rule and the chain are deleted
+//! ```
+//!
+//! Guards are dropped in reverse order of creation, so a rule appended to a scoped chain is deleted
+//! before the chain.
+
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::table::{ChainName, Policy, Table};
+use crate::RIPTables;
+
+/// How to revert a change.
+#[derive(Debug, Clone, PartialEq)]
+enum Undo {
+  DeleteRule { table: String, chain: String, rule: String },
+  DeleteChain { table: String, chain: String },
+  SetPolicy { table: String, chain: String, policy: String },
+}
+
+/// A change reverted when the guard is dropped, see the module documentation.
+#[must_use = "the change is undone as soon as the guard is dropped"]
+pub struct Guard<'a> {
+  iptables: &'a RIPTables,
+  undo: Option<Undo>,
+}
+
+impl<'a> Guard<'a> {
+  fn new(iptables: &'a RIPTables, undo: Undo) -> Guard<'a> {
+    Guard { iptables, undo: Some(undo) }
+  }
+
+  /// Keeps the change.
+  pub fn commit(mut self) {
+    self.undo = None;
+  }
+
+  /// Keeps the change, same as `commit`.
+  pub fn forget(self) {
+    self.commit()
+  }
+
+  /// Reverts the change now, reporting errors `Drop` has to ignore.
+  /// Returns `true` if the change is reverted.
+  pub fn undo(mut self) -> RIPTResult<bool> {
+    match self.undo.take() {
+      Some(undo) => self::revert(self.iptables, undo),
+      None => Ok(true),
+    }
+  }
+}
+
+impl<'a> Drop for Guard<'a> {
+  fn drop(&mut self) {
+    if let Some(undo) = self.undo.take() {
+      let _ = self::revert(self.iptables, undo);
+    }
+  }
+}
+
+fn revert(iptables: &RIPTables, undo: Undo) -> RIPTResult<bool> {
+  match undo {
+    Undo::DeleteRule { table, chain, rule } => iptables.delete(&table, &chain, &rule),
+    Undo::DeleteChain { table, chain } => {
+      if !iptables.flush_chain(&table, &chain)? {
+        return Ok(false);
+      }
+      iptables.delete_chain(&table, &chain)
+    }
+    Undo::SetPolicy { table, chain, policy } => iptables.set_policy(&table, &chain, &policy),
+  }
+}
+
+impl RIPTables {
+  /// Appends `rule` to the table/chain, deleting it when the returned guard is dropped. A rule
+  /// already in the chain is left alone and the guard does nothing, so dropping it cannot delete
+  /// the earlier copy.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let rule = iptables.append_scoped("filter", "INPUT", "-p tcp --dport 8080 -j ACCEPT").unwrap();
+  /// rule.commit();
+  /// ```
+  pub fn append_scoped<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<Guard<'_>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(table.to_string(), chain.to_string(), rule.as_ref())? {
+      return Ok(Guard { iptables: self, undo: None });
+    }
+    let args = crate::rule_args(&table, "-A", &chain, None, rule.as_ref());
+    self.execute_scoped(&args, Undo::DeleteRule {
+      table: table.to_string(),
+      chain: chain.to_string(),
+      rule: crate::to_string(rule),
+    })
+  }
+
+  /// Inserts `rule` in the `position` to the table/chain, deleting it when the returned guard is dropped.
+  /// As with `append_scoped`, a rule already in the chain is left alone.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let _rule = iptables.insert_scoped("filter", "INPUT", "-s 10.0.0.0/8 -j ACCEPT", 1).unwrap();
+  /// ```
+  pub fn insert_scoped<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<Guard<'_>> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(table.to_string(), chain.to_string(), rule.as_ref())? {
+      return Ok(Guard { iptables: self, undo: None });
+    }
+    let args = crate::rule_args(&table, "-I", &chain, Some(position), rule.as_ref());
+    self.execute_scoped(&args, Undo::DeleteRule {
+      table: table.to_string(),
+      chain: chain.to_string(),
+      rule: crate::to_string(rule),
+    })
+  }
+
+  /// Creates a user-defined chain, flushing and deleting it when the returned guard is dropped.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let _chain = iptables.new_chain_scoped("filter", "TESTCHAIN").unwrap();
+  /// ```
+  pub fn new_chain_scoped<T, C>(&self, table: T, chain: C) -> RIPTResult<Guard<'_>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?.to_string();
+    let chain = ChainName::try_from(chain.as_ref())?.to_string();
+    let args = vec!["-t".to_string(), table.clone(), "-N".to_string(), chain.clone()];
+    self.execute_scoped(&args, Undo::DeleteChain { table, chain })
+  }
+
+  /// Sets the policy of a built-in chain, restoring the current one when the returned guard is dropped.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let _policy = iptables.set_policy_scoped("mangle", "FORWARD", "DROP").unwrap();
+  /// ```
+  pub fn set_policy_scoped<T, C, P>(&self, table: T, chain: C, policy: P) -> RIPTResult<Guard<'_>> where T: AsRef<OsStr>, C: AsRef<OsStr>, P: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?.to_string();
+    let chain = ChainName::try_from(chain.as_ref())?.to_string();
+    let policy = Policy::try_from(policy.as_ref())?;
+    let current = match self.get_policy(&table, &chain)? {
+      Some(current) => current,
+      None => return Err(RIPTError::Other("the chain has no policy")),
+    };
+    let args = vec!["-t".to_string(), table.clone(), "-P".to_string(), chain.clone(), policy.to_string()];
+    self.execute_scoped(&args, Undo::SetPolicy { table, chain, policy: current })
+  }
+
+  fn execute_scoped(&self, args: &[String], undo: Undo) -> RIPTResult<Guard<'_>> {
+    let (code, output) = self.execute(|iptables| iptables.args(args))?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(Guard::new(self, undo))
+  }
+}
@@ -0,0 +1,325 @@
+//! Managing ipsets with the `ipset` utility.
+//!
+//! Large allow and deny lists belong in a set matched by a single rule, built with
+//! `RuleBuilder::match_set`. `IPSet` creates and destroys sets, changes their members, replaces
+//! all the members of a set at once and saves and restores sets:
+//!
+//! ```rust
+//! use riptables::builder::RuleBuilder;
+//!
+//! let ipset = riptables::ipset::new().unwrap();
+//! ipset.create("blocklist", "hash:net", "").unwrap();
+//! ipset.add("blocklist", "198.51.100.0/24").unwrap();
+//! ipset.replace("blocklist", "hash:net", "", &["203.0.113.0/24", "192.0.2.0/24"]).unwrap();
+//!
+//! let iptables = riptables::new(false).unwrap();
+//! let rule = RuleBuilder::new().match_set("blocklist", "src").jump("DROP").build();
+//! iptables.append_unique("filter", "INPUT", &rule[..]).unwrap();
+//! ```
+
+use std::ffi::OsStr;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+
+/// Longest name of a set.
+const SET_NAME_MAX: usize = 31;
+
+/// Start of the suffix of the set filled by `replace` before it is swapped in.
+const SWAP_SUFFIX: &str = "-swap";
+
+/// Number of the next set filled by `replace` in this process.
+static SWAP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct IPSet {
+  /// The utility command, `ipset`.
+  pub cmd: &'static str,
+}
+
+#[cfg(target_os = "linux")]
+pub fn new() -> RIPTResult<IPSet> {
+  let cmd = "ipset";
+  Command::new(cmd).arg("version").output()?;
+  Ok(IPSet { cmd })
+}
+
+/// A set as `ipset list` describes it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Set {
+  pub name: String,
+  /// Type of the set, e.g. `hash:ip`.
+  pub kind: String,
+  /// Creation options, e.g. `family inet hashsize 1024 maxelem 65536`.
+  pub header: String,
+  /// Number of rules using the set.
+  pub references: usize,
+  /// Members with their options, e.g. `10.0.0.1 timeout 30`.
+  pub members: Vec<String>,
+}
+
+/// Reads the output of `ipset list`.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::ipset;
+///
+/// let sets = ipset::parse_list("Name: allow
+/// Type: hash:ip
+/// Revision: 4
+/// Header: family inet hashsize 1024 maxelem 65536
+/// Size in memory: 168
+/// References: 1
+/// Number of entries: 2
+/// Members:
+/// 10.0.0.1
+/// 10.0.0.2
+/// ");
+/// assert_eq!(sets[0].kind, "hash:ip");
+/// assert_eq!(sets[0].members, vec!["10.0.0.1", "10.0.0.2"]);
+/// ```
+pub fn parse_list<S: AsRef<str>>(text: S) -> Vec<Set> {
+  let mut sets: Vec<Set> = vec![];
+  let mut members = false;
+  for line in text.as_ref().lines().map(|line| line.trim()) {
+    if let Some(name) = line.strip_prefix("Name:") {
+      sets.push(Set { name: name.trim().to_string(), ..Set::default() });
+      members = false;
+      continue;
+    }
+    let set = match sets.last_mut() {
+      Some(set) => set,
+      None => continue,
+    };
+    if members {
+      if !line.is_empty() {
+        set.members.push(line.to_string());
+      }
+      continue;
+    }
+    match line.split_once(':') {
+      Some(("Type", kind)) => set.kind = kind.trim().to_string(),
+      Some(("Header", header)) => set.header = header.trim().to_string(),
+      Some(("References", references)) => set.references = references.trim().parse().unwrap_or_default(),
+      Some(("Members", _)) => members = true,
+      _ => {}
+    }
+  }
+  sets
+}
+
+/// Writes the `ipset restore` commands giving `name` exactly `members`: `swap`, a set with the same
+/// type and options, is filled, swapped with `name` and destroyed. `name` is created first if
+/// missing. Members spanning lines are an error, they would add commands to the script.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::ipset;
+///
+/// assert_eq!(ipset::replace_script("allow", "allow-swap", "hash:ip", "timeout 300", &["10.0.0.1"]).unwrap(), "\
+/// create allow hash:ip timeout 300
+/// create allow-swap hash:ip timeout 300
+/// flush allow-swap
+/// add allow-swap 10.0.0.1
+/// swap allow-swap allow
+/// destroy allow-swap
+/// ");
+/// ```
+pub fn replace_script<M: AsRef<str>>(name: &str, swap: &str, kind: &str, options: &str, members: &[M]) -> RIPTResult<String> {
+  if let Some(member) = members.iter().map(|member| member.as_ref()).find(|member| member.contains(['\n', '\r'])) {
+    return Err(RIPTError::Invalid(format!("invalid set member {:?}", member)));
+  }
+  let create = |name: &str| [name, kind, options].iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<&str>>().join(" ");
+  let mut script = format!("create {}\ncreate {}\nflush {}\n", create(name), create(swap), swap);
+  for member in members {
+    script.push_str(&format!("add {} {}\n", swap, member.as_ref()));
+  }
+  script.push_str(&format!("swap {} {}\ndestroy {}\n", swap, name, swap));
+  Ok(script)
+}
+
+/// Names a new set for `replace` to fill, unique to the process and the call, keeping within the
+/// length of a set name.
+fn swap_name(name: &str) -> String {
+  let suffix = format!("{}{:x}-{:x}", SWAP_SUFFIX, process::id(), SWAP_COUNT.fetch_add(1, Ordering::Relaxed));
+  let keep = name.char_indices().map(|(index, c)| index + c.len_utf8()).take_while(|end| *end + suffix.len() <= SET_NAME_MAX).last();
+  format!("{}{}", &name[..keep.unwrap_or(0)], suffix)
+}
+
+/// Checks that `name` is a name `ipset` accepts.
+fn check_name<S: AsRef<OsStr>>(name: S) -> RIPTResult<String> {
+  let name = crate::to_string(name);
+  if name.is_empty() || name.len() > SET_NAME_MAX || name.starts_with('-') || name.chars().any(|c| c.is_whitespace() || c.is_control()) {
+    return Err(RIPTError::Invalid(format!("invalid set name {:?}", name)));
+  }
+  Ok(name)
+}
+
+impl IPSet {
+  /// Execute ipset command
+  pub fn execute<T>(&self, caller: T) -> RIPTResult<(i32, String)> where T: Fn(&mut Command) -> &mut Command {
+    let output = caller(&mut Command::new(self.cmd)).output()?;
+    crate::output_result(output)
+  }
+
+  /// Creates a set of type `kind`, e.g. `hash:ip`, with the creation options `options`, e.g.
+  /// `family inet6 timeout 300`. Creating a set that exists with the same type and options is
+  /// not an error.
+  /// Returns `true` if the set exists afterwards.
+  pub fn create<S>(&self, name: S, kind: S, options: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let options = iptparser::split_quoted(options);
+    let (code, _output) = self.execute(|ipset| ipset.arg("-exist").arg("create").arg(&name).arg(kind.as_ref()).args(&options))?;
+    Ok(code == 0)
+  }
+
+  /// Destroys a set no rule uses.
+  /// Returns `true` if the set is destroyed.
+  pub fn destroy<S>(&self, name: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let (code, _output) = self.execute(|ipset| ipset.arg("destroy").arg(&name))?;
+    Ok(code == 0)
+  }
+
+  /// Returns `true` if the set exists.
+  pub fn exists<S>(&self, name: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let (code, _output) = self.execute(|ipset| ipset.arg("-name").arg("list").arg(&name))?;
+    Ok(code == 0)
+  }
+
+  /// Lists the names of the sets.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let ipset = riptables::ipset::new().unwrap();
+  /// for name in ipset.list_names().unwrap() {
+  ///   println!("{}", name);
+  /// }
+  /// ```
+  pub fn list_names(&self) -> RIPTResult<Vec<String>> {
+    let (code, output) = self.execute(|ipset| ipset.arg("-name").arg("list"))?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(output.lines().map(|line| line.trim().to_string()).filter(|line| !line.is_empty()).collect())
+  }
+
+  /// Describes a set with its members.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let ipset = riptables::ipset::new().unwrap();
+  /// let set = ipset.list("blocklist").unwrap();
+  /// println!("{} {} members", set.kind, set.members.len());
+  /// ```
+  pub fn list<S>(&self, name: S) -> RIPTResult<Set> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let (code, output) = self.execute(|ipset| ipset.arg("list").arg(&name))?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    self::parse_list(output).pop().ok_or(RIPTError::Other("ipset did not describe the set"))
+  }
+
+  /// Adds `entry`, e.g. `10.0.0.1` or `10.0.0.0/8 timeout 60`, to a set. Adding a member twice
+  /// is not an error.
+  /// Returns `true` if the entry is a member afterwards.
+  pub fn add<S>(&self, name: S, entry: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let entry = iptparser::split_quoted(entry);
+    let (code, _output) = self.execute(|ipset| ipset.arg("-exist").arg("add").arg(&name).args(&entry))?;
+    Ok(code == 0)
+  }
+
+  /// Removes `entry` from a set. Removing a missing member is not an error.
+  /// Returns `true` if the entry is not a member afterwards.
+  pub fn delete<S>(&self, name: S, entry: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let entry = iptparser::split_quoted(entry);
+    let (code, _output) = self.execute(|ipset| ipset.arg("-exist").arg("del").arg(&name).args(&entry))?;
+    Ok(code == 0)
+  }
+
+  /// Returns `true` if `entry` is in a set.
+  pub fn test<S>(&self, name: S, entry: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let entry = iptparser::split_quoted(entry);
+    let (code, _output) = self.execute(|ipset| ipset.arg("-quiet").arg("test").arg(&name).args(&entry))?;
+    Ok(code == 0)
+  }
+
+  /// Removes every member of a set.
+  pub fn flush<S>(&self, name: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let name = self::check_name(name)?;
+    let (code, _output) = self.execute(|ipset| ipset.arg("flush").arg(&name))?;
+    Ok(code == 0)
+  }
+
+  /// Exchanges the members of two sets of the same type at once; rules using either set see the
+  /// other set's members from then on.
+  pub fn swap<S>(&self, first: S, second: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let first = self::check_name(first)?;
+    let second = self::check_name(second)?;
+    let (code, _output) = self.execute(|ipset| ipset.arg("swap").arg(&first).arg(&second))?;
+    Ok(code == 0)
+  }
+
+  /// Makes `members` the members of the set at once, creating it if missing, see `replace_script`.
+  /// The set filled meanwhile has a name of its own; finding a set of that name is an error.
+  /// Rules using the set never see it partly filled.
+  /// Returns `true` if the set is replaced.
+  pub fn replace<S, M>(&self, name: S, kind: S, options: S, members: &[M]) -> RIPTResult<bool> where S: AsRef<OsStr>, M: AsRef<str> {
+    let name = self::check_name(name)?;
+    let swap = self::swap_name(&name);
+    let script = self::replace_script(&name, &swap, &crate::to_string(kind), &crate::to_string(options), members)?;
+    if self.exists(&swap)? {
+      return Err(RIPTError::Invalid(format!("set {} is in the way of replacing {}", swap, name)));
+    }
+    let restored = self.restore(script);
+    if !matches!(restored, Ok(true)) {
+      // a restore failing partway leaves the set it was filling, a missing set is fine
+      let _ = self.destroy(&swap);
+    }
+    restored
+  }
+
+  /// Saves the sets `names`, or every set when empty, in the format of `ipset restore`.
+  pub fn save<S>(&self, names: &[S]) -> RIPTResult<String> where S: AsRef<OsStr> {
+    let mut saved = String::new();
+    let names: Vec<String> = names.iter().map(self::check_name).collect::<RIPTResult<_>>()?;
+    let names = if names.is_empty() { vec![None] } else { names.into_iter().map(Some).collect() };
+    for name in names {
+      let (code, output) = self.execute(|ipset| ipset.arg("save").args(&name))?;
+      if code != 0 {
+        return Err(RIPTError::Stderr(output));
+      }
+      saved.push_str(&output);
+    }
+    Ok(saved)
+  }
+
+  /// Runs the commands of `script`, as written by `save`, in a single `ipset restore` call.
+  /// Creating or adding what exists is not an error.
+  /// Returns `true` if every command succeeded.
+  pub fn restore<S: AsRef<str>>(&self, script: S) -> RIPTResult<bool> {
+    let mut child = Command::new(self.cmd)
+      .arg("-exist")
+      .arg("restore")
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin.write_all(script.as_ref().as_bytes())?;
+    }
+    let (code, _output) = crate::output_result(child.wait_with_output()?)?;
+    Ok(code == 0)
+  }
+}
@@ -1,10 +1,13 @@
 use std::ffi::OsStr;
+use std::net::IpAddr;
 
 use rstring_builder::StringBuilder;
 use text_reader::TextReader;
 
 use crate::error::{RIPTAnalysisError, RIPTAnalysisResult, RIPTResult};
-use crate::rule::{Archive, RIPTInterface, RIPTRule};
+use crate::rule::{Archive, Extension, ExtensionOption, RIPTInterface, RIPTRule};
+use crate::ruleset::Ruleset;
+use crate::services::Services;
 
 pub fn parse_rules(table: String, text: String) -> RIPTAnalysisResult<Vec<RIPTRule>> {
   let mut rets = vec![];
@@ -41,7 +44,7 @@ pub fn parse_rules(table: String, text: String) -> RIPTAnalysisResult<Vec<RIPTRu
   Ok(rets)
 }
 
-fn to_rule(table: String, text: String) -> RIPTAnalysisResult<RIPTRule> {
+pub fn to_rule(table: String, text: String) -> RIPTAnalysisResult<RIPTRule> {
 //  println!("{:?}", text);
 
   let mut reader = TextReader::new(text.clone());
@@ -58,21 +61,43 @@ fn to_rule(table: String, text: String) -> RIPTAnalysisResult<RIPTRule> {
   while reader.has_next() {
     match reader.next() {
       Some('-') => {
-        if times == 1 {
+        if multi {
           builder.append('-');
           continue;
         }
+        if times == 1 {
+          // a value, unless the option has none, like `--syn`, and another one follows; a dash
+          // inside a value, like `foo-bar`, never starts an option
+          let option = builder.is_empty() && match reader.next() {
+            Some(ch) => {
+              reader.back();
+              ch == '-' || ch.is_ascii_alphabetic()
+            }
+            None => false,
+          };
+          if !option {
+            builder.append('-');
+            continue;
+          }
+          times = 0;
+        }
+        // long options start with a second dash
+        match reader.next() {
+          Some('-') | None => {}
+          Some(_) => {
+            reader.back();
+          }
+        }
         if !entry {
           entry = true;
           continue;
         }
-        if let Some('-') = reader.next() {
-          continue;
-        }
-        reader.back();
         psmp.push(iptrtup.clone());
         iptrtup.clear();
       }
+      Some('!') if multi => {
+        builder.append('!');
+      }
       Some('!') => {
         psmp.push(iptrtup.clone());
         iptrtup.clear();
@@ -134,6 +159,7 @@ fn to_rule(table: String, text: String) -> RIPTAnalysisResult<RIPTRule> {
     sport: "".to_string(),
     dport: "".to_string(),
     jump: "".to_string(),
+    goto: "".to_string(),
     extensions: vec![],
   };
 
@@ -158,17 +184,86 @@ fn to_rule(table: String, text: String) -> RIPTAnalysisResult<RIPTRule> {
       "sport" => rule.sport = item.value[0].clone(),
       "dport" => rule.dport = item.value[0].clone(),
       "j" => rule.jump = item.value[0].clone(),
+      "g" => rule.goto = item.value[0].clone(),
       _ => {}
     }
   });
 
+  // options following `-m <name>` belong to that match, until the target and its own options
+  let mut target = false;
+  for item in &psmp {
+    match &item.arg[..] {
+      "m" if !target && !item.value.is_empty() => rule.extensions.push(Extension { name: item.value[0].clone(), options: vec![] }),
+      "j" | "g" => target = true,
+      "" | "A" | "P" | "N" | "s" | "d" | "i" | "o" | "p" | "f" | "m" => {}
+      name if !target => if let Some(extension) = rule.extensions.last_mut() {
+        extension.options.push(ExtensionOption {
+          negate: item.negate,
+          name: name.to_string(),
+          values: item.value.iter().filter(|value| !value.is_empty()).cloned().collect(),
+        });
+      }
+      _ => {}
+    }
+  }
+
   Ok(rule)
 }
 
+/// Parses the canonical save format described in the `ruleset` module.
+pub fn parse_ruleset(text: &str) -> RIPTAnalysisResult<Ruleset> {
+  let mut ruleset = Ruleset::new();
+  let mut table: Option<String> = None;
+  for line in text.lines() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    if let Some(name) = line.strip_prefix('*') {
+      if table.is_some() {
+        return Err(RIPTAnalysisError::UnexpectedOutput(format!("missing COMMIT before: {}", line)));
+      }
+      ruleset.table_entry(name.trim());
+      table = Some(name.trim().to_string());
+      continue;
+    }
+    let name = match table {
+      Some(ref name) => name.clone(),
+      None => return Err(RIPTAnalysisError::UnexpectedOutput(format!("line outside of a table: {}", line))),
+    };
+    if line == "COMMIT" {
+      table = None;
+      continue;
+    }
+    if let Some(declaration) = line.strip_prefix(':') {
+      let mut parts = declaration.split_whitespace();
+      let (chain, policy) = match (parts.next(), parts.next()) {
+        (Some(chain), Some(policy)) => (chain, policy),
+        _ => return Err(RIPTAnalysisError::UnexpectedOutput(line.to_string())),
+      };
+      let entry = ruleset.table_entry(&name).chain_entry(chain);
+      if policy != "-" {
+        entry.policy = Some(policy.to_string());
+      }
+      continue;
+    }
+    if line.starts_with("-A ") {
+      let rule = self::to_rule(name.clone(), line.to_string())?;
+      ruleset.table_entry(&name).chain_entry(&rule.chain.clone()).rules.push(rule);
+      continue;
+    }
+    return Err(RIPTAnalysisError::UnexpectedOutput(line.to_string()));
+  }
+  if let Some(name) = table {
+    return Err(RIPTAnalysisError::UnexpectedOutput(format!("missing COMMIT for table {}", name)));
+  }
+  Ok(ruleset)
+}
+
 
 pub fn split_quoted<S>(text: S) -> Vec<String> where S: AsRef<OsStr> {
   let mut rets = vec![];
-  let mut reader = TextReader::new(text);
+  let mut reader = TextReader::new(&*text.as_ref().to_string_lossy());
   let mut quoted_p = false; // "
   let mut quoted_b = false; // '
   let mut builder = StringBuilder::new();
@@ -216,6 +311,108 @@ pub fn split_quoted<S>(text: S) -> Vec<String> where S: AsRef<OsStr> {
 }
 
 
+/// Rewrites a rule specification the way `iptables -S` prints it, so that two specifications of
+/// the same rule compare equal: long options become short ones, the match implied by `-p` is
+/// dropped, host addresses get their prefix length, protocols and ports are written as
+/// `iptables -S` prints them (see `services`), quotes are removed and the base options
+/// (`-s -d -i -o -p -f`) come first.
+pub fn normalize_spec<S>(spec: S) -> String where S: AsRef<OsStr> {
+  let services = Services::system();
+  let mut groups = self::spec_groups(spec);
+  groups.iter_mut()
+    .filter(|group| group.option == "-p")
+    .for_each(|group| group.values.iter_mut().for_each(|value| *value = services.protocol_name(value)));
+
+  let protocol = groups.iter()
+    .find(|group| group.option == "-p" && !group.negate && !group.values.is_empty())
+    .map(|group| group.values[0].clone());
+  groups.retain(|group| !(group.option == "-m" && group.values.len() == 1 && Some(&group.values[0]) == protocol.as_ref()));
+  for group in groups.iter_mut() {
+    match &group.option[..] {
+      "--sport" | "--dport" | "--sports" | "--dports" | "--ports" => group.values.iter_mut().for_each(|value| {
+        *value = services.ports(value, protocol.as_deref());
+      }),
+      "-s" | "-d" => group.values.iter_mut().for_each(|value| {
+        if !value.contains('/') {
+          match value.parse::<IpAddr>() {
+            Ok(IpAddr::V4(_)) => value.push_str("/32"),
+            Ok(IpAddr::V6(_)) => value.push_str("/128"),
+            Err(_) => {}
+          }
+        }
+      }),
+      _ => {}
+    }
+  }
+
+  let base = ["-s", "-d", "-i", "-o", "-p", "-f"];
+  let rank = |group: &SpecGroup| base.iter().position(|option| *option == group.option).unwrap_or(base.len());
+  groups.sort_by_key(|group| rank(group));
+  self::render_groups(&groups)
+}
+
+/// Writes groups back as a rule specification.
+pub(crate) fn render_groups(groups: &[SpecGroup]) -> String {
+  groups.iter()
+    .map(|group| {
+      let mut parts = vec![];
+      if group.negate {
+        parts.push("!".to_string());
+      }
+      if !group.option.is_empty() {
+        parts.push(group.option.clone());
+      }
+      parts.extend(group.values.iter().map(|value| if value.contains(' ') { format!("\"{}\"", value) } else { value.clone() }));
+      parts.join(" ")
+    })
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+/// Splits a rule specification into its options, long options replaced by short ones.
+pub(crate) fn spec_groups<S>(spec: S) -> Vec<SpecGroup> where S: AsRef<OsStr> {
+  let mut groups: Vec<SpecGroup> = vec![];
+  let mut negate = false;
+  for token in self::split_quoted(spec).into_iter().filter(|token| !token.is_empty()) {
+    if token == "!" {
+      match groups.last_mut() {
+        // old style: -s ! 10.0.0.0/8
+        Some(last) if last.values.is_empty() => last.negate = true,
+        _ => negate = true,
+      }
+      continue;
+    }
+    if token.len() > 1 && token.starts_with('-') && token.parse::<i64>().is_err() {
+      groups.push(SpecGroup { negate, option: self::short_option(&token).to_string(), values: vec![] });
+      negate = false;
+      continue;
+    }
+    match groups.last_mut() {
+      Some(last) => last.values.push(token),
+      None => groups.push(SpecGroup { negate: false, option: "".to_string(), values: vec![token] }),
+    }
+  }
+  groups
+}
+
+fn short_option(option: &str) -> &str {
+  match option {
+    "--source" | "--src" => "-s",
+    "--destination" | "--dst" => "-d",
+    "--in-interface" => "-i",
+    "--out-interface" => "-o",
+    "--protocol" => "-p",
+    "--fragment" => "-f",
+    "--match" => "-m",
+    "--jump" => "-j",
+    "--goto" => "-g",
+    "--source-port" => "--sport",
+    "--destination-port" => "--dport",
+    _ => option,
+  }
+}
+
+
 pub fn iptables_version(text: String) -> RIPTResult<(i32, i32, i32)> {
   let mut reader = TextReader::new(text);
   let mut version = Vec::with_capacity(3);
@@ -294,3 +491,11 @@ impl Clone for IPTRTup {
     }
   }
 }
+
+
+/// An option of a rule specification with its values, see `spec_groups`.
+pub(crate) struct SpecGroup {
+  pub negate: bool,
+  pub option: String,
+  pub values: Vec<String>,
+}
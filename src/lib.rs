@@ -1,24 +1,56 @@
+use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::os::unix::io::AsRawFd;
-use std::process::Command;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use nix::fcntl::{flock, FlockArg};
 
 use error::{RIPTError, RIPTResult};
 use rule::{Archive, RIPTRule};
+use ruleset::{Change, Ruleset};
+use table::{ChainName, Policy, Table};
 
 mod iptparser;
+mod plan;
+pub mod builder;
+pub mod confirm;
+pub mod dualstack;
 pub mod error;
+pub mod forward;
+pub mod gateway;
+pub mod graph;
+pub mod guard;
+pub mod ipset;
+pub mod lint;
+pub mod lockout;
+pub mod owner;
+pub mod packet;
+pub mod parity;
+pub mod query;
 pub mod rule;
+pub mod ruleset;
+pub mod services;
+pub mod table;
+pub mod template;
+pub mod zone;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "async")]
+pub mod asynchronous;
 
 
-// List of built-in chains taken from: man 8 iptables
-const BUILTIN_CHAINS_FILTER: &'static [&'static str] = &["INPUT", "FORWARD", "OUTPUT"];
-const BUILTIN_CHAINS_MANGLE: &'static [&'static str] = &["PREROUTING", "OUTPUT", "INPUT", "FORWARD", "POSTROUTING"];
-const BUILTIN_CHAINS_NAT: &'static [&'static str] = &["PREROUTING", "POSTROUTING", "OUTPUT"];
-const BUILTIN_CHAINS_RAW: &'static [&'static str] = &["PREROUTING", "OUTPUT"];
-const BUILTIN_CHAINS_SECURITY: &'static [&'static str] = &["INPUT", "OUTPUT", "FORWARD"];
+/// Lock file used to serialize calls on iptables versions without `--wait`.
+const XTABLES_OLD_LOCK: &str = "/var/run/xtables_old.lock";
+
+/// Delay between two attempts to take the xtables lock.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Longest wait for the xtables lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
 
 
 pub struct RIPTables {
@@ -41,13 +73,12 @@ pub struct RIPTables {
 pub fn new(ipv6: bool) -> RIPTResult<RIPTables> {
   let cmd = if ipv6 { "ip6tables" } else { "iptables" };
   let version_output = Command::new(cmd).arg("--version").output()?;
-  let version_string = String::from_utf8_lossy(&version_output.stdout).into_owned();
-  let (v_major, v_minor, v_patch) = iptparser::iptables_version(version_string)?;
+  let (has_check, has_wait) = self::capabilities(&version_output)?;
 
   Ok(RIPTables {
     cmd,
-    has_check: (v_major > 1) || (v_major == 1 && v_minor > 4) || (v_major == 1 && v_minor == 4 && v_patch > 10),
-    has_wait: (v_major > 1) || (v_major == 1 && v_minor > 4) || (v_major == 1 && v_minor == 4 && v_patch > 19),
+    has_check,
+    has_wait,
   })
 }
 
@@ -72,20 +103,16 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.get_policy("filter", "INPUT").is_ok();
   /// ```
-  pub fn get_policy<S>(&self, table: S, chain: S) -> RIPTResult<Option<String>> where S: AsRef<OsStr> + Clone {
-    let bchs = self::builtin_chains(table.clone())?;
-    if !bchs.iter().as_slice().contains(&&self::to_string(chain.clone())[..]) {
-      return Err(RIPTError::Other("given chain is not a default chain in the given table, can't get policy"));
-    }
+  pub fn get_policy<T, C>(&self, table: T, chain: C) -> RIPTResult<Option<String>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    self::check_builtin_chain(&table, &chain)?;
 
-    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-S").arg(chain.clone()))?;
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S").arg(&chain))?;
     if code != 0 {
       return Err(RIPTError::Stderr(output));
     }
-    let rules = iptparser::parse_rules(self::to_string(table.clone()), output)?;
-    Ok(rules.into_iter()
-      .find(|item| item.archive == Archive::Policy && item.chain == self::to_string(chain.clone()))
-      .map(|item| item.jump))
+    self::find_policy(&table, &chain, output)
   }
 
   /// Set the default policy for a table/chain.
@@ -96,12 +123,12 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.set_policy("mangle", "FORWARD", "DROP").unwrap();
   /// ```
-  pub fn set_policy<S>(&self, table: S, chain: S, policy: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let bchs = self::builtin_chains(table.clone())?;
-    if !bchs.iter().as_slice().contains(&&self::to_string(chain.clone())[..]) {
-      return Err(RIPTError::Other("given chain is not a default chain in the given table, can't get policy"));
-    }
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-P").arg(chain.clone()).arg(policy.clone()))?;
+  pub fn set_policy<T, C, P>(&self, table: T, chain: C, policy: P) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, P: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let policy = Policy::try_from(policy.as_ref())?;
+    self::check_builtin_chain(&table, &chain)?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-P").arg(&chain).arg(policy))?;
     Ok(code == 0)
   }
 
@@ -114,20 +141,11 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.insert("nat", "TESTNAT", "-j ACCEPT", 1).unwrap();
   /// ```
-  pub fn insert<S>(&self, table: S, chain: S, rule: S, position: i32) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let rule_vec = iptparser::split_quoted(rule);
-    let pstr = position.to_string();
-    let args = &[
-      &[
-        "-t",
-        table.as_ref().to_str().unwrap(),
-        "-I",
-        chain.as_ref().to_str().unwrap(),
-        &pstr
-      ],
-      rule_vec.iter().map(|item| &item[..]).collect::<Vec<&str>>().as_slice()
-    ].concat();
-    let (code, _output) = self.execute(|iptables| iptables.args(args))?;
+  pub fn insert<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = self::rule_args(&table, "-I", &chain, Some(position), rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args))?;
     Ok(code == 0)
   }
 
@@ -141,11 +159,13 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.insert_unique("nat", "TESTNAT", "-j ACCEPT", 1).unwrap();
   /// ```
-  pub fn insert_unique<S>(&self, table: S, chain: S, rule: S, position: i32) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    if self.exists(table.clone(), chain.clone(), rule.clone())? {
+  pub fn insert_unique<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(&table, &chain, rule.clone())? {
       return Ok(true);
     }
-    self.insert(table.clone(), chain.clone(), rule.clone(), position)
+    self.insert(&table, &chain, rule, position)
   }
 
   /// Replaces `rule` in the `position` to the table/chain.
@@ -157,20 +177,11 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.replace("nat", "TESTNAT", "-j ACCEPT", 1).unwrap();
   /// ```
-  pub fn replace<S>(&self, table: S, chain: S, rule: S, position: i32) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let rule_vec = iptparser::split_quoted(rule);
-    let pstr = position.to_string();
-    let args = &[
-      &[
-        "-t",
-        table.as_ref().to_str().unwrap(),
-        "-R",
-        chain.as_ref().to_str().unwrap(),
-        &pstr
-      ],
-      rule_vec.iter().map(|item| &item[..]).collect::<Vec<&str>>().as_slice()
-    ].concat();
-    let (code, _output) = self.execute(|iptables| iptables.args(args))?;
+  pub fn replace<T, C, S>(&self, table: T, chain: C, rule: S, position: i32) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = self::rule_args(&table, "-R", &chain, Some(position), rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args))?;
     Ok(code == 0)
   }
 
@@ -184,18 +195,11 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.append("nat", "TESTNAT", "-m comment --comment \"double-quoted comment\" -j ACCEPT").unwrap();
   /// ```
-  pub fn append<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let rule_vec = iptparser::split_quoted(rule);
-    let args = &[
-      &[
-        "-t",
-        table.as_ref().to_str().unwrap(),
-        "-A",
-        chain.as_ref().to_str().unwrap(),
-      ],
-      rule_vec.iter().map(|item| &item[..]).collect::<Vec<&str>>().as_slice()
-    ].concat();
-    let (code, _output) = self.execute(|iptables| iptables.args(args))?;
+  pub fn append<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = self::rule_args(&table, "-A", &chain, None, rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args))?;
     Ok(code == 0)
   }
 
@@ -208,11 +212,13 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.append_unique("nat", "TESTNAT", "-m comment --comment \"double-quoted comment\" -j ACCEPT").unwrap();
   /// ```
-  pub fn append_unique<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    if self.exists(table.clone(), chain.clone(), rule.clone())? {
+  pub fn append_unique<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(&table, &chain, rule.clone())? {
       return Ok(true);
     }
-    self.append(table.clone(), chain.clone(), rule.clone())
+    self.append(&table, &chain, rule)
   }
 
   /// Appends or replaces `rule` to the table/chain if it does not exist.
@@ -224,13 +230,15 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.append_replace("nat", "TESTNAT", "-m comment --comment \"double-quoted comment\" -j ACCEPT").unwrap();
   /// ```
-  pub fn append_replace<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    if self.exists(table.clone(), chain.clone(), rule.clone())? {
-      if !self.delete(table.clone(), chain.clone(), rule.clone())? {
+  pub fn append_replace<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists(&table, &chain, rule.clone())? {
+      if !self.delete(&table, &chain, rule.clone())? {
         return Ok(false);
       }
     }
-    self.append(table, chain, rule)
+    self.append(&table, &chain, rule)
   }
 
   /// Deletes `rule` from the table/chain.
@@ -242,18 +250,11 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.delete("nat", "TESTNAT", "-j ACCEPT").unwrap();
   /// ```
-  pub fn delete<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let rule_vec = iptparser::split_quoted(rule);
-    let args = &[
-      &[
-        "-t",
-        table.as_ref().to_str().unwrap(),
-        "-D",
-        chain.as_ref().to_str().unwrap()
-      ],
-      rule_vec.iter().map(|item| &item[..]).collect::<Vec<&str>>().as_slice()
-    ].concat();
-    let (code, _output) = self.execute(|iptables| iptables.args(args))?;
+  pub fn delete<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let args = self::rule_args(&table, "-D", &chain, None, rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args))?;
     Ok(code == 0)
   }
 
@@ -263,9 +264,11 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.delete_all("nat", "TESTNAT", "-j ACCEPT").unwrap();
   /// ```
-  pub fn delete_all<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    while self.exists(table.clone(), chain.clone(), rule.clone())? {
-      self.delete(table.clone(), chain.clone(), rule.clone())?;
+  pub fn delete_all<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    while self.exists(&table, &chain, rule.clone())? {
+      self.delete(&table, &chain, rule.clone())?;
     }
     Ok(true)
   }
@@ -296,13 +299,14 @@ impl RIPTables {
   ///   println!("{:?}", rule.origin);
   /// }
   /// ```
-  pub fn list<S>(&self, table: S) -> RIPTResult<Vec<RIPTRule>> where S: AsRef<OsStr> + Clone {
-    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-S"))?;
+  pub fn list<T>(&self, table: T) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S"))?;
 //    let sodt = "-P OUTPUT  ACCEPT".to_string();
     if code != 0 {
       return Err(RIPTError::Stderr(output));
     }
-    Ok(iptparser::parse_rules(self::to_string(table), output)?)
+    Ok(iptparser::parse_rules(table.to_string(), output)?)
   }
 
 
@@ -314,7 +318,7 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// let names = iptables.chain_names("nat");
   /// ```
-  pub fn chain_names<S>(&self, table: S) -> RIPTResult<Vec<String>> where S: AsRef<OsStr> + Clone {
+  pub fn chain_names<T>(&self, table: T) -> RIPTResult<Vec<String>> where T: AsRef<OsStr> {
     Ok(self.list(table)?.iter()
       .filter(|item| item.archive == Archive::Policy || item.archive == Archive::NewChain)
       .map(|item| item.chain.clone())
@@ -331,12 +335,14 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// let rules: Vec<RIPTRule> = iptables.list_chains("nat", "INPUT").unwrap();
   /// ```
-  pub fn list_chains<S>(&self, table: S, chain: S) -> RIPTResult<Vec<RIPTRule>> where S: AsRef<OsStr> + Clone {
-    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-S").arg(chain.clone()))?;
+  pub fn list_chains<T, C>(&self, table: T, chain: C) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S").arg(&chain))?;
     if code != 0 {
       return Err(RIPTError::Stderr(output));
     }
-    Ok(iptparser::parse_rules(self::to_string(table), output)?)
+    Ok(iptparser::parse_rules(table.to_string(), output)?)
   }
 
   /// Creates a new user-defined chain.
@@ -348,11 +354,74 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.new_chain("nat", "TESTNAT");
   /// ```
-  pub fn new_chain<S>(&self, table: S, chain: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-N").arg(chain.clone()))?;
+  pub fn new_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-N").arg(&chain))?;
     Ok(code == 0)
   }
 
+  /// Creates a user-defined chain unless it already exists.
+  /// Returns `true` if the chain exists afterwards.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.ensure_chain("filter", "MYSERVICE").unwrap();
+  /// ```
+  pub fn ensure_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    if self.exists_chain(&table, &chain)? || self.new_chain(&table, &chain)? {
+      return Ok(true);
+    }
+    // someone else may have created it in the meantime
+    self.exists_chain(&table, &chain)
+  }
+
+  /// Makes sure `from_chain` jumps to `to_chain` exactly once, at `position`, for packets matching
+  /// `extra_match` (which may be empty). A missing jump is inserted, a misplaced or repeated one is
+  /// moved to `position`, anything else is left untouched. A `position` past the end of the chain
+  /// appends the jump.
+  /// Returns `true` if the jump is in place.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.ensure_chain("filter", "MYSERVICE").unwrap();
+  /// iptables.ensure_jump("filter", "INPUT", "MYSERVICE", 1, "-p tcp --dport 8080").unwrap();
+  /// ```
+  pub fn ensure_jump<T, C, D, S>(&self, table: T, from_chain: C, to_chain: D, position: i32, extra_match: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, D: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let from_chain = ChainName::try_from(from_chain.as_ref())?;
+    let to_chain = ChainName::try_from(to_chain.as_ref())?;
+    let spec = plan::jump_spec(&self::to_string(extra_match), to_chain.as_str());
+    let position = plan::jump_position(position);
+    let found = match plan::misplaced_jumps(&self.list_chains(&table, &from_chain)?, &spec, position) {
+      Some(found) => found,
+      None => return Ok(true),
+    };
+
+    for index in &found {
+      let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-D").arg(&from_chain).arg(index.to_string()))?;
+      if code != 0 {
+        return Err(RIPTError::Stderr(output));
+      }
+    }
+    // the jump may be written in a way `normalize` does not recognize
+    if found.is_empty() {
+      self.delete_all(&table, &from_chain, &spec)?;
+    }
+
+    if plan::appends_at(&self.list_chains(&table, &from_chain)?, position) {
+      self.append(&table, &from_chain, &spec)
+    } else {
+      self.insert(&table, &from_chain, &spec, position as i32)
+    }
+  }
+
   /// Deletes a user-defined chain in the table.
   /// Returns `true` if the chain is deleted.
   ///
@@ -362,11 +431,52 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.delete_chain("nat", "TESTNAT");
   /// ```
-  pub fn delete_chain<S>(&self, table: S, chain: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-X").arg(chain.clone()))?;
+  pub fn delete_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-X").arg(&chain))?;
     Ok(code == 0)
   }
 
+  /// Deletes a user-defined chain together with every rule jumping or going to it.
+  /// With `children`, the chains only reachable from `chain` are deleted the same way.
+  /// The rules are removed, the chains flushed and deleted in a single `iptables-restore` call,
+  /// or one command at a time when `iptables-restore` is not available.
+  /// Returns `true` if the chains are deleted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.delete_chain_recursive("filter", "SERVICES", true).unwrap();
+  /// ```
+  pub fn delete_chain_recursive<T, C>(&self, table: T, chain: C, children: bool) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?.to_string();
+    let chain = ChainName::try_from(chain.as_ref())?.to_string();
+    let removal = match plan::ChainRemoval::new(&table, &chain, &self.list(&table)?, children)? {
+      Some(removal) => removal,
+      None => return Ok(false),
+    };
+
+    match self.execute_restore(&removal.script(), true) {
+      Ok((code, _output)) => Ok(code == 0),
+      Err(RIPTError::Io(ref err)) if err.kind() == ErrorKind::NotFound => {
+        let mut deleted = true;
+        for rule in &removal.references {
+          deleted &= self.delete(&table, &rule.chain, &rule.spec())?;
+        }
+        for name in &removal.doomed {
+          deleted &= self.flush_chain(&table, name)?;
+        }
+        for name in &removal.doomed {
+          deleted &= self.delete_chain(&table, name)?;
+        }
+        Ok(deleted)
+      }
+      Err(err) => Err(err),
+    }
+  }
+
   /// Renames a chain in the table.
   /// Returns `true` if the chain is renamed.
   ///
@@ -376,8 +486,11 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.rename_chain("nat", "TESTNAT", "OTHERNAME");
   /// ```
-  pub fn rename_chain<S>(&self, table: S, old_chain: S, new_chain: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-E").arg(old_chain.clone()).arg(new_chain.clone()))?;
+  pub fn rename_chain<T, C, D>(&self, table: T, old_chain: C, new_chain: D) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, D: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let old_chain = ChainName::try_from(old_chain.as_ref())?;
+    let new_chain = ChainName::try_from(new_chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-E").arg(&old_chain).arg(&new_chain))?;
     Ok(code == 0)
   }
 
@@ -390,8 +503,10 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.flush_chain("nat", "TESTNAT");
   /// ```
-  pub fn flush_chain<S>(&self, table: S, chain: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-F").arg(chain.clone()))?;
+  pub fn flush_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-F").arg(&chain))?;
     Ok(code == 0)
   }
 
@@ -404,8 +519,10 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.exists_chain("nat", "TESTNAT");
   /// ```
-  pub fn exists_chain<S>(&self, table: S, chain: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-L").arg(chain.clone()))?;
+  pub fn exists_chain<T, C>(&self, table: T, chain: C) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-L").arg(&chain))?;
     Ok(code == 0)
   }
 
@@ -418,8 +535,9 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.flush_table("nat");
   /// ```
-  pub fn flush_table<S>(&self, table: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-F"))?;
+  pub fn flush_table<T>(&self, table: T) -> RIPTResult<bool> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let (code, _output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-F"))?;
     Ok(code == 0)
   }
 
@@ -433,12 +551,13 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// let rule: Vec<RIPTRule> = iptables.list_tables("nat").unwrap();
   /// ```
-  pub fn list_tables<S>(&self, table: S) -> RIPTResult<Vec<RIPTRule>> where S: AsRef<OsStr> + Clone {
-    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-S"))?;
+  pub fn list_tables<T>(&self, table: T) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S"))?;
     if code != 0 {
       return Err(RIPTError::Stderr(output));
     }
-    Ok(iptparser::parse_rules(self::to_string(table.clone()), output)?)
+    Ok(iptparser::parse_rules(table.to_string(), output)?)
   }
 
   /// Checks for the existence of the `rule` in the table/chain.
@@ -450,32 +569,137 @@ impl RIPTables {
   /// let iptables = riptables::new(false).unwrap();
   /// iptables.exists("nat", "TESTNAT", "-j ACCEPT").unwrap();
   /// ```
-  pub fn exists<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
+  pub fn exists<T, C, S>(&self, table: T, chain: C, rule: S) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> + Clone {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
     if !self.has_check {
-      return self.exists_old_version(table, chain, rule);
+      return self.exists_old_version(&table, &chain, rule);
     }
 
-    let rule_vec = iptparser::split_quoted(rule);
-    let args = &[
-      &[
-        "-t",
-        table.as_ref().to_str().unwrap(),
-        "-C",
-        chain.as_ref().to_str().unwrap()
-      ],
-      rule_vec.iter().map(|item| &item[..]).collect::<Vec<&str>>().as_slice()
-    ].concat();
-    let (code, _output) = self.execute(|iptables| iptables.args(args))?;
+    let args = self::rule_args(&table, "-C", &chain, None, rule);
+    let (code, _output) = self.execute(|iptables| iptables.args(&args))?;
     Ok(code == 0)
   }
 
-  fn exists_old_version<S>(&self, table: S, chain: S, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> + Clone {
-    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table.clone()).arg("-S"))?;
+  /// Takes a snapshot of the given tables.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let ruleset = iptables.save(&["filter", "nat"]).unwrap();
+  /// println!("{}", ruleset);
+  /// ```
+  pub fn save<T>(&self, tables: &[T]) -> RIPTResult<Ruleset> where T: AsRef<OsStr> {
+    let mut rules = vec![];
+    for table in tables {
+      rules.extend(self.list(table)?);
+    }
+    Ok(Ruleset::from_rules(rules))
+  }
+
+  /// Replaces every table of `ruleset` with its content in a single `iptables-restore` call.
+  /// Tables not mentioned in `ruleset` are left untouched.
+  /// Returns `true` if the ruleset is restored.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let ruleset = iptables.save(&["filter"]).unwrap();
+  /// iptables.restore(&ruleset).unwrap();
+  /// ```
+  pub fn restore(&self, ruleset: &Ruleset) -> RIPTResult<bool> {
+    let (code, _output) = self.execute_restore(&ruleset.to_string(), false)?;
+    Ok(code == 0)
+  }
+
+  /// Brings the tables of `desired` to the exact content of `desired`.
+  /// Returns the changes between the current and the desired state; nothing is changed when
+  /// `dry_run` is set or the tables are already up to date.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let desired = Ruleset::parse("*filter\n:INPUT ACCEPT\nCOMMIT\n").unwrap();
+  /// for change in iptables.apply(&desired, true).unwrap() {
+  ///   println!("{}", change);
+  /// }
+  /// ```
+  pub fn apply(&self, desired: &Ruleset, dry_run: bool) -> RIPTResult<Vec<Change>> {
+    let current = self.save(&plan::applied_tables(desired))?;
+    let (changes, script) = plan::apply(&current, desired, dry_run);
+    if let Some(script) = script {
+      let (code, output) = self.execute_restore(&script, false)?;
+      if code != 0 {
+        return Err(RIPTError::Stderr(output));
+      }
+    }
+    Ok(changes)
+  }
+
+  /// Adds the content of `ruleset` to the current tables without removing anything:
+  /// missing chains are created, policies are set and rules that do not exist yet are appended.
+  /// Returns `true` if every change succeeded.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let ruleset = riptables::config::load_file("firewall.toml").unwrap();
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.append_ruleset(&ruleset).unwrap();
+  /// ```
+  pub fn append_ruleset(&self, ruleset: &Ruleset) -> RIPTResult<bool> {
+    let mut ok = true;
+    for table in &ruleset.tables {
+      for chain in plan::user_chains(table) {
+        if !self.exists_chain(&table.name, &chain.name)? {
+          ok &= self.new_chain(&table.name, &chain.name)?;
+        }
+      }
+      for chain in &table.chains {
+        if let Some(ref policy) = chain.policy {
+          ok &= self.set_policy(&table.name, &chain.name, policy)?;
+        }
+        for rule in &chain.rules {
+          ok &= self.append_unique(&table.name, &chain.name, &rule.spec())?;
+        }
+      }
+    }
+    Ok(ok)
+  }
+
+  /// Feeds `input` to `iptables-restore` (or `ip6tables-restore`).
+  fn execute_restore(&self, input: &str, noflush: bool) -> RIPTResult<(i32, String)> {
+    let mut command = Command::new(format!("{}-restore", self.cmd));
+    command.args(plan::restore_args(noflush, self.has_wait));
+
+    let mut file_lock = None;
+    if !self.has_wait {
+      let file = File::create(XTABLES_OLD_LOCK)?;
+      self::lock(&file)?;
+      file_lock = Some(file);
+    }
+
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    drop(file_lock);
+
+    self::output_result(output)
+  }
+
+  fn exists_old_version<S>(&self, table: &Table, chain: &ChainName, rule: S) -> RIPTResult<bool> where S: AsRef<OsStr> {
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(table).arg("-S"))?;
     if code != 0 {
       return Ok(false);
     }
-    let exists = output.contains(&format!("-A {} {}", chain.as_ref().to_str().unwrap(), rule.as_ref().to_str().unwrap()));
-    Ok(exists)
+    Ok(self::contains_rule(&output, chain, rule))
   }
 }
 
@@ -500,20 +724,9 @@ impl<T> IptablesCaller<T> where T: Fn(&mut Command) -> &mut Command {
     if has_wait {
       command.arg("--wait");
     } else {
-      file_lock = Some(File::create("/var/run/xtables_old.lock")?);
-
-      let mut need_retry = true;
-      while need_retry {
-        match flock(file_lock.as_ref().unwrap().as_raw_fd(), FlockArg::LockExclusiveNonblock) {
-          Ok(_) => need_retry = false,
-          Err(e) => if e.errno() == nix::errno::EAGAIN {
-            // FIXME: may cause infinite loop
-            need_retry = true;
-          } else {
-            return Err(RIPTError::Nix(e));
-          },
-        }
-      }
+      let file = File::create(XTABLES_OLD_LOCK)?;
+      self::lock(&file)?;
+      file_lock = Some(file);
     }
 
 //    println!("{:?}", command);
@@ -524,33 +737,105 @@ impl<T> IptablesCaller<T> where T: Fn(&mut Command) -> &mut Command {
       }
     }
 
-    let status = output.status.code();
+    self::output_result(output)
+  }
+}
 
-    match status {
-      Some(0) => {
-        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
-        Ok((0, stdout))
-      }
-      Some(code) => {
-        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
-        Ok((code, stderr))
-      }
-      None => Err(RIPTError::Other("None output code"))
+/// Parses the output of `iptables --version` into the `(has_check, has_wait)` flags.
+fn capabilities(version_output: &Output) -> RIPTResult<(bool, bool)> {
+  let version_string = String::from_utf8_lossy(&version_output.stdout).into_owned();
+  let (v_major, v_minor, v_patch) = iptparser::iptables_version(version_string)?;
+  let has_check = (v_major > 1) || (v_major == 1 && v_minor > 4) || (v_major == 1 && v_minor == 4 && v_patch > 10);
+  let has_wait = (v_major > 1) || (v_major == 1 && v_minor > 4) || (v_major == 1 && v_minor == 4 && v_patch > 19);
+  Ok((has_check, has_wait))
+}
+
+/// Takes the xtables lock, trying again every `LOCK_RETRY_INTERVAL` for up to `LOCK_TIMEOUT`.
+fn lock(file: &File) -> RIPTResult<()> {
+  let start = Instant::now();
+  while !self::try_lock(file)? {
+    if start.elapsed() >= LOCK_TIMEOUT {
+      return Err(RIPTError::Other("timed out waiting for the xtables lock"));
+    }
+    thread::sleep(LOCK_RETRY_INTERVAL);
+  }
+  Ok(())
+}
+
+/// Tries to take the xtables lock without blocking.
+/// Returns `false` if the lock is currently held by someone else.
+fn try_lock(file: &File) -> RIPTResult<bool> {
+  match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+    Ok(_) => Ok(true),
+    Err(e) => if e.errno() == nix::errno::EAGAIN {
+      Ok(false)
+    } else {
+      Err(RIPTError::Nix(e))
+    },
+  }
+}
+
+/// Turns a finished iptables process into `(code, stdout)` on success or `(code, stderr)` on failure.
+fn output_result(output: Output) -> RIPTResult<(i32, String)> {
+  match output.status.code() {
+    Some(0) => {
+      let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+      Ok((0, stdout))
+    }
+    Some(code) => {
+      let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+      Ok((code, stderr))
     }
+    None => Err(RIPTError::Other("None output code"))
   }
 }
 
-fn builtin_chains<S>(table: S) -> RIPTResult<&'static [&'static str]> where S: AsRef<OsStr> + Clone {
-  match &self::to_string(table)[..] {
-    "filter" => Ok(BUILTIN_CHAINS_FILTER),
-    "mangle" => Ok(BUILTIN_CHAINS_MANGLE),
-    "nat" => Ok(BUILTIN_CHAINS_NAT),
-    "raw" => Ok(BUILTIN_CHAINS_RAW),
-    "security" => Ok(BUILTIN_CHAINS_SECURITY),
-    _ => Err(RIPTError::Other("given table is not supported by iptables")),
+/// Builds `-t <table> <action> <chain> [position] <rule...>` arguments.
+fn rule_args<T, C, S>(table: T, action: &str, chain: C, position: Option<i32>, rule: S) -> Vec<String> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+  let mut args = vec![
+    "-t".to_string(),
+    self::to_string(table),
+    action.to_string(),
+    self::to_string(chain),
+  ];
+  if let Some(position) = position {
+    args.push(position.to_string());
   }
+  args.extend(iptparser::split_quoted(rule));
+  args
+}
+
+fn check_builtin_chain<T, C>(table: T, chain: C) -> RIPTResult<()> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+  let bchs = match Table::try_from(table.as_ref())?.builtin_chains() {
+    Some(bchs) => bchs,
+    // iptables knows the chains of the other tables
+    None => return Ok(()),
+  };
+  if !bchs.contains(&&self::to_string(chain)[..]) {
+    return Err(RIPTError::Other("given chain is not a default chain in the given table, can't get policy"));
+  }
+  Ok(())
+}
+
+fn find_policy<T, C>(table: T, chain: C, output: String) -> RIPTResult<Option<String>> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+  let rules = iptparser::parse_rules(self::to_string(table), output)?;
+  let chain = self::to_string(chain);
+  Ok(rules.into_iter()
+    .find(|item| item.archive == Archive::Policy && item.chain == chain)
+    .map(|item| item.jump))
+}
+
+/// Fallback for `exists` on iptables versions without `-C`.
+fn contains_rule<C, S>(output: &str, chain: C, rule: S) -> bool where C: AsRef<OsStr>, S: AsRef<OsStr> {
+  output.contains(&format!("-A {} {}", self::to_string(chain), self::to_string(rule)))
+}
+
+fn builtin_chains<S>(table: S) -> RIPTResult<&'static [&'static str]> where S: AsRef<OsStr> {
+  Table::try_from(table.as_ref()).ok().and_then(|table| table.builtin_chains())
+    .ok_or(RIPTError::Other("given table is not supported by iptables"))
 }
 
+/// Converts an argument, replacing what is not UTF-8.
 fn to_string<S>(text: S) -> String where S: AsRef<OsStr> {
-  text.as_ref().to_str().unwrap().to_string()
+  text.as_ref().to_string_lossy().into_owned()
 }
@@ -0,0 +1,313 @@
+//! Finds rules that can never match or do nothing.
+//!
+//! Rules are compared through what they match, as far as the `packet` evaluator understands it:
+//! addresses, interfaces, protocol, ports and connection states. Any other match has to be written
+//! the same way in both rules for one to cover the other.
+//!
+//! ```rust
+//! use riptables::lint::{self, LintKind};
+//! use riptables::ruleset::Ruleset;
+//!
+//! let ruleset = Ruleset::parse("*filter
+//! :INPUT ACCEPT
+//! -A INPUT -s 10.0.0.0/8 -j ACCEPT
+//! -A INPUT -s 10.1.0.0/16 -p tcp --dport 22 -j DROP
+//! COMMIT
+//! ").unwrap();
+//! let lints = lint::lint_table(ruleset.table("filter").unwrap());
+//! assert_eq!(lints[0].position, 2);
+//! assert_eq!(lints[0].kind, LintKind::Shadowed { by: 1 });
+//! println!("{}", lints[0]);
+//! ```
+
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::error::RIPTResult;
+use crate::iptparser;
+use crate::rule::{self, RIPTRule};
+use crate::ruleset::{Ruleset, RulesetTable};
+use crate::table::Table;
+use crate::RIPTables;
+
+/// Targets after which the packet leaves the chain.
+const TERMINAL_TARGETS: &[&str] = &[
+  "ACCEPT", "DROP", "REJECT", "RETURN", "QUEUE", "NFQUEUE", "DNAT", "SNAT", "MASQUERADE", "REDIRECT", "NETMAP", "TPROXY",
+];
+
+/// What is wrong with a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintKind {
+  /// Same rule as the one at position `of`.
+  Duplicate { of: usize },
+  /// Every packet it matches is already caught by the terminal rule at position `by`.
+  Shadowed { by: usize },
+  /// Jumps or goes to a chain without rules.
+  EmptyChain { chain: String },
+}
+
+/// A problem found in a chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+  pub table: String,
+  pub chain: String,
+  /// 1-based position of the offending rule.
+  pub position: usize,
+  pub spec: String,
+  pub kind: LintKind,
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {} rule {} ({}): ", self.table, self.chain, self.position, self.spec)?;
+    match self.kind {
+      LintKind::Duplicate { of } => write!(f, "duplicate of rule {}", of),
+      LintKind::Shadowed { by } => write!(f, "never matches, rule {} catches every packet it would", by),
+      LintKind::EmptyChain { ref chain } => write!(f, "{} has no rules", chain),
+    }
+  }
+}
+
+/// Lints every chain of `table`.
+pub fn lint_table(table: &RulesetTable) -> Vec<Lint> {
+  table.chains.iter().flat_map(|chain| self::lint_chain(table, &chain.name)).collect()
+}
+
+/// Lints `chain` of `table`. The table is needed to tell which targets are empty chains.
+pub fn lint_chain(table: &RulesetTable, chain: &str) -> Vec<Lint> {
+  let rules = match table.chain(chain) {
+    Some(chain) => &chain.rules,
+    None => return vec![],
+  };
+  let parsed: Vec<(String, Conditions)> = rules.iter()
+    .map(|rule| (rule::normalize(rule.spec()), Conditions::parse(&rule.spec())))
+    .collect();
+
+  let mut lints = vec![];
+  for (index, rule) in rules.iter().enumerate() {
+    let lint = |kind: LintKind| Lint {
+      table: table.name.clone(),
+      chain: chain.to_string(),
+      position: index + 1,
+      spec: rule.spec(),
+      kind,
+    };
+
+    let duplicate = parsed[..index].iter().position(|(spec, _)| *spec == parsed[index].0);
+    let shadowing = (0..index).find(|&earlier| {
+      self::is_terminal(&rules[earlier]) && parsed[earlier].1.covers(&parsed[index].1)
+    });
+    match (duplicate, shadowing) {
+      (Some(of), _) => lints.push(lint(LintKind::Duplicate { of: of + 1 })),
+      (None, Some(by)) => lints.push(lint(LintKind::Shadowed { by: by + 1 })),
+      (None, None) => {}
+    }
+
+    let target = if rule.goto.is_empty() { &rule.jump } else { &rule.goto };
+    if let Some(target) = table.chain(target) {
+      if target.rules.is_empty() {
+        lints.push(lint(LintKind::EmptyChain { chain: target.name.clone() }));
+      }
+    }
+  }
+  lints
+}
+
+/// Lints every table of `ruleset`.
+pub fn lint_ruleset(ruleset: &Ruleset) -> Vec<Lint> {
+  ruleset.tables.iter().flat_map(self::lint_table).collect()
+}
+
+/// Returns `true` if no packet matching `rule` reaches the next rule of the chain.
+/// Extension targets other than the known terminal ones, like `LOG`, are assumed to let it through.
+fn is_terminal(rule: &RIPTRule) -> bool {
+  !rule.goto.is_empty() || TERMINAL_TARGETS.contains(&&rule.jump[..])
+}
+
+/// A part of a rule restricting the packets it matches.
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+  Source,
+  Destination,
+  InInterface,
+  OutInterface,
+  Protocol,
+  Sport,
+  Dport,
+  State,
+  /// Any other match option, with its module.
+  Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+  field: Field,
+  negate: bool,
+  values: Vec<String>,
+}
+
+/// Everything a rule matches on.
+#[derive(Debug, Clone, PartialEq)]
+struct Conditions(Vec<Condition>);
+
+impl Conditions {
+  fn parse(spec: &str) -> Conditions {
+    let mut conditions = vec![];
+    let mut module = String::new();
+    for group in iptparser::spec_groups(spec) {
+      let field = match &group.option[..] {
+        "-j" | "-g" => break,
+        "-m" => {
+          module = group.values.join(" ");
+          continue;
+        }
+        "--comment" => continue,
+        "-s" => Field::Source,
+        "-d" => Field::Destination,
+        "-i" => Field::InInterface,
+        "-o" => Field::OutInterface,
+        "-p" => Field::Protocol,
+        "--sport" | "--sports" => Field::Sport,
+        "--dport" | "--dports" => Field::Dport,
+        "--ctstate" | "--state" => Field::State,
+        option => Field::Other(format!("{} {}", module, option)),
+      };
+      let values = match field {
+        // the values of other options only mean something together, e.g. `--tcp-flags SYN,ACK ACK`
+        Field::Other(_) => vec![group.values.join(" ")],
+        _ => group.values.iter()
+          .flat_map(|value| value.split(','))
+          .map(|value| value.to_string())
+          .collect(),
+      };
+      conditions.push(Condition { field, negate: group.negate, values });
+    }
+    Conditions(conditions)
+  }
+
+  /// Returns `true` if every packet matching `other` matches `self` too.
+  fn covers(&self, other: &Conditions) -> bool {
+    self.0.iter().all(|condition| {
+      condition.is_universal() || other.0.iter().any(|narrower| narrower.field == condition.field && condition.covers(narrower))
+    })
+  }
+}
+
+impl Condition {
+  fn is_universal(&self) -> bool {
+    !self.negate && self.field == Field::Protocol && self.values.iter().any(|value| value == "all" || value == "0")
+  }
+
+  fn covers(&self, other: &Condition) -> bool {
+    let includes = |outer: &[String], inner: &[String]| inner.iter().all(|value| outer.iter().any(|range| self.field.includes(range, value)));
+    match (self.negate, other.negate) {
+      (false, false) => includes(&self.values, &other.values),
+      // what `other` excludes is a superset of what `self` excludes
+      (true, true) => includes(&other.values, &self.values),
+      // nothing `other` matches is excluded by `self`
+      (true, false) => other.values.iter().all(|value| self.values.iter().all(|excluded| self.field.disjoint(excluded, value))),
+      (false, true) => false,
+    }
+  }
+}
+
+impl Field {
+  /// Returns `true` if everything `inner` matches, `outer` matches.
+  fn includes(&self, outer: &str, inner: &str) -> bool {
+    match *self {
+      Field::Source | Field::Destination => match (self::network(outer), self::network(inner)) {
+        (Some(outer), Some(inner)) => outer.bits == inner.bits && outer.prefix <= inner.prefix && outer.contains(inner.base),
+        _ => outer == inner,
+      },
+      Field::InInterface | Field::OutInterface => match outer.strip_suffix('+') {
+        Some(prefix) => inner.starts_with(prefix),
+        None => outer == inner,
+      },
+      Field::Sport | Field::Dport => match (self::ports(outer), self::ports(inner)) {
+        (Some((first, last)), Some((inner_first, inner_last))) => first <= inner_first && inner_last <= last,
+        _ => outer == inner,
+      },
+      Field::Protocol | Field::State => outer.eq_ignore_ascii_case(inner),
+      Field::Other(_) => outer == inner,
+    }
+  }
+
+  /// Returns `true` if nothing matches both `first` and `second`.
+  fn disjoint(&self, first: &str, second: &str) -> bool {
+    match *self {
+      Field::Source | Field::Destination => match (self::network(first), self::network(second)) {
+        (Some(first), Some(second)) => first.bits != second.bits || !(first.contains(second.base) || second.contains(first.base)),
+        _ => false,
+      },
+      Field::Sport | Field::Dport => match (self::ports(first), self::ports(second)) {
+        (Some(first), Some(second)) => first.1 < second.0 || second.1 < first.0,
+        _ => false,
+      },
+      Field::InInterface | Field::OutInterface => !first.ends_with('+') && !second.ends_with('+') && first != second,
+      Field::Protocol | Field::State => !first.eq_ignore_ascii_case(second),
+      Field::Other(_) => false,
+    }
+  }
+}
+
+/// An address with its prefix, as bits aligned to the left of a `u128`.
+#[derive(Debug, Clone, Copy)]
+struct Network {
+  base: u128,
+  prefix: u32,
+  bits: u32,
+}
+
+impl Network {
+  fn contains(&self, address: u128) -> bool {
+    let mask = if self.prefix == 0 { 0 } else { u128::MAX << (128 - self.prefix) };
+    self.base & mask == address & mask
+  }
+}
+
+fn network(text: &str) -> Option<Network> {
+  let (address, prefix) = match text.split_once('/') {
+    Some((address, prefix)) => (address, Some(prefix)),
+    None => (text, None),
+  };
+  let (base, bits) = match address.parse::<IpAddr>().ok()? {
+    IpAddr::V4(address) => ((u32::from(address) as u128) << 96, 32),
+    IpAddr::V6(address) => (u128::from(address), 128),
+  };
+  let prefix = match prefix {
+    None => bits,
+    Some(prefix) => match (prefix.parse::<u32>(), prefix.parse::<IpAddr>()) {
+      (Ok(length), _) if length <= bits => length,
+      (_, Ok(IpAddr::V4(mask))) if bits == 32 => u32::from(mask).leading_ones(),
+      _ => return None,
+    },
+  };
+  Some(Network { base, prefix, bits })
+}
+
+fn ports(text: &str) -> Option<(u16, u16)> {
+  let (first, last) = text.split_once(':').unwrap_or((text, text));
+  let first = if first.is_empty() { 0 } else { first.parse().ok()? };
+  let last = if last.is_empty() { u16::MAX } else { last.parse().ok()? };
+  Some((first, last))
+}
+
+impl RIPTables {
+  /// Lints the chains of a table.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// for lint in iptables.lint("filter").unwrap() {
+  ///   println!("{}", lint);
+  /// }
+  /// ```
+  pub fn lint<T>(&self, table: T) -> RIPTResult<Vec<Lint>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let ruleset = Ruleset::from_rules(self.list(&table)?);
+    Ok(self::lint_ruleset(&ruleset))
+  }
+}
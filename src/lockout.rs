@@ -0,0 +1,208 @@
+//! Checks that a ruleset keeps the management connection working before applying it.
+//!
+//! The connection is followed through the `filter` table with the `packet` evaluator: its packets
+//! in `INPUT`, for the current session and for a new one, and the replies in `OUTPUT`. Policies
+//! count, so a ruleset setting `INPUT` to `DROP` without allowing the connection is caught.
+//!
+//! ```rust
+//! use riptables::lockout::{self, Management};
+//! use riptables::ruleset::Ruleset;
+//!
+//! let ruleset = Ruleset::parse("*filter\n:INPUT DROP\n-A INPUT -p tcp --dport 80 -j ACCEPT\nCOMMIT\n").unwrap();
+//! let ssh = Management::new("192.168.1.10".parse().unwrap(), 22);
+//! let report = lockout::check(&ruleset, &ssh);
+//! assert!(report.is_locked_out());
+//! println!("{}", report);
+//! ```
+
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::packet::{self, Evaluation, Packet, Verdict};
+use crate::ruleset::{Change, Ruleset, RulesetChain, RulesetTable};
+use crate::RIPTables;
+
+/// The connection used to manage the host, e.g. an SSH session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Management {
+  /// Address of the administrator.
+  pub source: IpAddr,
+  /// Local port of the service, e.g. `22`.
+  pub port: u16,
+  pub protocol: String,
+  /// Local interface the connection arrives on, when known.
+  pub interface: Option<String>,
+  /// Local address the connection arrives on, when known.
+  pub destination: Option<IpAddr>,
+}
+
+impl Management {
+  /// A TCP connection from `source` to the local `port`.
+  pub fn new(source: IpAddr, port: u16) -> Management {
+    Management { source, port, protocol: "tcp".to_string(), interface: None, destination: None }
+  }
+
+  pub fn protocol<S: ToString>(mut self, protocol: S) -> Management {
+    self.protocol = protocol.to_string();
+    self
+  }
+
+  pub fn interface<S: ToString>(mut self, interface: S) -> Management {
+    self.interface = Some(interface.to_string());
+    self
+  }
+
+  pub fn destination(mut self, destination: IpAddr) -> Management {
+    self.destination = Some(destination);
+    self
+  }
+
+  /// An incoming packet of the connection in the given conntrack state.
+  fn packet(&self, state: &str) -> Packet {
+    let mut packet = Packet::new(&self.protocol).source(self.source).dport(self.port).state(state);
+    packet.destination = self.destination;
+    packet.in_interface = self.interface.clone();
+    packet
+  }
+}
+
+/// What the `filter` table does to the management connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockoutReport {
+  /// A new connection entering `INPUT`.
+  pub new_connection: Evaluation,
+  /// A packet of the current session entering `INPUT`.
+  pub established: Evaluation,
+  /// A reply of the host leaving through `OUTPUT`.
+  pub reply: Evaluation,
+}
+
+impl LockoutReport {
+  /// Returns `true` if some packet of the connection is not accepted.
+  pub fn is_locked_out(&self) -> bool {
+    self.evaluations().iter().any(|(_, evaluation)| evaluation.verdict != Verdict::Accept)
+  }
+
+  /// Returns `true` if rules the evaluator cannot decide may change the outcome.
+  pub fn is_uncertain(&self) -> bool {
+    self.evaluations().iter().any(|(_, evaluation)| !evaluation.uncertain.is_empty())
+  }
+
+  /// Returns `true` if the connection is known to survive.
+  pub fn is_safe(&self) -> bool {
+    !self.is_locked_out() && !self.is_uncertain()
+  }
+
+  fn evaluations(&self) -> [(&'static str, &Evaluation); 3] {
+    [
+      ("new connection", &self.new_connection),
+      ("established connection", &self.established),
+      ("reply", &self.reply),
+    ]
+  }
+}
+
+impl fmt::Display for LockoutReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut lines = vec![];
+    for (name, evaluation) in self.evaluations().iter() {
+      let verdict = &evaluation.verdict;
+      match evaluation.rule {
+        Some(ref rule) => lines.push(format!("{}: {} by {} rule {}: {}", name, verdict, rule.chain, rule.position, rule.spec)),
+        None => lines.push(format!("{}: {} by policy", name, verdict)),
+      }
+      for rule in &evaluation.uncertain {
+        lines.push(format!("  may match {} rule {}: {}", rule.chain, rule.position, rule.spec));
+      }
+    }
+    write!(f, "{}", lines.join("\n"))
+  }
+}
+
+/// Follows the management connection through the `filter` table of `ruleset`.
+/// Without a `filter` table, everything is accepted.
+pub fn check(ruleset: &Ruleset, management: &Management) -> LockoutReport {
+  let empty = RulesetTable { name: "filter".to_string(), chains: vec![] };
+  let filter = ruleset.table("filter").unwrap_or(&empty);
+  let new_connection = management.packet("NEW");
+  let established = management.packet("ESTABLISHED");
+  LockoutReport {
+    new_connection: packet::evaluate(filter, "INPUT", &new_connection),
+    established: packet::evaluate(filter, "INPUT", &established),
+    reply: packet::evaluate(filter, "OUTPUT", &established.reply()),
+  }
+}
+
+/// Like `check`, for `desired` applied over `current`: as `apply` keeps them, the `filter` table
+/// of `current` stands in for a missing one and its policies for those `desired` leaves out.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::lockout::{self, Management};
+/// use riptables::ruleset::Ruleset;
+///
+/// let current = Ruleset::parse("*filter\n:INPUT DROP [0:0]\nCOMMIT\n").unwrap();
+/// let desired = Ruleset::parse("*filter\n-A INPUT -p tcp --dport 80 -j ACCEPT\nCOMMIT\n").unwrap();
+/// assert!(lockout::check_with(&desired, &current, &Management::new("10.0.0.1".parse().unwrap(), 22)).is_locked_out());
+/// ```
+pub fn check_with(desired: &Ruleset, current: &Ruleset, management: &Management) -> LockoutReport {
+  let mut filter = match (desired.table("filter"), current.table("filter")) {
+    (Some(filter), _) | (None, Some(filter)) => filter.clone(),
+    (None, None) => return self::check(desired, management),
+  };
+  if let Some(current) = current.table("filter") {
+    for chain in current.chains.iter().filter(|chain| chain.policy.is_some()) {
+      match filter.chains.iter_mut().find(|desired| desired.name == chain.name) {
+        Some(desired) if desired.policy.is_none() => desired.policy = chain.policy.clone(),
+        Some(_) => {}
+        None => filter.chains.push(RulesetChain { name: chain.name.clone(), policy: chain.policy.clone(), rules: vec![] }),
+      }
+    }
+  }
+  self::check(&Ruleset { tables: vec![filter] }, management)
+}
+
+impl RIPTables {
+  /// Checks what `desired` would do to the management connection once applied over the current
+  /// `filter` table, see `check_with`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::lockout::Management;
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let desired = Ruleset::parse("*filter\n:INPUT DROP\nCOMMIT\n").unwrap();
+  /// let report = iptables.check_lockout(&desired, &Management::new("10.0.0.1".parse().unwrap(), 22)).unwrap();
+  /// println!("{}", report);
+  /// ```
+  pub fn check_lockout(&self, desired: &Ruleset, management: &Management) -> RIPTResult<LockoutReport> {
+    Ok(self::check_with(desired, &self.save(&["filter"])?, management))
+  }
+
+  /// Like `apply`, but refuses with `RIPTError::Lockout` a ruleset that is not known to keep the
+  /// management connection working, unless `force` is set.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::lockout::Management;
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let desired = Ruleset::parse("*filter\n:INPUT DROP\n-A INPUT -p tcp --dport 22 -j ACCEPT\nCOMMIT\n").unwrap();
+  /// iptables.apply_safe(&desired, &Management::new("10.0.0.1".parse().unwrap(), 22), false).unwrap();
+  /// ```
+  pub fn apply_safe(&self, desired: &Ruleset, management: &Management, force: bool) -> RIPTResult<Vec<Change>> {
+    if !force {
+      let report = self.check_lockout(desired, management)?;
+      if !report.is_safe() {
+        return Err(RIPTError::Lockout(format!("the ruleset may cut the management connection\n{}", report)));
+      }
+    }
+    self.apply(desired, false)
+  }
+}
@@ -0,0 +1,249 @@
+//! Ownership tags on rules.
+//!
+//! The `*_owned` methods of `RIPTables` mark every rule they create with a
+//! `-m comment --comment "<owner>:<id>"` match. The rules of an owner can then be listed and removed
+//! without touching rules created by someone else, and the ones whose id is no longer in use can be
+//! garbage-collected:
+//!
+//! ```rust
+//! let iptables = riptables::new(false).unwrap();
+//! iptables.append_owned("filter", "INPUT", "-p tcp --dport 8080 -j ACCEPT", "myservice", "web").unwrap();
+//! iptables.append_owned("filter", "INPUT", "-p tcp --dport 8443 -j ACCEPT", "myservice", "web-tls").unwrap();
+//!
+//! // "web-tls" is gone from the configuration of the service
+//! iptables.gc_owned("filter", "myservice", &["web"]).unwrap();
+//! // the service is uninstalled
+//! iptables.delete_owned("filter", "myservice").unwrap();
+//! ```
+
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::rule::{Archive, RIPTRule};
+use crate::table::{ChainName, Table};
+use crate::RIPTables;
+
+/// The `<owner>:<id>` comment of an owned rule.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OwnerTag {
+  pub owner: String,
+  pub id: String,
+}
+
+impl OwnerTag {
+  /// Creates a tag. The owner must not be empty nor contain `:`, and neither part may contain
+  /// whitespace or double quotes.
+  pub fn new<S: ToString>(owner: S, id: S) -> RIPTResult<OwnerTag> {
+    let tag = OwnerTag { owner: owner.to_string(), id: id.to_string() };
+    if tag.owner.is_empty() || tag.owner.contains(':') {
+      return Err(RIPTError::Other("owner must not be empty nor contain ':'"));
+    }
+    if format!("{}{}", tag.owner, tag.id).chars().any(|ch| ch.is_whitespace() || ch == '"') {
+      return Err(RIPTError::Other("owner and id must not contain whitespace nor '\"'"));
+    }
+    Ok(tag)
+  }
+
+  /// Reads a tag from the text of a comment, splitting at the first `:`.
+  pub fn parse(comment: &str) -> Option<OwnerTag> {
+    let (owner, id) = comment.split_once(':')?;
+    OwnerTag::new(owner, id).ok()
+  }
+
+  /// Adds the tag to `rule`, before its target so the rule reads the way `iptables -S` prints it.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::owner::OwnerTag;
+  ///
+  /// let tag = OwnerTag::new("myservice", "web").unwrap();
+  /// assert_eq!(tag.apply("-p tcp --dport 8080 -j ACCEPT"), "-p tcp --dport 8080 -m comment --comment myservice:web -j ACCEPT");
+  /// ```
+  pub fn apply<S: AsRef<OsStr>>(&self, rule: S) -> String {
+    let mut tokens: Vec<String> = iptparser::split_quoted(rule).into_iter()
+      .filter(|token| !token.is_empty())
+      .map(|token| if token.contains(' ') { format!("\"{}\"", token) } else { token })
+      .collect();
+    let target = tokens.iter()
+      .position(|token| ["-j", "--jump", "-g", "--goto"].contains(&&token[..]))
+      .unwrap_or(tokens.len());
+    let comment = vec!["-m".to_string(), "comment".to_string(), "--comment".to_string(), self.to_string()];
+    tokens.splice(target..target, comment);
+    tokens.join(" ")
+  }
+}
+
+/// A rule of a set managed as a unit, like a port forward.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedRule {
+  pub table: &'static str,
+  pub chain: &'static str,
+  /// Specification, tagged.
+  pub spec: String,
+}
+
+impl fmt::Display for OwnerTag {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.owner, self.id)
+  }
+}
+
+impl RIPTRule {
+  /// Returns the ownership tag of the rule, if one of its comments is a tag.
+  pub fn owner_tag(&self) -> Option<OwnerTag> {
+    self.extensions.iter()
+      .filter(|extension| extension.name == "comment")
+      .flat_map(|extension| extension.options.iter())
+      .filter(|option| option.name == "comment" && !option.negate)
+      .filter_map(|option| option.values.first())
+      .find_map(|comment| OwnerTag::parse(comment))
+  }
+
+  /// Returns `true` if the rule is tagged for `owner`.
+  pub fn is_owned_by(&self, owner: &str) -> bool {
+    self.owner_tag().map(|tag| tag.owner == owner).unwrap_or(false)
+  }
+}
+
+impl RIPTables {
+  /// Appends `rule` tagged with `owner:id` to the table/chain.
+  /// Returns `true` if the rule is appended.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.append_owned("filter", "INPUT", "-p tcp --dport 8080 -j ACCEPT", "myservice", "web").unwrap();
+  /// ```
+  pub fn append_owned<T, C, S>(&self, table: T, chain: C, rule: S, owner: &str, id: &str) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let rule = OwnerTag::new(owner, id)?.apply(rule);
+    self.append(&table, &chain, &rule[..])
+  }
+
+  /// Appends `rule` tagged with `owner:id` to the table/chain if it does not exist.
+  /// Returns `true` if the rule is appended or already exists.
+  pub fn append_owned_unique<T, C, S>(&self, table: T, chain: C, rule: S, owner: &str, id: &str) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let rule = OwnerTag::new(owner, id)?.apply(rule);
+    self.append_unique(&table, &chain, &rule[..])
+  }
+
+  /// Inserts `rule` tagged with `owner:id` in the `position` to the table/chain.
+  /// Returns `true` if the rule is inserted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.insert_owned("filter", "INPUT", "-s 10.0.0.0/8 -j ACCEPT", 1, "myservice", "lan").unwrap();
+  /// ```
+  pub fn insert_owned<T, C, S>(&self, table: T, chain: C, rule: S, position: i32, owner: &str, id: &str) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr>, S: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let rule = OwnerTag::new(owner, id)?.apply(rule);
+    self.insert(&table, &chain, &rule[..], position)
+  }
+
+  /// Lists the rules of the table tagged for `owner`, in listing order.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// for rule in iptables.list_owned("filter", "myservice").unwrap() {
+  ///   println!("{} {}", rule.owner_tag().unwrap().id, rule.origin);
+  /// }
+  /// ```
+  pub fn list_owned<T>(&self, table: T, owner: &str) -> RIPTResult<Vec<RIPTRule>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    Ok(self.list(&table)?.into_iter()
+      .filter(|rule| rule.archive == Archive::Append && rule.is_owned_by(owner))
+      .collect())
+  }
+
+  /// Deletes every rule of the table tagged for `owner`.
+  /// Returns the number of deleted rules.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.delete_owned("filter", "myservice").unwrap();
+  /// ```
+  pub fn delete_owned<T>(&self, table: T, owner: &str) -> RIPTResult<usize> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let rules = self.list_owned(&table, owner)?;
+    self.delete_rules(&table, &rules)
+  }
+
+  /// Deletes the rules of the table tagged with `owner:id`.
+  /// Returns the number of deleted rules.
+  pub fn delete_owned_id<T>(&self, table: T, owner: &str, id: &str) -> RIPTResult<usize> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let rules: Vec<RIPTRule> = self.list_owned(&table, owner)?.into_iter()
+      .filter(|rule| rule.owner_tag().map(|tag| tag.id == id).unwrap_or(false))
+      .collect();
+    self.delete_rules(&table, &rules)
+  }
+
+  /// Deletes the rules of the table tagged for `owner` whose id is not in `live`.
+  /// Returns the number of deleted rules.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.gc_owned("filter", "myservice", &["web", "lan"]).unwrap();
+  /// ```
+  pub fn gc_owned<T, I>(&self, table: T, owner: &str, live: &[I]) -> RIPTResult<usize> where T: AsRef<OsStr>, I: AsRef<str> {
+    let table = Table::try_from(table.as_ref())?;
+    let rules: Vec<RIPTRule> = self.list_owned(&table, owner)?.into_iter()
+      .filter(|rule| match rule.owner_tag() {
+        Some(tag) => !live.iter().any(|id| id.as_ref() == tag.id),
+        None => false,
+      })
+      .collect();
+    self.delete_rules(&table, &rules)
+  }
+
+  /// Makes the rules of `tables` tagged with `tag` exactly `rules`: the missing ones are added,
+  /// the others deleted. Rules of the `filter` table are inserted first in their chain, where no
+  /// earlier rule drops their packets, the others are appended.
+  /// Returns `true` if every rule is in place.
+  pub(crate) fn reconcile_owned(&self, tag: &OwnerTag, tables: &[&str], rules: &[OwnedRule]) -> RIPTResult<bool> {
+    for table in tables {
+      let table = Table::try_from(*table)?;
+      let stale: Vec<RIPTRule> = self.list_owned(&table, &tag.owner)?.into_iter()
+        .filter(|rule| rule.owner_tag().as_ref() == Some(tag))
+        .filter(|rule| !rules.iter().any(|wanted| rule.chain == wanted.chain && rule.is_spec(&wanted.spec)))
+        .collect();
+      self.delete_rules(&table, &stale)?;
+    }
+
+    let mut added = true;
+    for rule in rules {
+      added &= match rule.table {
+        "filter" => self.insert_unique(rule.table, rule.chain, &rule.spec[..], 1)?,
+        _ => self.append_unique(rule.table, rule.chain, &rule.spec[..])?,
+      };
+    }
+    Ok(added)
+  }
+
+  fn delete_rules(&self, table: &Table, rules: &[RIPTRule]) -> RIPTResult<usize> {
+    let mut deleted = 0;
+    for rule in rules {
+      if self.delete(table, &rule.chain, rule.spec())? {
+        deleted += 1;
+      }
+    }
+    Ok(deleted)
+  }
+}
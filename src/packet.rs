@@ -0,0 +1,631 @@
+//! Offline evaluation of packets against the rules of a table.
+//!
+//! A `Packet` describes what is known about some traffic. Fields left to `None` are unknown: rules
+//! testing them, like rules using matches this module does not model (`limit`, `recent`...), may or
+//! may not match. Such rules are skipped as non-matching and reported in `Evaluation::uncertain`.
+//!
+//! ```rust
+//! use riptables::packet::{self, Packet, Verdict};
+//! use riptables::ruleset::Ruleset;
+//!
+//! let ruleset = Ruleset::parse("*filter
+//! :INPUT DROP
+//! -A INPUT -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+//! -A INPUT -s 10.0.0.0/8 -p tcp --dport 22 -j ACCEPT
+//! COMMIT
+//! ").unwrap();
+//! let filter = ruleset.table("filter").unwrap();
+//!
+//! let ssh = Packet::new("tcp").source("10.1.2.3".parse().unwrap()).dport(22).state("NEW");
+//! assert_eq!(packet::evaluate(filter, "INPUT", &ssh).verdict, Verdict::Accept);
+//! let ssh = ssh.source("192.168.1.1".parse().unwrap());
+//! assert_eq!(packet::evaluate(filter, "INPUT", &ssh).verdict, Verdict::Drop);
+//! ```
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::error::RIPTResult;
+use crate::iptparser;
+use crate::plan;
+use crate::rule::RIPTRule;
+use crate::ruleset::{Ruleset, RulesetTable};
+use crate::services::Services;
+use crate::RIPTables;
+
+/// Targets ending the traversal of a table.
+const TERMINATING_TARGETS: &[&str] = &[
+  "ACCEPT", "DROP", "REJECT", "QUEUE", "NFQUEUE", "DNAT", "SNAT", "MASQUERADE", "REDIRECT", "NETMAP", "TPROXY",
+];
+
+/// Terminating targets letting the packet continue to the next table.
+const NAT_TARGETS: &[&str] = &["DNAT", "SNAT", "MASQUERADE", "REDIRECT", "NETMAP"];
+
+/// Tables traversed by `RIPTables::trace`.
+pub(crate) const TRACED_TABLES: &[&str] = &["raw", "mangle", "nat", "filter", "security"];
+
+/// Nested jumps followed before giving up, iptables itself refuses loops.
+const MAX_DEPTH: usize = 64;
+
+/// What is known about a packet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Packet {
+  pub source: Option<IpAddr>,
+  pub destination: Option<IpAddr>,
+  /// Protocol name as given to `-p`, e.g. `"tcp"`.
+  pub protocol: Option<String>,
+  pub sport: Option<u16>,
+  pub dport: Option<u16>,
+  pub in_interface: Option<String>,
+  pub out_interface: Option<String>,
+  /// Connection tracking state, e.g. `"NEW"` or `"ESTABLISHED"`.
+  pub state: Option<String>,
+}
+
+impl Packet {
+  pub fn new<S: ToString>(protocol: S) -> Packet {
+    Packet { protocol: Some(protocol.to_string().to_lowercase()), ..Packet::default() }
+  }
+
+  pub fn source(mut self, source: IpAddr) -> Packet {
+    self.source = Some(source);
+    self
+  }
+
+  pub fn destination(mut self, destination: IpAddr) -> Packet {
+    self.destination = Some(destination);
+    self
+  }
+
+  pub fn sport(mut self, port: u16) -> Packet {
+    self.sport = Some(port);
+    self
+  }
+
+  pub fn dport(mut self, port: u16) -> Packet {
+    self.dport = Some(port);
+    self
+  }
+
+  pub fn in_interface<S: ToString>(mut self, interface: S) -> Packet {
+    self.in_interface = Some(interface.to_string());
+    self
+  }
+
+  pub fn out_interface<S: ToString>(mut self, interface: S) -> Packet {
+    self.out_interface = Some(interface.to_string());
+    self
+  }
+
+  pub fn state<S: ToString>(mut self, state: S) -> Packet {
+    self.state = Some(state.to_string().to_uppercase());
+    self
+  }
+
+  /// The packet answering this one: addresses, ports and interfaces swapped, state `ESTABLISHED`.
+  pub fn reply(&self) -> Packet {
+    Packet {
+      source: self.destination,
+      destination: self.source,
+      protocol: self.protocol.clone(),
+      sport: self.dport,
+      dport: self.sport,
+      in_interface: self.out_interface.clone(),
+      out_interface: self.in_interface.clone(),
+      state: Some("ESTABLISHED".to_string()),
+    }
+  }
+}
+
+/// Whether a rule matches a packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Match {
+  Yes,
+  No,
+  /// Depends on something the packet does not tell.
+  Maybe,
+}
+
+impl Match {
+  fn from_bool(matched: bool) -> Match {
+    if matched { Match::Yes } else { Match::No }
+  }
+
+  fn and(self, other: Match) -> Match {
+    match (self, other) {
+      (Match::No, _) | (_, Match::No) => Match::No,
+      (Match::Yes, Match::Yes) => Match::Yes,
+      _ => Match::Maybe,
+    }
+  }
+
+  fn negate(self, negate: bool) -> Match {
+    match (self, negate) {
+      (Match::Yes, true) => Match::No,
+      (Match::No, true) => Match::Yes,
+      (matched, _) => matched,
+    }
+  }
+}
+
+/// The fate of a packet at the end of a table traversal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+  Accept,
+  Drop,
+  Reject,
+  /// The end of a user-defined chain was reached, or `RETURN` taken, without a decision.
+  Return,
+  /// Another terminating target, e.g. `DNAT` or `NFQUEUE`.
+  Other(String),
+  /// Jumps nested too deep to follow.
+  Loop,
+}
+
+impl fmt::Display for Verdict {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Verdict::Accept => write!(f, "ACCEPT"),
+      Verdict::Drop => write!(f, "DROP"),
+      Verdict::Reject => write!(f, "REJECT"),
+      Verdict::Return => write!(f, "RETURN"),
+      Verdict::Other(ref target) => write!(f, "{}", target),
+      Verdict::Loop => write!(f, "LOOP"),
+    }
+  }
+}
+
+/// A rule of a chain, by 1-based position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleRef {
+  pub chain: String,
+  pub position: usize,
+  pub spec: String,
+}
+
+/// The result of `evaluate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+  pub verdict: Verdict,
+  /// The rule deciding the verdict, `None` when the policy of the chain did.
+  pub rule: Option<RuleRef>,
+  /// Rules which may match the packet and would change its path, skipped as non-matching.
+  pub uncertain: Vec<RuleRef>,
+  /// Every rule matching the packet on its way, in order, `rule` included.
+  pub hits: Vec<RuleRef>,
+}
+
+/// The way of a packet through the host, decided by routing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Path {
+  /// Addressed to the host: `PREROUTING`, `INPUT`.
+  Input,
+  /// Routed through the host: `PREROUTING`, `FORWARD`, `POSTROUTING`.
+  Forward,
+  /// Sent by the host: `OUTPUT`, `POSTROUTING`.
+  Output,
+}
+
+impl Path {
+  /// The built-in chains on the path, in order.
+  pub fn hooks(self) -> &'static [&'static str] {
+    match self {
+      Path::Input => &["PREROUTING", "INPUT"],
+      Path::Forward => &["PREROUTING", "FORWARD", "POSTROUTING"],
+      Path::Output => &["OUTPUT", "POSTROUTING"],
+    }
+  }
+}
+
+/// The traversal of one built-in chain of a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep {
+  pub table: String,
+  pub chain: String,
+  pub evaluation: Evaluation,
+}
+
+/// The result of `trace`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+  /// `Accept` if the packet made it through every step.
+  pub verdict: Verdict,
+  pub steps: Vec<TraceStep>,
+  /// The packet after the last step, addresses translated.
+  pub packet: Packet,
+}
+
+impl Trace {
+  /// Every rule hit, with its table, in order.
+  pub fn hits(&self) -> Vec<(&str, &RuleRef)> {
+    self.steps.iter()
+      .flat_map(|step| step.evaluation.hits.iter().map(move |rule| (&step.table[..], rule)))
+      .collect()
+  }
+
+  /// Returns `true` if rules the evaluator cannot decide may change the outcome.
+  pub fn is_uncertain(&self) -> bool {
+    self.steps.iter().any(|step| !step.evaluation.uncertain.is_empty())
+  }
+}
+
+impl fmt::Display for Trace {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for step in &self.steps {
+      writeln!(f, "{} {}: {}", step.table, step.chain, step.evaluation.verdict)?;
+      for rule in &step.evaluation.hits {
+        writeln!(f, "  -A {} {}", rule.chain, rule.spec)?;
+      }
+      for rule in &step.evaluation.uncertain {
+        writeln!(f, "  ? -A {} {}", rule.chain, rule.spec)?;
+      }
+    }
+    write!(f, "verdict: {}", self.verdict)
+  }
+}
+
+/// Tells whether `rule` matches `packet`.
+pub fn matches(rule: &RIPTRule, packet: &Packet) -> Match {
+  self::matches_spec(&rule.spec(), packet)
+}
+
+/// Tells whether the rule specification `spec` matches `packet`.
+pub fn matches_spec(spec: &str, packet: &Packet) -> Match {
+  let mut matched = Match::Yes;
+  for group in iptparser::spec_groups(spec) {
+    let values = &group.values;
+    let option = match &group.option[..] {
+      // the target and its options
+      "-j" | "-g" => break,
+      // modules only matter through their options
+      "-m" => continue,
+      "-s" => self::match_address(values, packet.source),
+      "-d" => self::match_address(values, packet.destination),
+      "-i" => self::match_interface(values, &packet.in_interface),
+      "-o" => self::match_interface(values, &packet.out_interface),
+      "-p" => self::match_protocol(values, &packet.protocol),
+      // unfragmented packets only
+      "-f" => Match::No,
+      "--sport" | "--sports" => self::match_ports(values, packet.sport),
+      "--dport" | "--dports" => self::match_ports(values, packet.dport),
+      "--ports" => match (self::match_ports(values, packet.sport), self::match_ports(values, packet.dport)) {
+        (Match::Yes, _) | (_, Match::Yes) => Match::Yes,
+        (Match::No, Match::No) => Match::No,
+        _ => Match::Maybe,
+      },
+      "--ctstate" | "--state" => self::match_state(values, &packet.state),
+      "--comment" => Match::Yes,
+      _ => Match::Maybe,
+    };
+    matched = matched.and(option.negate(group.negate));
+    if matched == Match::No {
+      break;
+    }
+  }
+  matched
+}
+
+/// Follows `packet` through `chain` of `table` and the chains it jumps to, the way the kernel does.
+pub fn evaluate(table: &RulesetTable, chain: &str, packet: &Packet) -> Evaluation {
+  let mut uncertain = vec![];
+  let mut hits = vec![];
+  // (chain, index of the next rule)
+  let mut frames: Vec<(&str, usize)> = vec![(chain, 0)];
+
+  while let Some(&(current, index)) = frames.last() {
+    let rules = table.chain(current).map(|chain| &chain.rules[..]).unwrap_or(&[]);
+    if index >= rules.len() {
+      frames.pop();
+      continue;
+    }
+    if let Some(frame) = frames.last_mut() {
+      frame.1 += 1;
+    }
+    let rule = &rules[index];
+    let rule_ref = || RuleRef { chain: current.to_string(), position: index + 1, spec: rule.spec() };
+    let (target, goto) = if rule.goto.is_empty() { (&rule.jump[..], false) } else { (&rule.goto[..], true) };
+    let target_chain = table.chain(target);
+
+    match self::matches(rule, packet) {
+      Match::No => continue,
+      Match::Maybe => {
+        if target_chain.is_some() || target == "RETURN" || TERMINATING_TARGETS.contains(&target) {
+          uncertain.push(rule_ref());
+        }
+        continue;
+      }
+      Match::Yes => hits.push(rule_ref()),
+    }
+
+    if let Some(target_chain) = target_chain {
+      // processing continues in the caller of a chain left by a goto
+      if goto {
+        frames.pop();
+      }
+      if frames.len() >= MAX_DEPTH {
+        return Evaluation { verdict: Verdict::Loop, rule: Some(rule_ref()), uncertain, hits };
+      }
+      frames.push((&target_chain.name, 0));
+      continue;
+    }
+    let verdict = match target {
+      "RETURN" => {
+        frames.pop();
+        continue;
+      }
+      "ACCEPT" => Verdict::Accept,
+      "DROP" => Verdict::Drop,
+      "REJECT" => Verdict::Reject,
+      target if TERMINATING_TARGETS.contains(&target) => Verdict::Other(target.to_string()),
+      // LOG, MARK... and rules without target
+      _ => continue,
+    };
+    return Evaluation { verdict, rule: Some(rule_ref()), uncertain, hits };
+  }
+  Evaluation { verdict: self::policy(table, chain), rule: None, uncertain, hits }
+}
+
+/// Follows `packet` through the built-in chains of every table of `ruleset` on `path`, in the order
+/// netfilter traverses them. `DNAT`, `SNAT` and `REDIRECT` rewrite the packet for the next steps.
+/// Tables missing from `ruleset` are skipped, and `nat` is only traversed by `NEW` packets.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::packet::{self, Packet, Path, Verdict};
+/// use riptables::ruleset::Ruleset;
+///
+/// let ruleset = Ruleset::parse("*nat
+/// :PREROUTING ACCEPT
+/// -A PREROUTING -p tcp --dport 443 -j DNAT --to-destination 10.0.0.2:8443
+/// COMMIT
+/// *filter
+/// :FORWARD DROP
+/// -A FORWARD -d 10.0.0.2 -p tcp --dport 8443 -j ACCEPT
+/// COMMIT
+/// ").unwrap();
+/// let https = Packet::new("tcp").source("203.0.113.9".parse().unwrap()).dport(443).in_interface("eth0").state("NEW");
+/// let trace = packet::trace(&ruleset, Path::Forward, &https);
+/// assert_eq!(trace.verdict, Verdict::Accept);
+/// assert_eq!(trace.packet.dport, Some(8443));
+/// println!("{}", trace);
+/// ```
+pub fn trace(ruleset: &Ruleset, path: Path, packet: &Packet) -> Trace {
+  let mut packet = packet.clone();
+  let mut steps = vec![];
+  for hook in path.hooks() {
+    for name in self::hook_tables(hook) {
+      let first = packet.state.as_ref().map(|state| state == "NEW").unwrap_or(true);
+      if *name == "nat" && !first {
+        continue;
+      }
+      let table = match ruleset.table(name) {
+        Some(table) if table.chain(hook).is_some() => table,
+        _ => continue,
+      };
+      let evaluation = self::evaluate(table, hook, &packet);
+      let verdict = evaluation.verdict.clone();
+      if let (Verdict::Other(ref target), Some(ref rule)) = (&verdict, &evaluation.rule) {
+        self::translate(&mut packet, target, &rule.spec);
+      }
+      steps.push(TraceStep { table: name.to_string(), chain: hook.to_string(), evaluation });
+      match verdict {
+        Verdict::Accept | Verdict::Return => {}
+        Verdict::Other(ref target) if NAT_TARGETS.contains(&&target[..]) => {}
+        verdict => return Trace { verdict, steps, packet },
+      }
+    }
+  }
+  Trace { verdict: Verdict::Accept, steps, packet }
+}
+
+/// Applies the address translation of a `DNAT`, `SNAT`, `MASQUERADE` or `REDIRECT` rule.
+/// Addresses chosen by the kernel become unknown.
+fn translate(packet: &mut Packet, target: &str, spec: &str) {
+  for group in iptparser::spec_groups(spec) {
+    let value = match group.values.first() {
+      Some(value) => value,
+      None => continue,
+    };
+    match (target, &group.option[..]) {
+      ("DNAT", "--to-destination") => {
+        let (address, port) = self::nat_address(value);
+        packet.destination = address;
+        packet.dport = port.or(packet.dport);
+      }
+      ("SNAT", "--to-source") => {
+        let (address, port) = self::nat_address(value);
+        packet.source = address;
+        packet.sport = port.or(packet.sport);
+      }
+      ("REDIRECT", "--to-ports") | ("MASQUERADE", "--to-ports") => {
+        let port = value.split(['-', ':']).next().and_then(|port| port.parse().ok());
+        if target == "REDIRECT" {
+          packet.dport = port;
+        } else {
+          packet.sport = port;
+        }
+      }
+      _ => {}
+    }
+  }
+  match target {
+    "MASQUERADE" => packet.source = None,
+    "REDIRECT" => packet.destination = None,
+    _ => {}
+  }
+}
+
+/// Splits `address[-address][:port[-port]]` (IPv6 addresses in brackets), keeping the first of
+/// each range.
+fn nat_address(value: &str) -> (Option<IpAddr>, Option<u16>) {
+  let (address, port) = match value.strip_prefix('[') {
+    Some(rest) => match rest.split_once(']') {
+      Some((address, port)) => (address, port.strip_prefix(':')),
+      None => (rest, None),
+    },
+    None if value.matches(':').count() == 1 => match value.split_once(':') {
+      Some((address, port)) => (address, Some(port)),
+      None => (value, None),
+    },
+    None => (value, None),
+  };
+  let address = address.split('-').next().and_then(|address| address.parse().ok());
+  let port = port.and_then(|port| port.split('-').next()).and_then(|port| port.parse().ok());
+  (address, port)
+}
+
+/// Tables of a hook, in the order netfilter traverses them.
+fn hook_tables(hook: &str) -> &'static [&'static str] {
+  match hook {
+    "PREROUTING" => &["raw", "mangle", "nat"],
+    "INPUT" => &["mangle", "filter", "security", "nat"],
+    "FORWARD" => &["mangle", "filter", "security"],
+    "OUTPUT" => &["raw", "mangle", "nat", "filter", "security"],
+    "POSTROUTING" => &["mangle", "nat"],
+    _ => &[],
+  }
+}
+
+/// Verdict when the end of `chain` is reached.
+fn policy(table: &RulesetTable, chain: &str) -> Verdict {
+  let builtin = crate::builtin_chains(&table.name[..]).map(|chains| chains.contains(&chain)).unwrap_or(false);
+  match table.chain(chain).and_then(|chain| chain.policy.as_ref()) {
+    Some(policy) if policy == "DROP" => Verdict::Drop,
+    Some(policy) if policy == "ACCEPT" => Verdict::Accept,
+    Some(policy) => Verdict::Other(policy.clone()),
+    None if builtin => Verdict::Accept,
+    None => Verdict::Return,
+  }
+}
+
+pub(crate) fn match_address(values: &[String], address: Option<IpAddr>) -> Match {
+  let address = match address {
+    Some(address) => address,
+    None => return Match::Maybe,
+  };
+  let mut matched = Match::No;
+  for network in values.iter().flat_map(|value| value.split(',')) {
+    matched = match (matched, self::in_network(network, address)) {
+      (Match::Yes, _) | (_, Match::Yes) => Match::Yes,
+      (Match::Maybe, _) | (_, Match::Maybe) => Match::Maybe,
+      _ => Match::No,
+    };
+  }
+  matched
+}
+
+/// Tells whether `address` is in `network`, written `address[/prefix]` or `address/mask`.
+/// Host names cannot be resolved here and may match.
+fn in_network(network: &str, address: IpAddr) -> Match {
+  let (base, prefix) = match network.split_once('/') {
+    Some((base, prefix)) => (base, Some(prefix)),
+    None => (network, None),
+  };
+  let base: IpAddr = match base.parse() {
+    Ok(base) => base,
+    Err(_) => return Match::Maybe,
+  };
+  let (base, address, bits) = match (base, address) {
+    (IpAddr::V4(base), IpAddr::V4(address)) => (u32::from(base) as u128, u32::from(address) as u128, 32),
+    (IpAddr::V6(base), IpAddr::V6(address)) => (u128::from(base), u128::from(address), 128),
+    _ => return Match::No,
+  };
+  let mask: u128 = match prefix {
+    None => u128::MAX,
+    Some(prefix) => match (prefix.parse::<u32>(), prefix.parse::<IpAddr>()) {
+      (Ok(length), _) if length <= bits => if length == 0 { 0 } else { u128::MAX << (128 - length) },
+      (_, Ok(IpAddr::V4(mask))) if bits == 32 => (u32::from(mask) as u128) << 96,
+      (_, Ok(IpAddr::V6(mask))) if bits == 128 => u128::from(mask),
+      _ => return Match::Maybe,
+    },
+  };
+  let shift = 128 - bits;
+  Match::from_bool((base << shift) & mask == (address << shift) & mask)
+}
+
+pub(crate) fn match_interface(values: &[String], interface: &Option<String>) -> Match {
+  let (pattern, interface) = match (values.first(), interface) {
+    (Some(pattern), Some(interface)) => (pattern, interface),
+    _ => return Match::Maybe,
+  };
+  match pattern.strip_suffix('+') {
+    Some(prefix) => Match::from_bool(interface.starts_with(prefix)),
+    None => Match::from_bool(interface == pattern),
+  }
+}
+
+pub(crate) fn match_protocol(values: &[String], protocol: &Option<String>) -> Match {
+  let (expected, protocol) = match (values.first(), protocol) {
+    (Some(expected), _) if expected.eq_ignore_ascii_case("all") || expected == "0" => return Match::Yes,
+    (Some(expected), Some(protocol)) => (expected.to_lowercase(), protocol),
+    _ => return Match::Maybe,
+  };
+  let number = |name: &str| match name {
+    "icmp" => "1",
+    "tcp" => "6",
+    "udp" => "17",
+    "ipv6-icmp" | "icmpv6" => "58",
+    "sctp" => "132",
+    _ => "",
+  }.to_string();
+  let services = Services::system();
+  Match::from_bool(
+    expected == *protocol || number(&expected) == *protocol || expected == number(protocol)
+      || services.protocol_name(&expected) == services.protocol_name(protocol)
+  )
+}
+
+pub(crate) fn match_ports(values: &[String], port: Option<u16>) -> Match {
+  let port = match port {
+    Some(port) => port,
+    None => return Match::Maybe,
+  };
+  let mut matched = Match::No;
+  let values: Vec<String> = values.iter().map(|value| Services::system().ports(value, None)).collect();
+  for range in values.iter().flat_map(|value| value.split(',')) {
+    let (first, last) = match range.split_once(':') {
+      Some((first, last)) => (first, last),
+      None => (range, range),
+    };
+    let first = if first.is_empty() { Ok(0) } else { first.parse::<u16>() };
+    let last = if last.is_empty() { Ok(u16::MAX) } else { last.parse::<u16>() };
+    match (first, last) {
+      (Ok(first), Ok(last)) if first <= port && port <= last => return Match::Yes,
+      (Ok(_), Ok(_)) => {}
+      // service names
+      _ => matched = Match::Maybe,
+    }
+  }
+  matched
+}
+
+fn match_state(values: &[String], state: &Option<String>) -> Match {
+  match state {
+    Some(state) => Match::from_bool(values.iter().flat_map(|value| value.split(',')).any(|value| value.eq_ignore_ascii_case(state))),
+    None => Match::Maybe,
+  }
+}
+
+impl RIPTables {
+  /// Traces `packet` through the current tables, see `trace`.
+  /// Tables the kernel does not provide are skipped.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::packet::{Packet, Path};
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let https = Packet::new("tcp").source("10.0.0.5".parse().unwrap()).dport(443).in_interface("eth0").state("NEW");
+  /// println!("{}", iptables.trace(Path::Input, &https).unwrap());
+  /// ```
+  pub fn trace(&self, path: Path, packet: &Packet) -> RIPTResult<Trace> {
+    let mut rules = vec![];
+    for table in plan::loaded(TRACED_TABLES, &self.loaded_tables()?) {
+      rules.extend(self.list(OsStr::new(table))?);
+    }
+    Ok(self::trace(&Ruleset::from_rules(rules), path, packet))
+  }
+}
@@ -0,0 +1,210 @@
+//! Finding what IPv4 and IPv6 tables do differently.
+//!
+//! Rules are compared in a family-neutral form: addresses are replaced by a placeholder and ICMP
+//! matches, types and reject messages are written the same way for both families. A rule is missing
+//! from a family when the chain there has fewer rules of the same neutral form; the order of rules
+//! within a chain is not compared.
+//!
+//! ```rust
+//! use riptables::dualstack::Family;
+//! use riptables::parity::{self, Difference};
+//! use riptables::ruleset::Ruleset;
+//!
+//! let v4 = Ruleset::parse("*filter
+//! :INPUT DROP
+//! -A INPUT -s 10.0.0.0/8 -p tcp -m tcp --dport 22 -j ACCEPT
+//! -A INPUT -p tcp -m tcp --dport 443 -j ACCEPT
+//! COMMIT
+//! ").unwrap();
+//! let v6 = Ruleset::parse("*filter
+//! :INPUT DROP
+//! -A INPUT -s fd00::/8 -p tcp -m tcp --dport 22 -j ACCEPT
+//! COMMIT
+//! ").unwrap();
+//! let differences = parity::compare(&v4, &v6);
+//! assert_eq!(differences.len(), 1);
+//! assert!(matches!(differences[0], Difference::Rule { missing_in: Family::V6, position: 2, .. }));
+//! println!("{}", differences[0]);
+//! ```
+
+use std::ffi::OsStr;
+use std::fmt;
+
+use crate::dualstack::{self, DualStack, Family};
+use crate::error::RIPTResult;
+use crate::iptparser;
+use crate::ruleset::{Ruleset, RulesetChain, RulesetTable};
+
+/// Stands for any address in the neutral form of a rule.
+const ADDRESS: &str = "<address>";
+
+/// Something one family has and the other has not.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+  /// The chain only exists in the other family.
+  Chain { table: String, chain: String, missing_in: Family },
+  /// The built-in chain has different policies.
+  Policy { table: String, chain: String, v4: Option<String>, v6: Option<String> },
+  /// The rule at `position` of the other family has no counterpart in `missing_in`.
+  Rule { table: String, chain: String, missing_in: Family, position: usize, spec: String },
+}
+
+impl fmt::Display for Difference {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let policy = |policy: &Option<String>| policy.clone().unwrap_or_else(|| "-".to_string());
+    match self {
+      Difference::Chain { table, chain, missing_in } => write!(f, "{} {}: chain missing in {}", table, chain, missing_in),
+      Difference::Policy { table, chain, v4, v6 } => {
+        write!(f, "{} {}: policy {} in IPv4, {} in IPv6", table, chain, policy(v4), policy(v6))
+      }
+      Difference::Rule { table, chain, missing_in, position, spec } => {
+        let other = match missing_in {
+          Family::V4 => Family::V6,
+          Family::V6 => Family::V4,
+        };
+        write!(f, "{} {}: rule {} of {} missing in {}: {}", table, chain, position, other, missing_in, spec)
+      }
+    }
+  }
+}
+
+/// Rewrites `spec` without what differs between the families, see the module documentation.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::parity;
+///
+/// assert_eq!(
+///   parity::neutral("-s 10.0.0.0/8 -p icmp -m icmp --icmp-type 8 -j ACCEPT"),
+///   parity::neutral("-s fd00::/8 -p ipv6-icmp -m icmp6 --icmpv6-type 128 -j ACCEPT"),
+/// );
+/// ```
+pub fn neutral<S: AsRef<OsStr>>(spec: S) -> String {
+  let mut groups = iptparser::spec_groups(spec);
+  for group in groups.iter_mut() {
+    match &group.option[..] {
+      "-p" => group.values.iter_mut().for_each(|value| {
+        if ["1", "icmp", "58", "icmpv6", "ipv6-icmp"].contains(&&value.to_lowercase()[..]) {
+          *value = "icmp".to_string();
+        }
+      }),
+      "-m" => group.values.iter_mut().filter(|value| *value == "icmp6").for_each(|value| *value = "icmp".to_string()),
+      "--icmp-type" | "--icmpv6-type" => {
+        let v6 = group.option == "--icmpv6-type";
+        group.option = "--icmp-type".to_string();
+        group.values.iter_mut().for_each(|value| *value = self::icmp_type(value, v6));
+      }
+      "--reject-with" => group.values.iter_mut().for_each(|value| *value = self::reject_with(value)),
+      "--comment" | "--log-prefix" | "--nflog-prefix" => {}
+      _ => group.values.iter_mut().for_each(|value| {
+        if value.split([',', '-']).all(|part| dualstack::address(part).is_some()) {
+          *value = ADDRESS.to_string();
+        }
+      }),
+    }
+  }
+  iptparser::normalize_spec(iptparser::render_groups(&groups))
+}
+
+/// Names ICMP types with the same meaning in both families.
+fn icmp_type(value: &str, v6: bool) -> String {
+  let name = match (v6, value) {
+    (false, "0") | (true, "129") => "echo-reply",
+    (false, "3") | (true, "1") => "destination-unreachable",
+    (false, "8") | (true, "128") => "echo-request",
+    (false, "11") | (true, "3") => "time-exceeded",
+    (false, "12") | (true, "4") => "parameter-problem",
+    (true, "2") => "packet-too-big",
+    _ => value,
+  };
+  name.to_string()
+}
+
+/// Maps ICMPv6 reject messages to their ICMP counterpart.
+fn reject_with(value: &str) -> String {
+  let value = match value {
+    "icmp6-no-route" | "no-route" => "icmp-net-unreachable",
+    "icmp6-addr-unreachable" | "addr-unreach" => "icmp-host-unreachable",
+    "icmp6-port-unreachable" | "port-unreach" => "icmp-port-unreachable",
+    "icmp6-adm-prohibited" | "adm-prohibited" | "icmp-host-prohibited" | "icmp-net-prohibited" => "icmp-admin-prohibited",
+    _ => value,
+  };
+  value.to_string()
+}
+
+/// Compares the tables of an IPv4 and an IPv6 ruleset. A table missing from one ruleset counts
+/// as a table without chains.
+pub fn compare(v4: &Ruleset, v6: &Ruleset) -> Vec<Difference> {
+  let empty = |name: &str| RulesetTable { name: name.to_string(), chains: vec![] };
+  let mut names: Vec<&str> = v4.tables.iter().map(|table| &table.name[..]).collect();
+  names.extend(v6.tables.iter().map(|table| &table.name[..]).filter(|name| v4.table(name).is_none()));
+
+  let mut differences = vec![];
+  for name in names {
+    let (v4_empty, v6_empty) = (empty(name), empty(name));
+    let v4 = v4.table(name).unwrap_or(&v4_empty);
+    let v6 = v6.table(name).unwrap_or(&v6_empty);
+    let mut chains: Vec<&str> = v4.chains.iter().map(|chain| &chain.name[..]).collect();
+    chains.extend(v6.chains.iter().map(|chain| &chain.name[..]).filter(|chain| v4.chain(chain).is_none()));
+    for chain in chains {
+      let difference = |missing_in| Difference::Chain { table: name.to_string(), chain: chain.to_string(), missing_in };
+      match (v4.chain(chain), v6.chain(chain)) {
+        (Some(v4), Some(v6)) => self::compare_chain(name, v4, v6, &mut differences),
+        (Some(_), None) => differences.push(difference(Family::V6)),
+        (None, _) => differences.push(difference(Family::V4)),
+      }
+    }
+  }
+  differences
+}
+
+fn compare_chain(table: &str, v4: &RulesetChain, v6: &RulesetChain, differences: &mut Vec<Difference>) {
+  if v4.policy != v6.policy {
+    differences.push(Difference::Policy {
+      table: table.to_string(),
+      chain: v4.name.clone(),
+      v4: v4.policy.clone(),
+      v6: v6.policy.clone(),
+    });
+  }
+
+  let v4_specs: Vec<String> = v4.rules.iter().map(|rule| self::neutral(rule.spec())).collect();
+  let v6_specs: Vec<String> = v6.rules.iter().map(|rule| self::neutral(rule.spec())).collect();
+  let mut matched = vec![false; v6_specs.len()];
+  let mut missing = vec![];
+  for (index, spec) in v4_specs.iter().enumerate() {
+    match (0..v6_specs.len()).find(|&other| !matched[other] && v6_specs[other] == *spec) {
+      Some(other) => matched[other] = true,
+      None => missing.push((Family::V6, index, v4.rules[index].spec())),
+    }
+  }
+  for (index, _) in matched.iter().enumerate().filter(|(_, matched)| !**matched) {
+    missing.push((Family::V4, index, v6.rules[index].spec()));
+  }
+  for (missing_in, index, spec) in missing {
+    differences.push(Difference::Rule { table: table.to_string(), chain: v4.name.clone(), missing_in, position: index + 1, spec });
+  }
+}
+
+impl DualStack {
+  /// Lists `tables` in both families and compares them, see `compare`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let dual = riptables::dualstack::new().unwrap();
+  /// for difference in dual.parity(&["filter", "nat"]).unwrap() {
+  ///   println!("{}", difference);
+  /// }
+  /// ```
+  pub fn parity<S>(&self, tables: &[S]) -> RIPTResult<Vec<Difference>> where S: AsRef<OsStr> + Clone {
+    let mut v4 = vec![];
+    let mut v6 = vec![];
+    for table in tables {
+      v4.extend(self.v4.list(table.clone())?);
+      v6.extend(self.v6.list(table.clone())?);
+    }
+    Ok(self::compare(&Ruleset::from_rules(v4), &Ruleset::from_rules(v6)))
+  }
+}
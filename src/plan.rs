@@ -0,0 +1,142 @@
+//! What to run, decided from what was read.
+//!
+//! `RIPTables` and `AsyncRIPTables` only read the tables and run what these functions plan, so
+//! both behave the same.
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::graph::ChainGraph;
+use crate::rule::{Archive, RIPTRule};
+use crate::ruleset::{self, Change, Ruleset, RulesetChain, RulesetTable};
+use crate::table::Table;
+
+/// The rule of `ensure_jump`, jumping to `to_chain` for packets matching `extra_match`.
+pub(crate) fn jump_spec(extra_match: &str, to_chain: &str) -> String {
+  format!("{} -j {}", extra_match, to_chain).trim().to_string()
+}
+
+/// The 1-based position `ensure_jump` puts its rule at.
+pub(crate) fn jump_position(position: i32) -> usize {
+  position.max(1) as usize
+}
+
+/// Returns `None` when `spec` is in the `rules` of a chain exactly once at `position`, otherwise
+/// the positions of its copies to delete, last first.
+pub(crate) fn misplaced_jumps(rules: &[RIPTRule], spec: &str, position: usize) -> Option<Vec<usize>> {
+  let rules: Vec<&RIPTRule> = rules.iter().filter(|rule| rule.archive == Archive::Append).collect();
+  let found: Vec<usize> = rules.iter().enumerate()
+    .filter(|(_, rule)| rule.is_spec(spec))
+    .map(|(index, _)| index + 1)
+    .collect();
+  if found.len() == 1 && found[0] == position.min(rules.len()) {
+    return None;
+  }
+  Some(found.into_iter().rev().collect())
+}
+
+/// Returns `true` if a rule meant for `position` is appended to a chain listed as `rules`.
+pub(crate) fn appends_at(rules: &[RIPTRule], position: usize) -> bool {
+  position > rules.iter().filter(|rule| rule.archive == Archive::Append).count()
+}
+
+/// The chains `delete_chain_recursive` deletes and the rules of other chains referencing them.
+pub(crate) struct ChainRemoval {
+  pub table: String,
+  pub doomed: Vec<String>,
+  pub references: Vec<RIPTRule>,
+}
+
+impl ChainRemoval {
+  /// Plans deleting `chain` of `table`, listed as `rules`, and with `children` the chains only
+  /// reachable from it. Returns `None` when the table has no such chain.
+  pub(crate) fn new(table: &str, chain: &str, rules: &[RIPTRule], children: bool) -> RIPTResult<Option<ChainRemoval>> {
+    let graph = ChainGraph::from_rules(table, rules);
+    if !graph.chains().iter().any(|name| name == chain) {
+      return Ok(None);
+    }
+    if graph.is_builtin(chain) {
+      return Err(RIPTError::Other("given chain is a built-in chain, it can't be deleted"));
+    }
+
+    let mut doomed = vec![chain.to_string()];
+    if children {
+      // A child goes too once every chain referencing it goes.
+      let mut changed = true;
+      while changed {
+        changed = false;
+        for candidate in graph.reachable(chain) {
+          if !doomed.contains(&candidate) && !graph.is_builtin(&candidate)
+            && graph.referrers(&candidate).iter().all(|edge| doomed.contains(&edge.from)) {
+            doomed.push(candidate);
+            changed = true;
+          }
+        }
+      }
+    }
+
+    let references = rules.iter()
+      .filter(|rule| rule.archive == Archive::Append && !doomed.contains(&rule.chain))
+      .filter(|rule| doomed.contains(&rule.jump) || doomed.contains(&rule.goto))
+      .cloned()
+      .collect();
+    Ok(Some(ChainRemoval { table: table.to_string(), doomed, references }))
+  }
+
+  /// The `iptables-restore --noflush` input removing everything at once.
+  pub(crate) fn script(&self) -> String {
+    let mut lines = vec![format!("*{}", self.table)];
+    lines.extend(self.references.iter().map(|rule| format!("-D {} {}", rule.chain, rule.spec())));
+    lines.extend(self.doomed.iter().map(|name| format!("-F {}", name)));
+    lines.extend(self.doomed.iter().map(|name| format!("-X {}", name)));
+    lines.push("COMMIT".to_string());
+    lines.join("\n") + "\n"
+  }
+}
+
+/// The tables `apply` saves before comparing them with `desired`.
+pub(crate) fn applied_tables(desired: &Ruleset) -> Vec<&str> {
+  desired.tables.iter().map(|table| &table.name[..]).collect()
+}
+
+/// The changes `apply` makes to `current`, with the `iptables-restore` input making them unless
+/// there are none or `dry_run` is set.
+pub(crate) fn apply(current: &Ruleset, desired: &Ruleset, dry_run: bool) -> (Vec<Change>, Option<String>) {
+  let changes = ruleset::diff(current, desired);
+  if dry_run || changes.is_empty() {
+    return (changes, None);
+  }
+  (changes, Some(desired.to_string()))
+}
+
+/// The user-defined chains of `table`, which `append_ruleset` creates when missing.
+pub(crate) fn user_chains(table: &RulesetTable) -> impl Iterator<Item = &RulesetChain> {
+  let builtins = crate::builtin_chains(&table.name[..]).unwrap_or(&[]);
+  table.chains.iter().filter(move |chain| !builtins.contains(&&chain.name[..]))
+}
+
+/// Of `tables`, those the kernel lists in `loaded`, or all of them when it lists none.
+pub(crate) fn loaded<'a>(tables: &[&'a str], loaded: &[Table]) -> Vec<&'a str> {
+  tables.iter()
+    .filter(|table| loaded.is_empty() || loaded.iter().any(|loaded| loaded.name() == **table))
+    .cloned()
+    .collect()
+}
+
+/// The built-in chains of a table, listed as `rules`, for tables `Table` does not know.
+pub(crate) fn policy_chains(rules: Vec<RIPTRule>) -> Vec<String> {
+  rules.into_iter()
+    .filter(|rule| rule.archive == Archive::Policy)
+    .map(|rule| rule.chain)
+    .collect()
+}
+
+/// The arguments of `iptables-restore`.
+pub(crate) fn restore_args(noflush: bool, has_wait: bool) -> Vec<&'static str> {
+  let mut args = vec![];
+  if noflush {
+    args.push("--noflush");
+  }
+  if has_wait {
+    args.push("--wait");
+  }
+  args
+}
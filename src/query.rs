@@ -0,0 +1,293 @@
+//! Selecting rules by what they match.
+//!
+//! A `Query` is built with its methods or parsed from a small text language, as used by the
+//! `riptables query` command: space-separated `key=value` pairs, every pair having to hold.
+//!
+//! | key                     | selects rules                                              |
+//! |-------------------------|------------------------------------------------------------|
+//! | `table`, `chain`        | in the table/chain                                         |
+//! | `target`                | jumping or going to the target, e.g. `ACCEPT`              |
+//! | `proto` / `protocol`    | matching the protocol with `-p`                            |
+//! | `port`                  | whose ports include the port, a number or a service name   |
+//! | `addr` / `address`      | whose source or destination network contains the address   |
+//! | `iface` / `interface`   | whose input or output interface covers the interface       |
+//! | `module` / `match`      | loading the match module with `-m`                         |
+//! | `comment`               | whose comment contains the text                            |
+//!
+//! Values containing spaces are written between double quotes. Criteria only select rules testing
+//! the field themselves: `port=22` does not select a rule accepting every port, nor a rule matching
+//! every port but 22 with `!`.
+//!
+//! ```rust
+//! use riptables::query::Query;
+//! use riptables::ruleset::Ruleset;
+//!
+//! let ruleset = Ruleset::parse("*filter
+//! :INPUT DROP
+//! -A INPUT -i lo -j ACCEPT
+//! -A INPUT -s 10.0.0.0/8 -p tcp -m tcp --dport 22 -m comment --comment \"admin ssh\" -j ACCEPT
+//! COMMIT
+//! ").unwrap();
+//! let query = Query::parse("chain=INPUT port=22 addr=10.1.2.3").unwrap();
+//! let found = query.select(&ruleset);
+//! assert_eq!(found[0].position, 2);
+//! assert_eq!(found[0].rule.comment(), Some("admin ssh"));
+//! assert_eq!(Query::new().comment("admin").select(&ruleset).len(), 1);
+//! ```
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::packet::{self, Match};
+use crate::plan;
+use crate::rule::RIPTRule;
+use crate::ruleset::Ruleset;
+use crate::services::Services;
+use crate::RIPTables;
+
+/// Tables queried by `RIPTables::query` when the query does not name one.
+pub(crate) const QUERIED_TABLES: &[&str] = &["filter", "nat", "mangle", "raw", "security"];
+
+/// Criteria selecting rules, see the module documentation. Unset criteria select every rule.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+  pub table: Option<String>,
+  pub chain: Option<String>,
+  pub target: Option<String>,
+  pub protocol: Option<String>,
+  pub port: Option<u16>,
+  pub address: Option<IpAddr>,
+  pub interface: Option<String>,
+  pub module: Option<String>,
+  pub comment: Option<String>,
+}
+
+/// A rule selected by a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found {
+  /// 1-based position of the rule in its chain.
+  pub position: usize,
+  pub rule: RIPTRule,
+}
+
+impl fmt::Display for Found {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {} {}: {}", self.rule.table, self.rule.chain, self.position, self.rule.spec())
+  }
+}
+
+impl Query {
+  pub fn new() -> Query {
+    Query::default()
+  }
+
+  /// Parses a query written in the text language of the module documentation.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::query::Query;
+  ///
+  /// let query = Query::parse("table=nat target=DNAT comment=\"port forward\"").unwrap();
+  /// assert_eq!(query, Query::new().table("nat").target("DNAT").comment("port forward"));
+  /// assert!(Query::parse("colour=blue").is_err());
+  /// ```
+  pub fn parse<S: AsRef<str>>(text: S) -> RIPTResult<Query> {
+    let mut query = Query::new();
+    for pair in self::split(text.as_ref())? {
+      let (key, value) = match pair.split_once('=') {
+        Some((key, value)) if !value.is_empty() => (key, value),
+        _ => return Err(RIPTError::Query(format!("expected key=value, found {}", pair))),
+      };
+      query = match key {
+        "table" => query.table(value),
+        "chain" => query.chain(value),
+        "target" => query.target(value),
+        "proto" | "protocol" => query.protocol(value),
+        "port" => query.port(Services::system().port(value, None).ok_or_else(|| RIPTError::Query(format!("invalid port {}", value)))?),
+        "addr" | "address" => query.address(value.parse().map_err(|_| RIPTError::Query(format!("invalid address {}", value)))?),
+        "iface" | "interface" => query.interface(value),
+        "module" | "match" => query.module(value),
+        "comment" => query.comment(value),
+        _ => return Err(RIPTError::Query(format!("unknown key {}", key))),
+      };
+    }
+    Ok(query)
+  }
+
+  pub fn table<S: ToString>(mut self, table: S) -> Query {
+    self.table = Some(table.to_string());
+    self
+  }
+
+  pub fn chain<S: ToString>(mut self, chain: S) -> Query {
+    self.chain = Some(chain.to_string());
+    self
+  }
+
+  pub fn target<S: ToString>(mut self, target: S) -> Query {
+    self.target = Some(target.to_string());
+    self
+  }
+
+  pub fn protocol<S: ToString>(mut self, protocol: S) -> Query {
+    self.protocol = Some(protocol.to_string().to_lowercase());
+    self
+  }
+
+  pub fn port(mut self, port: u16) -> Query {
+    self.port = Some(port);
+    self
+  }
+
+  pub fn address(mut self, address: IpAddr) -> Query {
+    self.address = Some(address);
+    self
+  }
+
+  pub fn interface<S: ToString>(mut self, interface: S) -> Query {
+    self.interface = Some(interface.to_string());
+    self
+  }
+
+  pub fn module<S: ToString>(mut self, module: S) -> Query {
+    self.module = Some(module.to_string());
+    self
+  }
+
+  pub fn comment<S: ToString>(mut self, comment: S) -> Query {
+    self.comment = Some(comment.to_string());
+    self
+  }
+
+  /// Returns `true` if `rule` meets every criterion. Only `Append` rules can.
+  pub fn matches(&self, rule: &RIPTRule) -> bool {
+    let spec = rule.spec();
+    if spec.is_empty() {
+      return false;
+    }
+    let selected = |value: &Option<String>, actual: &str| value.as_ref().is_none_or(|value| value == actual);
+    if !selected(&self.table, &rule.table) || !selected(&self.chain, &rule.chain) {
+      return false;
+    }
+    if let Some(ref target) = self.target {
+      if rule.jump != *target && rule.goto != *target {
+        return false;
+      }
+    }
+    if let Some(ref module) = self.module {
+      if rule.extension(module).is_none() {
+        return false;
+      }
+    }
+    if let Some(ref comment) = self.comment {
+      if !rule.comment().is_some_and(|text| text.contains(&comment[..])) {
+        return false;
+      }
+    }
+
+    let groups = iptparser::spec_groups(&spec);
+    // some option of the rule, outside of its target, matches for sure
+    let tested = |options: &[&str], test: &dyn Fn(&[String]) -> Match| {
+      groups.iter()
+        .take_while(|group| group.option != "-j" && group.option != "-g")
+        .any(|group| !group.negate && options.contains(&&group.option[..]) && test(&group.values) == Match::Yes)
+    };
+    if let Some(ref protocol) = self.protocol {
+      let protocol = Some(protocol.clone());
+      if !tested(&["-p"], &|values| packet::match_protocol(values, &protocol)) {
+        return false;
+      }
+    }
+    if let Some(port) = self.port {
+      let ports = ["--sport", "--sports", "--dport", "--dports", "--ports"];
+      if !tested(&ports, &|values| packet::match_ports(values, Some(port))) {
+        return false;
+      }
+    }
+    if let Some(address) = self.address {
+      if !tested(&["-s", "-d"], &|values| packet::match_address(values, Some(address))) {
+        return false;
+      }
+    }
+    if let Some(ref interface) = self.interface {
+      let interface = Some(interface.clone());
+      if !tested(&["-i", "-o"], &|values| packet::match_interface(values, &interface)) {
+        return false;
+      }
+    }
+    true
+  }
+
+  /// Returns the rules of `ruleset` meeting every criterion, in order.
+  pub fn select(&self, ruleset: &Ruleset) -> Vec<Found> {
+    let mut found = vec![];
+    for chain in ruleset.tables.iter().flat_map(|table| table.chains.iter()) {
+      for (index, rule) in chain.rules.iter().enumerate() {
+        if self.matches(rule) {
+          found.push(Found { position: index + 1, rule: rule.clone() });
+        }
+      }
+    }
+    found
+  }
+}
+
+/// Splits at spaces outside of double quotes, removing the quotes.
+fn split(text: &str) -> RIPTResult<Vec<String>> {
+  let mut words = vec![];
+  let mut word = String::new();
+  let mut quoted = false;
+  for c in text.chars() {
+    match c {
+      '"' => quoted = !quoted,
+      c if c.is_whitespace() && !quoted => {
+        if !word.is_empty() {
+          words.push(word.clone());
+          word.clear();
+        }
+      }
+      c => word.push(c),
+    }
+  }
+  if quoted {
+    return Err(RIPTError::Query("unterminated quote".to_string()));
+  }
+  if !word.is_empty() {
+    words.push(word);
+  }
+  Ok(words)
+}
+
+impl RIPTables {
+  /// Returns the current rules meeting every criterion of `query`. Without a table in the query,
+  /// every table loaded in the kernel is searched, or all of them when the kernel does not list
+  /// its tables; failing to list one of them is an error.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::query::Query;
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// for found in iptables.query(&Query::new().target("DROP").port(22)).unwrap() {
+  ///   println!("{}", found);
+  /// }
+  /// ```
+  pub fn query(&self, query: &Query) -> RIPTResult<Vec<Found>> {
+    let rules = match query.table {
+      Some(ref table) => self.list(table)?,
+      None => {
+        let mut rules = vec![];
+        for table in plan::loaded(QUERIED_TABLES, &self.loaded_tables()?) {
+          rules.extend(self.list(OsStr::new(table))?);
+        }
+        rules
+      }
+    };
+    Ok(query.select(&Ruleset::from_rules(rules)))
+  }
+}
@@ -1,25 +1,75 @@
+//! Rule types returned by the iptables output parser.
+//!
+//! With the `serde` feature enabled every type here implements `Serialize` and
+//! `Deserialize`. The JSON shape is part of the public API:
+//!
+//! ```json
+//! {
+//!   "origin": "-A INPUT -i eth0 -p tcp -m tcp --dport 22 -j ACCEPT",
+//!   "archive": "append",
+//!   "table": "filter",
+//!   "chain": "INPUT",
+//!   "input": { "negate": false, "value": "eth0" },
+//!   "output": null,
+//!   "protocol": "tcp",
+//!   "sport": "",
+//!   "dport": "22",
+//!   "jump": "ACCEPT",
+//!   "goto": "",
+//!   "extensions": [
+//!     { "name": "tcp", "options": [{ "negate": false, "name": "dport", "values": ["22"] }] }
+//!   ]
+//! }
+//! ```
+//!
+//! `archive` is one of `"policy"`, `"new_chain"` or `"append"`. Fields that
+//! were not present in the rule are empty strings, `null` or empty arrays.
+//! `extensions` lists the `-m` matches of the rule, each with the options the
+//! parser read after it.
+
 use std::vec::Vec;
 
-#[derive(Debug, PartialEq)]
+use crate::iptparser;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
 pub enum Archive {
   Policy,
   NewChain,
   Append,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RIPTInterface {
   pub negate: bool,
   pub value: String,
 }
 
-#[derive(Debug)]
+/// A match extension loaded with `-m`, e.g. `-m comment --comment "text"`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Extension {
-  m: &'static str,
-//  item:
+  /// Name of the match module.
+  pub name: String,
+  /// Options given to the module, in order.
+  pub options: Vec<ExtensionOption>,
+}
+
+/// A single `[!] --name value...` option of an `Extension`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExtensionOption {
+  pub negate: bool,
+  pub name: String,
+  pub values: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RIPTRule {
   pub origin: String,
   pub archive: Archive,
@@ -31,6 +81,74 @@ pub struct RIPTRule {
   pub sport: String,
   pub dport: String,
   pub jump: String,
+  /// Chain given to `-g`, processing continues there without returning.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub goto: String,
   pub extensions: Vec<Extension>,
 }
 
+impl RIPTRule {
+  /// Returns the rule specification of an `Append` rule, that is `origin` without the leading
+  /// `-A <chain>`, ready to be passed back to `append`/`insert`/`delete`.
+  /// Returns an empty string for `Policy` and `NewChain` rules.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// for rule in iptables.list("filter").unwrap() {
+  ///   println!("{}", rule.spec());
+  /// }
+  /// ```
+  pub fn spec(&self) -> String {
+    if self.archive != Archive::Append {
+      return "".to_string();
+    }
+    let prefix = format!("-A {}", self.chain);
+    match self.origin.trim().strip_prefix(&prefix[..]) {
+      Some(spec) => spec.trim().to_string(),
+      None => "".to_string(),
+    }
+  }
+
+  /// Returns the match extension loaded with `-m <name>`.
+  pub fn extension(&self, name: &str) -> Option<&Extension> {
+    self.extensions.iter().find(|extension| extension.name == name)
+  }
+
+  /// Returns the text of the `-m comment --comment` match, unquoted.
+  pub fn comment(&self) -> Option<&str> {
+    self.extension("comment")
+      .and_then(|extension| extension.options.iter().find(|option| option.name == "comment"))
+      .and_then(|option| option.values.first())
+      .map(|value| &value[..])
+  }
+
+  /// Returns `true` if this `Append` rule has the specification `spec`, however it is written.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let rules = iptables.list_chains("filter", "INPUT").unwrap();
+  /// let ssh = rules.iter().position(|rule| rule.is_spec("--protocol tcp --dport 22 -j ACCEPT"));
+  /// ```
+  pub fn is_spec<S: AsRef<str>>(&self, spec: S) -> bool {
+    self.archive == Archive::Append && self::normalize(self.spec()) == self::normalize(spec)
+  }
+}
+
+/// Rewrites a rule specification the way `iptables -S` prints it, so that different ways of writing
+/// the same rule compare equal.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::rule;
+///
+/// assert_eq!(rule::normalize("--protocol tcp --dport 22 -s 10.0.0.1 -j ACCEPT"), "-s 10.0.0.1/32 -p tcp --dport 22 -j ACCEPT");
+/// assert_eq!(rule::normalize("-s 10.0.0.1/32 -p tcp -m tcp --dport 22 -j ACCEPT"), "-s 10.0.0.1/32 -p tcp --dport 22 -j ACCEPT");
+/// ```
+pub fn normalize<S: AsRef<str>>(spec: S) -> String {
+  iptparser::normalize_spec(spec.as_ref())
+}
@@ -0,0 +1,308 @@
+//! Whole-table snapshots in the canonical `riptables` save format.
+//!
+//! The canonical format is the counter-less subset of `iptables-save`:
+//!
+//! ```text
+//! *filter
+//! :INPUT ACCEPT
+//! :FORWARD DROP
+//! :OUTPUT ACCEPT
+//! :SSH -
+//! -A INPUT -j SSH
+//! -A SSH -p tcp -m tcp --dport 22 -j ACCEPT
+//! COMMIT
+//! ```
+//!
+//! Every table starts with `*<table>`, declares its chains with `:<chain> <policy>` (`-` for
+//! user-defined chains), lists its rules with `-A` and ends with `COMMIT`. Empty lines and lines
+//! starting with `#` are ignored, as are `[packets:bytes]` counters after a chain declaration, so
+//! plain `iptables-save` output can be read too.
+
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::RIPTResult;
+use crate::iptparser;
+use crate::rule::{Archive, RIPTRule};
+
+/// Chains and rules of several tables.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Ruleset {
+  pub tables: Vec<RulesetTable>,
+}
+
+/// Chains of a single table, in declaration order.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RulesetTable {
+  pub name: String,
+  pub chains: Vec<RulesetChain>,
+}
+
+/// A chain and its rules. `policy` is `None` for user-defined chains.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RulesetChain {
+  pub name: String,
+  pub policy: Option<String>,
+  pub rules: Vec<RIPTRule>,
+}
+
+/// One step needed to turn a ruleset into another, see `diff`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "snake_case", tag = "change"))]
+pub enum Change {
+  NewChain { table: String, chain: String },
+  DeleteChain { table: String, chain: String },
+  SetPolicy { table: String, chain: String, policy: String },
+  /// `rule` is inserted at `position` (1-based) of the resulting chain.
+  Insert { table: String, chain: String, position: usize, rule: String },
+  /// `rule` is deleted from `position` (1-based) of the original chain.
+  Delete { table: String, chain: String, position: usize, rule: String },
+}
+
+impl Ruleset {
+  pub fn new() -> Ruleset {
+    Ruleset { tables: vec![] }
+  }
+
+  /// Groups rules returned by `list` by table and chain.
+  pub fn from_rules(rules: Vec<RIPTRule>) -> Ruleset {
+    let mut ruleset = Ruleset::new();
+    for rule in rules {
+      let chain = ruleset.table_entry(&rule.table).chain_entry(&rule.chain);
+      match rule.archive {
+        Archive::Policy => chain.policy = Some(rule.jump),
+        Archive::NewChain => {}
+        Archive::Append => chain.rules.push(rule),
+      }
+    }
+    ruleset
+  }
+
+  /// Parses a ruleset in the canonical save format.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::ruleset::Ruleset;
+  ///
+  /// let ruleset = Ruleset::parse("*filter\n:INPUT DROP\n-A INPUT -i lo -j ACCEPT\nCOMMIT\n").unwrap();
+  /// assert_eq!(ruleset.tables[0].chains[0].rules.len(), 1);
+  /// ```
+  pub fn parse<S: AsRef<str>>(text: S) -> RIPTResult<Ruleset> {
+    Ok(iptparser::parse_ruleset(text.as_ref())?)
+  }
+
+  pub fn table(&self, name: &str) -> Option<&RulesetTable> {
+    self.tables.iter().find(|table| table.name == name)
+  }
+
+  /// Returns the table `name`, adding an empty one if it is missing.
+  pub fn table_entry(&mut self, name: &str) -> &mut RulesetTable {
+    match self.tables.iter().position(|table| table.name == name) {
+      Some(index) => &mut self.tables[index],
+      None => {
+        self.tables.push(RulesetTable { name: name.to_string(), chains: vec![] });
+        self.tables.last_mut().unwrap()
+      }
+    }
+  }
+
+  /// Iterates over every rule of every table.
+  pub fn rules(&self) -> impl Iterator<Item=&RIPTRule> {
+    self.tables.iter().flat_map(|table| table.rules())
+  }
+}
+
+impl RulesetTable {
+  pub fn chain(&self, name: &str) -> Option<&RulesetChain> {
+    self.chains.iter().find(|chain| chain.name == name)
+  }
+
+  /// Returns the chain `name`, adding an empty user-defined one if it is missing.
+  pub fn chain_entry(&mut self, name: &str) -> &mut RulesetChain {
+    match self.chains.iter().position(|chain| chain.name == name) {
+      Some(index) => &mut self.chains[index],
+      None => {
+        self.chains.push(RulesetChain { name: name.to_string(), policy: None, rules: vec![] });
+        self.chains.last_mut().unwrap()
+      }
+    }
+  }
+
+  /// Iterates over the rules of every chain.
+  pub fn rules(&self) -> impl Iterator<Item=&RIPTRule> {
+    self.chains.iter().flat_map(|chain| chain.rules.iter())
+  }
+}
+
+impl fmt::Display for Ruleset {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    for table in &self.tables {
+      writeln!(f, "*{}", table.name)?;
+      let builtins = crate::builtin_chains(&table.name[..]).unwrap_or(&[]);
+      for chain in &table.chains {
+        match chain.policy {
+          Some(ref policy) => writeln!(f, ":{} {}", chain.name, policy)?,
+          // A built-in chain without a policy keeps whatever policy it has.
+          None if builtins.contains(&&chain.name[..]) => {}
+          None => writeln!(f, ":{} -", chain.name)?,
+        }
+      }
+      for chain in &table.chains {
+        for rule in &chain.rules {
+          let spec = rule.spec();
+          if spec.is_empty() {
+            writeln!(f, "-A {}", chain.name)?;
+          } else {
+            writeln!(f, "-A {} {}", chain.name, spec)?;
+          }
+        }
+      }
+      writeln!(f, "COMMIT")?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Change {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    match *self {
+      Change::NewChain { ref table, ref chain } => write!(f, "-t {} -N {}", table, chain),
+      Change::DeleteChain { ref table, ref chain } => write!(f, "-t {} -X {}", table, chain),
+      Change::SetPolicy { ref table, ref chain, ref policy } => write!(f, "-t {} -P {} {}", table, chain, policy),
+      Change::Insert { ref table, ref chain, position, ref rule } => write!(f, "-t {} -I {} {} {}", table, chain, position, rule),
+      Change::Delete { ref table, ref chain, position, ref rule } => write!(f, "-t {} -D {} {} # {}", table, chain, position, rule),
+    }
+  }
+}
+
+/// Computes the changes turning `from` into `to`.
+///
+/// Tables and chains missing from `to` are considered empty. Policies are only compared when both
+/// sides declare one. Rules are matched by their specification after `rule::normalize`, keeping
+/// the longest common ordered subsequence of both chains in place; changes carry the specification
+/// as written. `Delete` positions refer to `from` and are listed from the last rule to the first,
+/// `Insert` positions refer to `to` and are listed in ascending order, so applying the changes in
+/// order yields `to`.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::ruleset::{self, Ruleset};
+///
+/// let from = Ruleset::parse("*filter\n-A INPUT -j ACCEPT\nCOMMIT\n").unwrap();
+/// let to = Ruleset::parse("*filter\n-A INPUT -j DROP\nCOMMIT\n").unwrap();
+/// for change in ruleset::diff(&from, &to) {
+///   println!("{}", change);
+/// }
+/// ```
+pub fn diff(from: &Ruleset, to: &Ruleset) -> Vec<Change> {
+  let empty = RulesetTable { name: "".to_string(), chains: vec![] };
+  let mut names: Vec<&str> = from.tables.iter().map(|table| &table.name[..]).collect();
+  for table in &to.tables {
+    if !names.contains(&&table.name[..]) {
+      names.push(&table.name);
+    }
+  }
+
+  let mut changes = vec![];
+  for name in names {
+    let from_table = from.table(name).unwrap_or(&empty);
+    let to_table = to.table(name).unwrap_or(&empty);
+    self::diff_table(name, from_table, to_table, &mut changes);
+  }
+  changes
+}
+
+fn diff_table(table: &str, from: &RulesetTable, to: &RulesetTable, changes: &mut Vec<Change>) {
+  let builtins = crate::builtin_chains(table).unwrap_or(&[]);
+  let mut deleted_chains = vec![];
+
+  for chain in &to.chains {
+    if from.chain(&chain.name).is_none() && !builtins.contains(&&chain.name[..]) {
+      changes.push(Change::NewChain { table: table.to_string(), chain: chain.name.clone() });
+    }
+  }
+
+  for from_chain in &from.chains {
+    let to_chain = to.chain(&from_chain.name);
+    if to_chain.is_none() && !builtins.contains(&&from_chain.name[..]) {
+      deleted_chains.push(from_chain.name.clone());
+    }
+    if let (Some(old), Some(new)) = (from_chain.policy.as_ref(), to_chain.and_then(|chain| chain.policy.as_ref())) {
+      if old != new {
+        changes.push(Change::SetPolicy { table: table.to_string(), chain: from_chain.name.clone(), policy: new.to_string() });
+      }
+    }
+  }
+  for to_chain in &to.chains {
+    if let (None, Some(policy)) = (from.chain(&to_chain.name), to_chain.policy.as_ref()) {
+      changes.push(Change::SetPolicy { table: table.to_string(), chain: to_chain.name.clone(), policy: policy.to_string() });
+    }
+  }
+
+  let mut names: Vec<&str> = from.chains.iter().map(|chain| &chain.name[..]).collect();
+  for chain in &to.chains {
+    if !names.contains(&&chain.name[..]) {
+      names.push(&chain.name);
+    }
+  }
+  for name in names {
+    let old: Vec<String> = from.chain(name).map(|chain| chain.rules.iter().map(|rule| rule.spec()).collect()).unwrap_or_default();
+    let new: Vec<String> = to.chain(name).map(|chain| chain.rules.iter().map(|rule| rule.spec()).collect()).unwrap_or_default();
+    self::diff_rules(table, name, &old, &new, changes);
+  }
+
+  for chain in deleted_chains {
+    changes.push(Change::DeleteChain { table: table.to_string(), chain });
+  }
+}
+
+fn diff_rules(table: &str, chain: &str, old: &[String], new: &[String], changes: &mut Vec<Change>) {
+  let old_keys: Vec<String> = old.iter().map(crate::rule::normalize).collect();
+  let new_keys: Vec<String> = new.iter().map(crate::rule::normalize).collect();
+
+  // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+  let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+  for i in (0..old.len()).rev() {
+    for j in (0..new.len()).rev() {
+      lengths[i][j] = if old_keys[i] == new_keys[j] {
+        lengths[i + 1][j + 1] + 1
+      } else {
+        lengths[i + 1][j].max(lengths[i][j + 1])
+      };
+    }
+  }
+
+  let mut kept_old = vec![false; old.len()];
+  let mut kept_new = vec![false; new.len()];
+  let (mut i, mut j) = (0, 0);
+  while i < old.len() && j < new.len() {
+    if old_keys[i] == new_keys[j] {
+      kept_old[i] = true;
+      kept_new[j] = true;
+      i += 1;
+      j += 1;
+    } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+
+  for (index, rule) in old.iter().enumerate().rev() {
+    if !kept_old[index] {
+      changes.push(Change::Delete { table: table.to_string(), chain: chain.to_string(), position: index + 1, rule: rule.clone() });
+    }
+  }
+  for (index, rule) in new.iter().enumerate() {
+    if !kept_new[index] {
+      changes.push(Change::Insert { table: table.to_string(), chain: chain.to_string(), position: index + 1, rule: rule.clone() });
+    }
+  }
+}
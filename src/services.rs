@@ -0,0 +1,142 @@
+//! Service and protocol names, read from `/etc/services` and `/etc/protocols`.
+//!
+//! iptables takes `--dport https` as well as `--dport 443`, and `-p 6` as well as `-p tcp`, but
+//! `iptables -S` always prints the port number and the protocol name. `normalize` and the rule
+//! builder rewrite names the same way, so that rules compare equal however they are written.
+//! Names missing from the files are kept as they are.
+//!
+//! ```rust
+//! use riptables::services::Services;
+//!
+//! let services = Services::parse("https 443/tcp\nhttps 443/udp\nhttp 80/tcp www\n", "tcp 6 TCP\nudp 17 UDP\n");
+//! assert_eq!(services.port("www", Some("tcp")), Some(80));
+//! assert_eq!(services.ports("http:https,8080", Some("tcp")), "80:443,8080");
+//! assert_eq!(services.protocol_name("6"), "tcp");
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+use crate::error::RIPTResult;
+
+/// Where the port of each service is.
+pub const SERVICES_PATH: &str = "/etc/services";
+/// Where the number of each protocol is.
+pub const PROTOCOLS_PATH: &str = "/etc/protocols";
+
+/// The services and protocols of the host, read once.
+static SYSTEM: OnceLock<Services> = OnceLock::new();
+
+/// Service and protocol names with their numbers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Services {
+  /// Port of each service name and alias, by protocol.
+  ports: HashMap<(String, String), u16>,
+  /// Number of each protocol name and alias.
+  protocols: HashMap<String, u8>,
+  /// Name of each protocol number.
+  protocol_names: HashMap<u8, String>,
+}
+
+impl Services {
+  /// Reads the contents of `/etc/services` and `/etc/protocols`. Comments and malformed lines are
+  /// skipped; the first name given to a port or number wins, as in the C library.
+  pub fn parse<S: AsRef<str>, P: AsRef<str>>(services: S, protocols: P) -> Services {
+    let mut parsed = Services::default();
+    for words in self::entries(services.as_ref()) {
+      let (port, protocol) = match words.get(1).and_then(|entry| entry.split_once('/')) {
+        Some((port, protocol)) => (port, protocol.to_lowercase()),
+        None => continue,
+      };
+      let port = match port.parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => continue,
+      };
+      for name in words.iter().take(1).chain(words.iter().skip(2)) {
+        parsed.ports.entry((name.to_string(), protocol.clone())).or_insert(port);
+      }
+    }
+    for words in self::entries(protocols.as_ref()) {
+      let number = match words.get(1).and_then(|number| number.parse::<u8>().ok()) {
+        Some(number) => number,
+        None => continue,
+      };
+      parsed.protocol_names.entry(number).or_insert_with(|| words[0].to_lowercase());
+      for name in words.iter().take(1).chain(words.iter().skip(2)) {
+        parsed.protocols.entry(name.to_lowercase()).or_insert(number);
+      }
+    }
+    parsed
+  }
+
+  /// Reads the files of the host. A missing file reads as an empty one.
+  pub fn load() -> RIPTResult<Services> {
+    let read = |path: &str| match fs::read_to_string(path) {
+      Ok(text) => Ok(text),
+      Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+      Err(err) => Err(err),
+    };
+    Ok(Services::parse(read(SERVICES_PATH)?, read(PROTOCOLS_PATH)?))
+  }
+
+  /// The services of the host, read on first use. Unreadable files count as empty.
+  pub fn system() -> &'static Services {
+    SYSTEM.get_or_init(|| Services::load().unwrap_or_default())
+  }
+
+  /// Returns the port of `name` for `protocol`, or for any protocol when `None`. Numbers are
+  /// returned as they are.
+  pub fn port(&self, name: &str, protocol: Option<&str>) -> Option<u16> {
+    if let Ok(port) = name.parse() {
+      return Some(port);
+    }
+    match protocol {
+      Some(protocol) => self.ports.get(&(name.to_string(), protocol.to_lowercase())).copied(),
+      None => ["tcp", "udp", "sctp", "dccp"].iter().find_map(|protocol| self.ports.get(&(name.to_string(), protocol.to_string()))).copied(),
+    }
+  }
+
+  /// Rewrites the service names of a port option value, e.g. `ssh,http:https` or `!https`, to
+  /// numbers.
+  pub fn ports(&self, value: &str, protocol: Option<&str>) -> String {
+    if let Some(value) = value.strip_prefix('!') {
+      return format!("!{}", self.ports(value, protocol));
+    }
+    value.split(',')
+      .map(|range| {
+        range.split(':')
+          .map(|port| match self.port(port, protocol) {
+            Some(number) if !port.is_empty() => number.to_string(),
+            _ => port.to_string(),
+          })
+          .collect::<Vec<String>>()
+          .join(":")
+      })
+      .collect::<Vec<String>>()
+      .join(",")
+  }
+
+  /// Returns the number of the protocol `name`. Numbers are returned as they are.
+  pub fn protocol(&self, name: &str) -> Option<u8> {
+    name.parse().ok().or_else(|| self.protocols.get(&name.to_lowercase()).copied())
+  }
+
+  /// Returns the name `iptables -S` prints for `protocol`, given by name, alias or number.
+  /// `all` and unknown protocols are returned lowercased.
+  pub fn protocol_name(&self, protocol: &str) -> String {
+    let lower = protocol.to_lowercase();
+    match self.protocol(&lower) {
+      Some(0) | None => lower,
+      Some(number) => self.protocol_names.get(&number).cloned().unwrap_or(lower),
+    }
+  }
+}
+
+/// Splits the lines of a services or protocols file into words, without comments and empty lines.
+fn entries(text: &str) -> impl Iterator<Item = Vec<&str>> {
+  text.lines()
+    .map(|line| line.split('#').next().unwrap_or_default().split_whitespace().collect::<Vec<&str>>())
+    .filter(|words| words.len() >= 2)
+}
@@ -0,0 +1,453 @@
+//! Tables, chains and policies, and what the kernel provides.
+//!
+//! The kernel lists the tables, matches and targets of its loaded modules in `/proc/net`
+//! (`ip_tables_names`, `ip_tables_matches`, `ip_tables_targets` and their `ip6_` counterparts).
+//! A table or extension missing there may still be available: its module is loaded on first use.
+//! On hosts using the nftables backend of iptables the lists stay empty.
+//!
+//! `RIPTables::supports_match` and `supports_target` also ask iptables whether it has the extension,
+//! which covers modules not loaded yet.
+//!
+//! ```rust
+//! use riptables::table::{self, Table};
+//!
+//! assert_eq!("nat".parse::<Table>().unwrap(), Table::Nat);
+//! assert_eq!("broute".parse::<Table>().unwrap(), Table::Custom("broute".to_string()));
+//! assert!("not a table".parse::<Table>().is_err());
+//! for table in table::loaded_tables(false).unwrap() {
+//!   println!("{} {:?}", table, table.builtin_chains());
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::plan;
+use crate::RIPTables;
+
+// List of built-in chains taken from: man 8 iptables
+const BUILTIN_CHAINS_FILTER: &[&str] = &["INPUT", "FORWARD", "OUTPUT"];
+const BUILTIN_CHAINS_MANGLE: &[&str] = &["PREROUTING", "OUTPUT", "INPUT", "FORWARD", "POSTROUTING"];
+const BUILTIN_CHAINS_NAT: &[&str] = &["PREROUTING", "POSTROUTING", "OUTPUT"];
+const BUILTIN_CHAINS_RAW: &[&str] = &["PREROUTING", "OUTPUT"];
+const BUILTIN_CHAINS_SECURITY: &[&str] = &["INPUT", "OUTPUT", "FORWARD"];
+
+/// Longest names the kernel takes, without the trailing NUL.
+const TABLE_NAME_MAX: usize = 31;
+const CHAIN_NAME_MAX: usize = 28;
+
+/// Targets handled by iptables itself, without extension.
+const STANDARD_TARGETS: &[&str] = &["ACCEPT", "DROP", "RETURN", "QUEUE"];
+
+/// An extension of a command: `'m'` for matches or `'j'` for targets, and its name.
+type Extension = (&'static str, char, String);
+
+/// Results of `supports_match` and `supports_target`.
+static SUPPORTED: OnceLock<Mutex<HashMap<Extension, bool>>> = OnceLock::new();
+
+/// A table, `Custom` for the ones iptables does not document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Table {
+  Filter,
+  Nat,
+  Mangle,
+  Raw,
+  Security,
+  Custom(String),
+}
+
+impl Table {
+  /// The name given to `-t`.
+  pub fn name(&self) -> &str {
+    match self {
+      Table::Filter => "filter",
+      Table::Nat => "nat",
+      Table::Mangle => "mangle",
+      Table::Raw => "raw",
+      Table::Security => "security",
+      Table::Custom(name) => name,
+    }
+  }
+
+  /// The built-in chains of the table, `None` for a `Custom` one.
+  /// `RIPTables::builtin_chains` asks iptables instead.
+  pub fn builtin_chains(&self) -> Option<&'static [&'static str]> {
+    match self {
+      Table::Filter => Some(BUILTIN_CHAINS_FILTER),
+      Table::Nat => Some(BUILTIN_CHAINS_NAT),
+      Table::Mangle => Some(BUILTIN_CHAINS_MANGLE),
+      Table::Raw => Some(BUILTIN_CHAINS_RAW),
+      Table::Security => Some(BUILTIN_CHAINS_SECURITY),
+      Table::Custom(_) => None,
+    }
+  }
+}
+
+impl TryFrom<&str> for Table {
+  type Error = RIPTError;
+
+  /// Fails for names iptables refuses: empty, longer than 31 bytes, with spaces or starting with `-`.
+  fn try_from(name: &str) -> RIPTResult<Table> {
+    let table = match name {
+      "filter" => Table::Filter,
+      "nat" => Table::Nat,
+      "mangle" => Table::Mangle,
+      "raw" => Table::Raw,
+      "security" => Table::Security,
+      name if self::is_name(name, TABLE_NAME_MAX) => Table::Custom(name.to_string()),
+      name => return Err(RIPTError::Invalid(format!("invalid table name {:?}", name))),
+    };
+    Ok(table)
+  }
+}
+
+impl TryFrom<&OsStr> for Table {
+  type Error = RIPTError;
+
+  fn try_from(name: &OsStr) -> RIPTResult<Table> {
+    Table::try_from(self::utf8(name, "table")?)
+  }
+}
+
+impl FromStr for Table {
+  type Err = RIPTError;
+
+  fn from_str(name: &str) -> RIPTResult<Table> {
+    Table::try_from(name)
+  }
+}
+
+impl fmt::Display for Table {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl AsRef<OsStr> for Table {
+  fn as_ref(&self) -> &OsStr {
+    OsStr::new(self.name())
+  }
+}
+
+/// The name of a chain, checked the way iptables does.
+///
+/// # Example
+///
+/// ```rust
+/// use std::convert::TryFrom;
+/// use riptables::table::ChainName;
+///
+/// let chain = ChainName::try_from("SERVICES").unwrap();
+/// assert_eq!(chain.as_str(), "SERVICES");
+/// assert!(ChainName::try_from("MY CHAIN").is_err());
+/// assert!("A_CHAIN_NAME_LONGER_THAN_28_BYTES".parse::<ChainName>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChainName(String);
+
+impl ChainName {
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl TryFrom<&str> for ChainName {
+  type Error = RIPTError;
+
+  /// Fails for names iptables refuses: empty, longer than 28 bytes, with spaces or quotes, or
+  /// starting with `-` or `!`.
+  fn try_from(name: &str) -> RIPTResult<ChainName> {
+    if !self::is_name(name, CHAIN_NAME_MAX) || name.starts_with('!') {
+      return Err(RIPTError::Invalid(format!("invalid chain name {:?}", name)));
+    }
+    Ok(ChainName(name.to_string()))
+  }
+}
+
+impl TryFrom<&OsStr> for ChainName {
+  type Error = RIPTError;
+
+  fn try_from(name: &OsStr) -> RIPTResult<ChainName> {
+    ChainName::try_from(self::utf8(name, "chain")?)
+  }
+}
+
+impl TryFrom<String> for ChainName {
+  type Error = RIPTError;
+
+  fn try_from(name: String) -> RIPTResult<ChainName> {
+    ChainName::try_from(&name[..])
+  }
+}
+
+impl FromStr for ChainName {
+  type Err = RIPTError;
+
+  fn from_str(name: &str) -> RIPTResult<ChainName> {
+    ChainName::try_from(name)
+  }
+}
+
+impl fmt::Display for ChainName {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl AsRef<OsStr> for ChainName {
+  fn as_ref(&self) -> &OsStr {
+    OsStr::new(&self.0)
+  }
+}
+
+/// The policy of a built-in chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Policy {
+  Accept,
+  Drop,
+}
+
+impl Policy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Policy::Accept => "ACCEPT",
+      Policy::Drop => "DROP",
+    }
+  }
+}
+
+impl TryFrom<&str> for Policy {
+  type Error = RIPTError;
+
+  /// Accepts `ACCEPT` and `DROP`, in any case.
+  fn try_from(policy: &str) -> RIPTResult<Policy> {
+    match &policy.to_uppercase()[..] {
+      "ACCEPT" => Ok(Policy::Accept),
+      "DROP" => Ok(Policy::Drop),
+      _ => Err(RIPTError::Invalid(format!("invalid policy {:?}, expected ACCEPT or DROP", policy))),
+    }
+  }
+}
+
+impl TryFrom<&OsStr> for Policy {
+  type Error = RIPTError;
+
+  fn try_from(policy: &OsStr) -> RIPTResult<Policy> {
+    Policy::try_from(self::utf8(policy, "policy")?)
+  }
+}
+
+impl FromStr for Policy {
+  type Err = RIPTError;
+
+  fn from_str(policy: &str) -> RIPTResult<Policy> {
+    Policy::try_from(policy)
+  }
+}
+
+impl fmt::Display for Policy {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl AsRef<OsStr> for Policy {
+  fn as_ref(&self) -> &OsStr {
+    OsStr::new(self.as_str())
+  }
+}
+
+fn is_name(name: &str, max: usize) -> bool {
+  !name.is_empty() && name.len() <= max && !name.starts_with('-')
+    && name.chars().all(|c| !c.is_whitespace() && !c.is_control() && c != '"' && c != '\'')
+}
+
+fn utf8<'a>(text: &'a OsStr, what: &str) -> RIPTResult<&'a str> {
+  text.to_str().ok_or_else(|| RIPTError::Invalid(format!("{} name is not UTF-8: {:?}", what, text)))
+}
+
+/// The tables loaded in the kernel.
+pub fn loaded_tables(ipv6: bool) -> RIPTResult<Vec<Table>> {
+  let names = self::read_names(if ipv6 { "ip6_tables_names" } else { "ip_tables_names" })?;
+  Ok(names.iter().filter_map(|name| Table::try_from(&name[..]).ok()).collect())
+}
+
+/// The match extensions loaded in the kernel, e.g. `conntrack`.
+pub fn loaded_matches(ipv6: bool) -> RIPTResult<Vec<String>> {
+  self::read_names(if ipv6 { "ip6_tables_matches" } else { "ip_tables_matches" })
+}
+
+/// The target extensions loaded in the kernel, e.g. `REJECT`.
+pub fn loaded_targets(ipv6: bool) -> RIPTResult<Vec<String>> {
+  self::read_names(if ipv6 { "ip6_tables_targets" } else { "ip_tables_targets" })
+}
+
+/// Reads a list of `/proc/net`, one name per line, repeated once per revision of the extension.
+/// A missing file, when the module is not loaded, is an empty list.
+fn read_names(file: &str) -> RIPTResult<Vec<String>> {
+  let text = match fs::read_to_string(format!("/proc/net/{}", file)) {
+    Ok(text) => text,
+    Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+    Err(err) => return Err(RIPTError::Io(err)),
+  };
+  let mut names: Vec<String> = vec![];
+  for name in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+    if !names.iter().any(|known| known == name) {
+      names.push(name.to_string());
+    }
+  }
+  Ok(names)
+}
+
+/// Whether `cmd` supports an extension, when known without asking iptables: invalid names are
+/// not, standard targets are, and the others once cached.
+pub(crate) fn known_support(cmd: &'static str, kind: char, name: &str) -> Option<bool> {
+  if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') || name.starts_with('-') {
+    return Some(false);
+  }
+  if kind == 'j' && STANDARD_TARGETS.contains(&name) {
+    return Some(true);
+  }
+  let supported = SUPPORTED.get_or_init(|| Mutex::new(HashMap::new()));
+  let known = supported.lock().unwrap_or_else(|err| err.into_inner()).get(&(cmd, kind, name.to_string())).copied();
+  known
+}
+
+/// Clears what is cached about the extensions of `cmd`.
+pub(crate) fn forget_support(cmd: &'static str) {
+  if let Some(supported) = SUPPORTED.get() {
+    supported.lock().unwrap_or_else(|err| err.into_inner()).retain(|(known, _, _), _| *known != cmd);
+  }
+}
+
+/// Returns `true` if the kernel lists the extension, of `kind` `m` for a match or `j` for a target.
+pub(crate) fn is_loaded(ipv6: bool, kind: char, name: &str) -> RIPTResult<bool> {
+  let loaded = match kind {
+    'm' => self::loaded_matches(ipv6)?,
+    _ => self::loaded_targets(ipv6)?,
+  };
+  Ok(loaded.iter().any(|loaded| loaded == name))
+}
+
+/// The arguments asking iptables for the help of an extension, which fails when it does not have
+/// the extension.
+pub(crate) fn help_args(kind: char, name: &str) -> Vec<String> {
+  vec![format!("-{}", kind), name.to_string(), "-h".to_string()]
+}
+
+/// Caches whether `cmd` supports an extension.
+pub(crate) fn remember_support(cmd: &'static str, kind: char, name: &str, known: bool) {
+  let supported = SUPPORTED.get_or_init(|| Mutex::new(HashMap::new()));
+  supported.lock().unwrap_or_else(|err| err.into_inner()).insert((cmd, kind, name.to_string()), known);
+}
+
+impl RIPTables {
+  fn is_ipv6(&self) -> bool {
+    self.cmd == "ip6tables"
+  }
+
+  /// The tables loaded in the kernel for this family, see `table::loaded_tables`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// for table in iptables.loaded_tables().unwrap() {
+  ///   println!("{}", table);
+  /// }
+  /// ```
+  pub fn loaded_tables(&self) -> RIPTResult<Vec<Table>> {
+    self::loaded_tables(self.is_ipv6())
+  }
+
+  /// The match extensions loaded in the kernel for this family.
+  pub fn loaded_matches(&self) -> RIPTResult<Vec<String>> {
+    self::loaded_matches(self.is_ipv6())
+  }
+
+  /// The target extensions loaded in the kernel for this family.
+  pub fn loaded_targets(&self) -> RIPTResult<Vec<String>> {
+    self::loaded_targets(self.is_ipv6())
+  }
+
+  /// The built-in chains of a table, the chains with a policy in `iptables -S` for tables iptables
+  /// does not document.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// assert_eq!(iptables.builtin_chains("raw").unwrap(), vec!["PREROUTING", "OUTPUT"]);
+  /// ```
+  pub fn builtin_chains<T>(&self, table: T) -> RIPTResult<Vec<String>> where T: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    if let Some(chains) = table.builtin_chains() {
+      return Ok(chains.iter().map(|chain| chain.to_string()).collect());
+    }
+    let (code, output) = self.execute(|iptables| iptables.arg("-t").arg(&table).arg("-S"))?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(plan::policy_chains(iptparser::parse_rules(table.to_string(), output)?))
+  }
+
+  /// Returns `true` if the match extension `name`, as given to `-m`, can be used: the kernel has it
+  /// loaded, or iptables has it and the kernel loads it on first use. Results are cached for the
+  /// life of the process, see `forget_supported`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// let rule = if iptables.supports_match("hashlimit").unwrap() {
+  ///   "-p tcp --dport 22 -m hashlimit --hashlimit-above 10/min --hashlimit-mode srcip --hashlimit-name ssh -j DROP"
+  /// } else {
+  ///   "-p tcp --dport 22 -m limit --limit 10/min -j ACCEPT"
+  /// };
+  /// ```
+  pub fn supports_match(&self, name: &str) -> RIPTResult<bool> {
+    self.supports('m', name)
+  }
+
+  /// Returns `true` if the target extension `name`, as given to `-j`, can be used, see
+  /// `supports_match`.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// if !iptables.supports_target("TPROXY").unwrap() {
+  ///   eprintln!("transparent proxying is not available");
+  /// }
+  /// ```
+  pub fn supports_target(&self, name: &str) -> RIPTResult<bool> {
+    self.supports('j', name)
+  }
+
+  /// Clears the cached results of `supports_match` and `supports_target`, e.g. after installing
+  /// a module.
+  pub fn forget_supported(&self) {
+    self::forget_support(self.cmd)
+  }
+
+  fn supports(&self, kind: char, name: &str) -> RIPTResult<bool> {
+    if let Some(known) = self::known_support(self.cmd, kind, name) {
+      return Ok(known);
+    }
+
+    let known = self::is_loaded(self.is_ipv6(), kind, name)? || {
+      let (code, _output) = self.execute(|iptables| iptables.args(self::help_args(kind, name)))?;
+      code == 0
+    };
+    self::remember_support(self.cmd, kind, name, known);
+    Ok(known)
+  }
+}
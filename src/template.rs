@@ -0,0 +1,211 @@
+//! Rule templates with named variables.
+//!
+//! A template is a rule specification referencing variables as `$NAME` or `${NAME}` (`$$` is a
+//! literal `$`). Each variable holds one or more values and a template expands into one rule per
+//! combination of the values of the variables it references, in order of appearance:
+//!
+//! ```rust
+//! use riptables::template::{self, Variables};
+//!
+//! let mut variables = Variables::new();
+//! variables.set_list("WEB_PORTS", &[80, 443]);
+//! variables.set_list("TRUSTED_NETS", &["10.0.0.0/8", "192.168.0.0/16"]);
+//!
+//! let rules = template::expand("-s $TRUSTED_NETS -p tcp --dport $WEB_PORTS -j ACCEPT", &variables).unwrap();
+//! assert_eq!(rules, vec![
+//!   "-s 10.0.0.0/8 -p tcp --dport 80 -j ACCEPT",
+//!   "-s 10.0.0.0/8 -p tcp --dport 443 -j ACCEPT",
+//!   "-s 192.168.0.0/16 -p tcp --dport 80 -j ACCEPT",
+//!   "-s 192.168.0.0/16 -p tcp --dport 443 -j ACCEPT",
+//! ]);
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+
+use crate::builder::RuleBuilder;
+use crate::error::{RIPTError, RIPTResult};
+use crate::table::{ChainName, Table};
+use crate::RIPTables;
+
+/// Values of template variables.
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+  values: HashMap<String, Vec<String>>,
+}
+
+/// A piece of a parsed template.
+enum Part {
+  Text(String),
+  Variable(String),
+}
+
+impl Variables {
+  pub fn new() -> Variables {
+    Variables::default()
+  }
+
+  /// Sets `name` to a single value.
+  pub fn set<S: ToString>(&mut self, name: &str, value: S) -> &mut Variables {
+    self.values.insert(name.to_string(), vec![value.to_string()]);
+    self
+  }
+
+  /// Sets `name` to a list of values, each producing its own rule.
+  pub fn set_list<S: ToString>(&mut self, name: &str, values: &[S]) -> &mut Variables {
+    self.values.insert(name.to_string(), values.iter().map(|value| value.to_string()).collect());
+    self
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Vec<String>> {
+    self.values.get(name)
+  }
+}
+
+/// Expands `template` into one rule per combination of its variables' values; a variable
+/// referenced twice has the same value in both places.
+/// Returns an error naming the first undefined variable.
+pub fn expand<S: AsRef<str>>(template: S, variables: &Variables) -> RIPTResult<Vec<String>> {
+  let parts = self::parse(template.as_ref())?;
+
+  let mut combinations: Vec<HashMap<&str, &str>> = vec![HashMap::new()];
+  for part in &parts {
+    if let Part::Variable(ref name) = *part {
+      if combinations.first().map(|combination| combination.contains_key(&name[..])).unwrap_or(false) {
+        continue;
+      }
+      let values = variables.get(name)
+        .ok_or_else(|| RIPTError::Template(format!("undefined variable ${}", name)))?;
+      combinations = combinations.iter()
+        .flat_map(|combination| values.iter().map(move |value| {
+          let mut combination = combination.clone();
+          combination.insert(&name[..], &value[..]);
+          combination
+        }))
+        .collect();
+    }
+  }
+  Ok(combinations.iter()
+    .map(|combination| parts.iter().map(|part| match *part {
+      Part::Text(ref text) => &text[..],
+      Part::Variable(ref name) => combination[&name[..]],
+    }).collect())
+    .collect())
+}
+
+fn parse(template: &str) -> RIPTResult<Vec<Part>> {
+  let mut parts = vec![];
+  let mut text = String::new();
+  let mut chars = template.chars().peekable();
+  while let Some(ch) = chars.next() {
+    if ch != '$' {
+      text.push(ch);
+      continue;
+    }
+    let mut name = String::new();
+    match chars.peek() {
+      Some('$') => {
+        chars.next();
+        text.push('$');
+        continue;
+      }
+      Some('{') => {
+        chars.next();
+        loop {
+          match chars.next() {
+            Some('}') => break,
+            Some(ch) => name.push(ch),
+            None => return Err(RIPTError::Template(format!("unterminated variable ${{{}", name))),
+          }
+        }
+      }
+      _ => {
+        while let Some(&ch) = chars.peek() {
+          if !(ch.is_ascii_alphanumeric() || ch == '_') {
+            break;
+          }
+          name.push(ch);
+          chars.next();
+        }
+      }
+    }
+    if name.is_empty() {
+      return Err(RIPTError::Template(format!("missing variable name in {}", template)));
+    }
+    parts.push(Part::Text(text.clone()));
+    parts.push(Part::Variable(name));
+    text.clear();
+  }
+  parts.push(Part::Text(text));
+  Ok(parts)
+}
+
+impl RuleBuilder {
+  /// Builds the rule and expands the variables used in its values.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::builder::RuleBuilder;
+  /// use riptables::template::Variables;
+  ///
+  /// let mut variables = Variables::new();
+  /// variables.set_list("WEB_PORTS", &[80, 443]);
+  /// let rules = RuleBuilder::new().protocol("tcp").dport("$WEB_PORTS").jump("ACCEPT").expand(&variables).unwrap();
+  /// assert_eq!(rules.len(), 2);
+  /// ```
+  pub fn expand(&self, variables: &Variables) -> RIPTResult<Vec<String>> {
+    self::expand(self.build(), variables)
+  }
+}
+
+impl RIPTables {
+  /// Expands `template` and appends every resulting rule to the table/chain.
+  /// Nothing is appended if a variable is undefined.
+  /// Returns `true` if all the rules are appended.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::template::Variables;
+  ///
+  /// let mut variables = Variables::new();
+  /// variables.set_list("WEB_PORTS", &[80, 443]);
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.append_template("filter", "INPUT", "-p tcp --dport $WEB_PORTS -j ACCEPT", &variables).unwrap();
+  /// ```
+  pub fn append_template<T, C>(&self, table: T, chain: C, template: &str, variables: &Variables) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let mut appended = true;
+    for rule in self::expand(template, variables)? {
+      appended &= self.append(&table, &chain, &rule[..])?;
+    }
+    Ok(appended)
+  }
+
+  /// Expands `template` and inserts the resulting rules, in order, from `position` in the table/chain.
+  /// Nothing is inserted if a variable is undefined.
+  /// Returns `true` if all the rules are inserted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::template::Variables;
+  ///
+  /// let mut variables = Variables::new();
+  /// variables.set_list("TRUSTED_NETS", &["10.0.0.0/8", "192.168.0.0/16"]);
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.insert_template("filter", "INPUT", "-s $TRUSTED_NETS -j ACCEPT", 1, &variables).unwrap();
+  /// ```
+  pub fn insert_template<T, C>(&self, table: T, chain: C, template: &str, position: i32, variables: &Variables) -> RIPTResult<bool> where T: AsRef<OsStr>, C: AsRef<OsStr> {
+    let table = Table::try_from(table.as_ref())?;
+    let chain = ChainName::try_from(chain.as_ref())?;
+    let mut inserted = true;
+    for (offset, rule) in self::expand(template, variables)?.iter().enumerate() {
+      inserted &= self.insert(&table, &chain, &rule[..], position + offset as i32)?;
+    }
+    Ok(inserted)
+  }
+}
@@ -0,0 +1,384 @@
+//! Zone-based firewalling, in the spirit of firewalld.
+//!
+//! A `Zone` is bound to interfaces and source networks, allows services and rejects, drops or
+//! accepts everything else. It is compiled into two chains of the `filter` table:
+//!
+//! | chain              | reached from                                 | rules                           |
+//! |--------------------|----------------------------------------------|---------------------------------|
+//! | `ZONE-IN-<name>`   | `INPUT`, for the interfaces and sources      | established traffic, services   |
+//! | `ZONE-FWD-<name>`  | `FORWARD`, for the interfaces and sources    | established traffic             |
+//!
+//! each ending with the default target of the zone. The jumps are tagged for the
+//! `riptables-zone` owner (see `owner`) with the name of the zone and head `INPUT` and `FORWARD`:
+//! as in firewalld, the jumps of source networks come first, then those of interfaces, each in the
+//! order of the zones. A zone without interfaces nor sources has its chains but nothing reaches
+//! them.
+//!
+//! ```rust
+//! use riptables::zone::{Service, Zone, ZoneTarget};
+//!
+//! let zone = Zone::new("public")
+//!   .interface("eth0")
+//!   .service("22/tcp".parse::<Service>().unwrap())
+//!   .target(ZoneTarget::Drop);
+//! let chains = zone.chains().unwrap();
+//! assert_eq!(chains[0].0, "ZONE-IN-public");
+//! assert_eq!(chains[0].1, vec![
+//!   "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT",
+//!   "-p tcp -m tcp --dport 22 -j ACCEPT",
+//!   "-j DROP",
+//! ]);
+//! assert_eq!(zone.jumps().unwrap()[0].spec, "-i eth0 -m comment --comment riptables-zone:public -j ZONE-IN-public");
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::builder::RuleBuilder;
+use crate::error::{RIPTError, RIPTResult};
+use crate::iptparser;
+use crate::owner::{OwnedRule, OwnerTag};
+use crate::rule::{self, Archive, RIPTRule};
+use crate::table::ChainName;
+use crate::RIPTables;
+
+/// Owner of the jumps to the zones.
+pub const ZONE_OWNER: &str = "riptables-zone";
+
+const INPUT_PREFIX: &str = "ZONE-IN-";
+const FORWARD_PREFIX: &str = "ZONE-FWD-";
+
+/// What happens to the traffic a zone does not allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneTarget {
+  Accept,
+  Drop,
+  Reject,
+}
+
+impl fmt::Display for ZoneTarget {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ZoneTarget::Accept => write!(f, "ACCEPT"),
+      ZoneTarget::Drop => write!(f, "DROP"),
+      ZoneTarget::Reject => write!(f, "REJECT"),
+    }
+  }
+}
+
+/// A protocol and a port or range of ports, written `22/tcp` or `60000-61000/udp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+  pub protocol: String,
+  pub first: u16,
+  pub last: u16,
+}
+
+impl Service {
+  pub fn tcp(port: u16) -> Service {
+    Service { protocol: "tcp".to_string(), first: port, last: port }
+  }
+
+  pub fn udp(port: u16) -> Service {
+    Service { protocol: "udp".to_string(), first: port, last: port }
+  }
+
+  /// The port or range the way `--dport` takes it.
+  fn dport(&self) -> String {
+    if self.first == self.last {
+      self.first.to_string()
+    } else {
+      format!("{}:{}", self.first, self.last)
+    }
+  }
+}
+
+impl FromStr for Service {
+  type Err = RIPTError;
+
+  fn from_str(text: &str) -> RIPTResult<Service> {
+    let invalid = || RIPTError::Invalid(format!("invalid service {:?}, expected <port>/<protocol>", text));
+    let (ports, protocol) = text.split_once('/').ok_or_else(invalid)?;
+    let (first, last) = ports.split_once('-').unwrap_or((ports, ports));
+    let (first, last): (u16, u16) = (first.parse().map_err(|_| invalid())?, last.parse().map_err(|_| invalid())?);
+    if first == 0 || first > last || !["tcp", "udp", "udplite", "sctp", "dccp"].contains(&protocol) {
+      return Err(invalid());
+    }
+    Ok(Service { protocol: protocol.to_string(), first, last })
+  }
+}
+
+impl fmt::Display for Service {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.first == self.last {
+      write!(f, "{}/{}", self.first, self.protocol)
+    } else {
+      write!(f, "{}-{}/{}", self.first, self.last, self.protocol)
+    }
+  }
+}
+
+/// A zone, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+  pub name: String,
+  pub interfaces: Vec<String>,
+  pub sources: Vec<String>,
+  pub services: Vec<Service>,
+  pub target: ZoneTarget,
+}
+
+impl Zone {
+  /// Creates a zone rejecting everything.
+  pub fn new<S: ToString>(name: S) -> Zone {
+    Zone { name: name.to_string(), interfaces: vec![], sources: vec![], services: vec![], target: ZoneTarget::Reject }
+  }
+
+  pub fn interface<S: ToString>(mut self, interface: S) -> Zone {
+    self.interfaces.push(interface.to_string());
+    self
+  }
+
+  pub fn source<S: ToString>(mut self, network: S) -> Zone {
+    self.sources.push(network.to_string());
+    self
+  }
+
+  pub fn service(mut self, service: Service) -> Zone {
+    self.services.push(service);
+    self
+  }
+
+  pub fn target(mut self, target: ZoneTarget) -> Zone {
+    self.target = target;
+    self
+  }
+
+  /// The input and forward chains of the zone.
+  /// Fails if the name does not make valid chain names.
+  pub fn chain_names(&self) -> RIPTResult<(String, String)> {
+    let valid = !self.name.is_empty() && self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+      return Err(RIPTError::Invalid(format!("invalid zone name {:?}", self.name)));
+    }
+    let input = ChainName::try_from(&format!("{}{}", INPUT_PREFIX, self.name)[..])?;
+    let forward = ChainName::try_from(&format!("{}{}", FORWARD_PREFIX, self.name)[..])?;
+    Ok((input.to_string(), forward.to_string()))
+  }
+
+  /// Returns the chains of the zone with their rules, input chain first.
+  pub fn chains(&self) -> RIPTResult<Vec<(String, Vec<String>)>> {
+    let (input, forward) = self.chain_names()?;
+    let established = RuleBuilder::new().state(&["RELATED", "ESTABLISHED"]).jump("ACCEPT").build();
+    let last = RuleBuilder::new().jump(self.target).build();
+
+    let mut input_rules = vec![established.clone()];
+    input_rules.extend(self.services.iter().map(|service| {
+      RuleBuilder::new().protocol(&service.protocol).dport(service.dport()).jump("ACCEPT").build()
+    }));
+    input_rules.push(last.clone());
+    Ok(vec![(input, input_rules), (forward, vec![established, last])])
+  }
+
+  /// Returns the tagged jumps from `INPUT` and `FORWARD` to the chains of the zone, sources
+  /// first.
+  pub fn jumps(&self) -> RIPTResult<Vec<OwnedRule>> {
+    let (input, forward) = self.chain_names()?;
+    let tag = self.tag()?;
+    let mut jumps = vec![];
+    for (chain, target) in &[("INPUT", &input), ("FORWARD", &forward)] {
+      let bindings = self.sources.iter().map(|source| RuleBuilder::new().source(source))
+        .chain(self.interfaces.iter().map(|interface| RuleBuilder::new().in_interface(interface)));
+      jumps.extend(bindings.map(|binding| OwnedRule { table: "filter", chain, spec: binding.comment(&tag).jump(target).build() }));
+    }
+    Ok(jumps)
+  }
+
+  fn tag(&self) -> RIPTResult<OwnerTag> {
+    OwnerTag::new(ZONE_OWNER, &self.name[..])
+  }
+}
+
+/// Returns the jumps of `zones` in order of precedence: the jumps of source networks of every zone,
+/// then those of interfaces, each in the order of `zones`.
+///
+/// # Example
+///
+/// ```rust
+/// use riptables::zone::{self, Zone};
+///
+/// let zones = [Zone::new("public").interface("eth0"), Zone::new("trusted").source("10.0.0.0/8")];
+/// let jumps = zone::jumps(&zones).unwrap();
+/// assert_eq!(jumps[0].spec, "-s 10.0.0.0/8 -m comment --comment riptables-zone:trusted -j ZONE-IN-trusted");
+/// ```
+pub fn jumps(zones: &[Zone]) -> RIPTResult<Vec<OwnedRule>> {
+  let mut jumps = vec![];
+  for zone in zones {
+    jumps.extend(zone.jumps()?);
+  }
+  // a stable sort keeps the order of the zones, and of the bindings in each of them
+  jumps.sort_by_key(|jump| (jump.chain != "INPUT", !self::is_source_binding(&jump.spec)));
+  Ok(jumps)
+}
+
+fn is_source_binding(spec: &str) -> bool {
+  iptparser::spec_groups(spec).iter().any(|group| group.option == "-s")
+}
+
+/// Returns the `iptables-restore` commands putting `wanted` at the head of `chain`, in order, and
+/// deleting the other zone jumps of `current`, the rules of the chain.
+fn place_jumps(chain: &str, current: &[RIPTRule], wanted: &[String]) -> Vec<String> {
+  let mut lines = vec![];
+  let mut rules: Vec<(String, bool)> = current.iter()
+    .map(|rule| (rule::normalize(rule.spec()), rule.is_owned_by(ZONE_OWNER)))
+    .collect();
+  let mut keys: Vec<(String, &String)> = vec![];
+  for spec in wanted {
+    let key = rule::normalize(spec);
+    if !keys.iter().any(|(known, _)| *known == key) {
+      keys.push((key, spec));
+    }
+  }
+
+  for index in (0..rules.len()).rev() {
+    if rules[index].1 && !keys.iter().any(|(key, _)| *key == rules[index].0) {
+      lines.push(format!("-D {} {}", chain, index + 1));
+      rules.remove(index);
+    }
+  }
+  for (index, (key, spec)) in keys.into_iter().enumerate() {
+    if rules.get(index).map(|rule| &rule.0) == Some(&key) {
+      continue;
+    }
+    // the rules before `index` are in place, a copy can only be further down
+    if let Some(found) = rules.iter().position(|rule| rule.0 == key) {
+      lines.push(format!("-D {} {}", chain, found + 1));
+      rules.remove(found);
+    }
+    lines.push(format!("-I {} {} {}", chain, index + 1, spec));
+    rules.insert(index, (key, true));
+  }
+  lines
+}
+
+impl RIPTables {
+  /// Brings the chains and jumps of `zone` in line with it: missing chains are created, chains
+  /// whose rules differ are filled again, and the jumps of an earlier version of the zone it no
+  /// longer has are deleted. The jumps of the zone keep its place among the zones already applied,
+  /// a new zone comes after them. Everything changes at once, in a single `iptables-restore` call.
+  /// Returns `true` if every rule is in place.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// use riptables::zone::{Service, Zone};
+  ///
+  /// let iptables = riptables::new(false).unwrap();
+  /// let zone = Zone::new("internal").source("10.0.0.0/8").service(Service::tcp(22)).service(Service::udp(53));
+  /// iptables.apply_zone(&zone).unwrap();
+  /// ```
+  pub fn apply_zone(&self, zone: &Zone) -> RIPTResult<bool> {
+    self.write_zones(std::slice::from_ref(zone), false)
+  }
+
+  /// Applies every zone of `zones`, in order of precedence (see `zone::jumps`), and removes the
+  /// zones missing from it.
+  /// Returns `true` if every rule is in place.
+  pub fn apply_zones(&self, zones: &[Zone]) -> RIPTResult<bool> {
+    for name in self.list_zones()? {
+      if !zones.iter().any(|zone| zone.name == name) {
+        self.remove_zone(&name)?;
+      }
+    }
+    self.write_zones(zones, true)
+  }
+
+  /// Applies `zones` in one `iptables-restore --noflush` call. With `exclusive`, `zones` are all
+  /// the zones, in order; otherwise the zones already applied keep their jumps and place.
+  fn write_zones(&self, zones: &[Zone], exclusive: bool) -> RIPTResult<bool> {
+    let mut lines = vec![];
+    for zone in zones {
+      for (chain, specs) in zone.chains()? {
+        if self.exists_chain("filter", &chain)? {
+          let current: Vec<RIPTRule> = self.list_chains("filter", &chain)?.into_iter()
+            .filter(|rule| rule.archive == Archive::Append)
+            .collect();
+          if current.len() == specs.len() && current.iter().zip(specs.iter()).all(|(rule, spec)| rule.is_spec(spec)) {
+            continue;
+          }
+        }
+        // declaring a chain flushes it, even with --noflush
+        lines.push(format!(":{} - [0:0]", chain));
+        lines.extend(specs.iter().map(|spec| format!("-A {} {}", chain, spec)));
+      }
+    }
+
+    for chain in &["INPUT", "FORWARD"] {
+      let current: Vec<RIPTRule> = self.list_chains("filter", chain)?.into_iter()
+        .filter(|rule| rule.archive == Archive::Append)
+        .collect();
+      let mut jumps = vec![];
+      for zone in zones {
+        let specs = zone.jumps()?.into_iter().filter(|jump| jump.chain == *chain).map(|jump| jump.spec);
+        jumps.extend(specs.map(|spec| (zone.name.clone(), spec)));
+      }
+      if !exclusive {
+        // the other zones keep their jumps, and every zone its place among them
+        let mut names: Vec<String> = vec![];
+        for rule in &current {
+          if let Some(tag) = rule.owner_tag().filter(|tag| tag.owner == ZONE_OWNER) {
+            if !zones.iter().any(|zone| zone.name == tag.id) {
+              jumps.push((tag.id.clone(), rule.spec()));
+            }
+            if !names.contains(&tag.id) {
+              names.push(tag.id);
+            }
+          }
+        }
+        jumps.sort_by_key(|(name, _)| names.iter().position(|known| known == name).unwrap_or(names.len()));
+      }
+      // a stable sort keeps the order of the zones, and of the bindings in each of them
+      jumps.sort_by_key(|(_, spec)| !self::is_source_binding(spec));
+      let wanted: Vec<String> = jumps.into_iter().map(|(_, spec)| spec).collect();
+      lines.extend(self::place_jumps(chain, &current, &wanted));
+    }
+
+    if lines.is_empty() {
+      return Ok(true);
+    }
+    let script = format!("*filter\n{}\nCOMMIT\n", lines.join("\n"));
+    let (code, output) = self.execute_restore(&script, true)?;
+    if code != 0 {
+      return Err(RIPTError::Stderr(output));
+    }
+    Ok(true)
+  }
+
+  /// Deletes the chains of the zone `name` and every jump to them.
+  /// Returns `true` if the zone existed and is deleted.
+  ///
+  /// # Example
+  ///
+  /// ```rust
+  /// let iptables = riptables::new(false).unwrap();
+  /// iptables.remove_zone("internal").unwrap();
+  /// ```
+  pub fn remove_zone(&self, name: &str) -> RIPTResult<bool> {
+    let (input, forward) = Zone::new(name).chain_names()?;
+    let input = self.delete_chain_recursive("filter", &input, false)?;
+    let forward = self.delete_chain_recursive("filter", &forward, false)?;
+    Ok(input || forward)
+  }
+
+  /// Lists the names of the zones of the `filter` table.
+  pub fn list_zones(&self) -> RIPTResult<Vec<String>> {
+    let mut names: Vec<String> = self.chain_names("filter")?.iter()
+      .filter_map(|chain| chain.strip_prefix(INPUT_PREFIX).or_else(|| chain.strip_prefix(FORWARD_PREFIX)))
+      .map(|name| name.to_string())
+      .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+  }
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "async")]
+
+use riptables::asynchronous::AsyncRIPTables;
+use riptables::rule::Archive;
+
+async fn riptables() -> AsyncRIPTables {
+  riptables::asynchronous::new(false).await.unwrap()
+}
+
+#[tokio::test]
+async fn async_filter() {
+  let table = "filter";
+  let name = "ASYNCFILTER";
+
+  assert!(riptables().await.new_chain(table, name).await.unwrap());
+  assert!(riptables().await.insert(table, name, "-j ACCEPT", 1).await.unwrap());
+  assert!(riptables().await.replace(table, name, "-j DROP", 1).await.unwrap());
+  assert!(riptables().await.exists(table, name, "-j DROP").await.unwrap());
+  assert!(!riptables().await.exists(table, name, "-j ACCEPT").await.unwrap());
+  assert!(riptables().await.append(table, name, "-m comment --comment \"double-quoted comment\" -j ACCEPT").await.unwrap());
+  assert!(riptables().await.exists(table, name, "-m comment --comment \"double-quoted comment\" -j ACCEPT").await.unwrap());
+
+  let rules = riptables().await.list_chains(table, name).await.unwrap();
+  assert_eq!(rules.len(), 3);
+  assert_eq!(rules[0].archive, Archive::NewChain);
+
+  assert!(riptables().await.flush_chain(table, name).await.unwrap());
+  assert!(riptables().await.exists_chain(table, name).await.unwrap());
+  assert!(riptables().await.delete_chain(table, name).await.unwrap());
+  assert!(!riptables().await.exists_chain(table, name).await.unwrap());
+}
+
+#[tokio::test]
+async fn async_get_policy() {
+  assert!(riptables().await.get_policy("filter", "INPUT").await.is_ok());
+  assert!(riptables().await.get_policy("nat", "POSTROUTING").await.is_ok());
+
+  // Wrong table
+  assert!(riptables().await.get_policy("not_existant", "_").await.is_err());
+  // Wrong chain
+  assert!(riptables().await.get_policy("filter", "_").await.is_err());
+}
+
+#[tokio::test]
+async fn async_save_and_apply() {
+  let iptables = riptables().await;
+  let saved = iptables.save(&["filter"]).await.unwrap();
+  assert!(iptables.apply(&saved, true).await.unwrap().is_empty());
+  assert!(iptables.restore(&saved).await.unwrap());
+}
+
+#[tokio::test]
+async fn async_ensure_chain_and_jump() {
+  let iptables = riptables().await;
+  let table = "filter";
+
+  assert!(iptables.ensure_chain(table, "ASYNCENSURE").await.unwrap());
+  assert!(iptables.ensure_chain(table, "ASYNCENSURE").await.unwrap());
+  assert!(iptables.ensure_jump(table, "FORWARD", "ASYNCENSURE", 1, "-p tcp --dport 8080").await.unwrap());
+  assert!(iptables.ensure_jump(table, "FORWARD", "ASYNCENSURE", 1, "-p tcp -m tcp --dport 8080").await.unwrap());
+  let rules = iptables.list_chains(table, "FORWARD").await.unwrap();
+  assert_eq!(rules.iter().filter(|rule| rule.jump == "ASYNCENSURE").count(), 1);
+
+  assert!(iptables.delete_chain_recursive(table, "ASYNCENSURE", false).await.unwrap());
+  assert!(!iptables.exists_chain(table, "ASYNCENSURE").await.unwrap());
+  assert!(iptables.supports_target("ACCEPT").await.unwrap());
+  assert!(!iptables.supports_match("-bad").await.unwrap());
+}
@@ -0,0 +1,168 @@
+#![cfg(feature = "config")]
+
+use riptables::builder::RuleBuilder;
+use riptables::config;
+use riptables::error::RIPTError;
+
+const CONFIG: &str = r#"
+[[chains]]
+name = "INPUT"
+policy = "DROP"
+
+[[chains.rules]]
+in_interface = "lo"
+jump = "ACCEPT"
+
+[[chains.rules]]
+state = ["RELATED", "ESTABLISHED"]
+jump = "ACCEPT"
+
+[[chains.rules]]
+protocol = "tcp"
+source = "!10.0.0.0/8"
+dport = "8000:8080"
+comment = "web ports"
+goto = "WEB"
+
+[[chains]]
+name = "WEB"
+
+[[chains.rules]]
+jump = "ACCEPT"
+
+[[chains]]
+table = "nat"
+name = "POSTROUTING"
+
+[[chains.rules]]
+out_interface = "eth0"
+jump = "MASQUERADE"
+"#;
+
+const YAML_CONFIG: &str = r#"
+chains:
+  - name: INPUT
+    policy: DROP
+    rules:
+      - in_interface: lo
+        jump: ACCEPT
+      - state: [RELATED, ESTABLISHED]
+        jump: ACCEPT
+      - protocol: tcp
+        source: "!10.0.0.0/8"
+        dport: "8000:8080"
+        comment: web ports
+        goto: WEB
+  - name: WEB
+    rules:
+      - jump: ACCEPT
+  - table: nat
+    name: POSTROUTING
+    rules:
+      - out_interface: eth0
+        jump: MASQUERADE
+"#;
+
+fn error_position(text: &str) -> (usize, usize) {
+  match config::load(text) {
+    Err(RIPTError::Config(err)) => (err.line, err.column),
+    other => panic!("expected a config error, got {:?}", other),
+  }
+}
+
+#[test]
+fn test_load() {
+  let ruleset = config::load(CONFIG).unwrap();
+  let filter = ruleset.table("filter").unwrap();
+  let input = filter.chain("INPUT").unwrap();
+  assert_eq!(input.policy, Some("DROP".to_string()));
+  assert_eq!(input.rules.iter().map(|rule| rule.spec()).collect::<Vec<String>>(), vec![
+    "-i lo -j ACCEPT",
+    "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT",
+    "! -s 10.0.0.0/8 -p tcp -m tcp --dport 8000:8080 -m comment --comment \"web ports\" -g WEB",
+  ]);
+  assert_eq!(input.rules[0].input.as_ref().unwrap().value, "lo");
+  assert_eq!(input.rules[2].dport, "8000:8080");
+  assert_eq!(input.rules[2].goto, "WEB");
+  assert_eq!(filter.chain("WEB").unwrap().policy, None);
+  assert_eq!(ruleset.table("nat").unwrap().chain("POSTROUTING").unwrap().rules[0].jump, "MASQUERADE");
+}
+
+#[test]
+fn test_load_yaml() {
+  assert_eq!(config::load_yaml(YAML_CONFIG).unwrap(), config::load(CONFIG).unwrap());
+
+  // syntax errors have a position, invalid values their path
+  match config::load_yaml("chains:\n  - name: INPUT\n    sourc: 1.2.3.4\n") {
+    Err(RIPTError::Config(err)) => assert_eq!((err.line, err.path), (3, None)),
+    other => panic!("expected a config error, got {:?}", other),
+  }
+  match config::load_yaml("chains:\n  - name: WEB\n    policy: DROP\n") {
+    Err(RIPTError::Config(err)) => {
+      assert_eq!((err.line, err.column), (0, 0));
+      assert_eq!(err.path.as_deref(), Some("chains[0].policy"));
+    }
+    other => panic!("expected a config error, got {:?}", other),
+  }
+  match config::load_yaml("chains:\n  - name: INPUT\n  - name: INPUT\n    rules:\n      - jump: ACCEPT\n      - protocol: tcp\n        dport: 70000\n") {
+    Err(RIPTError::Config(err)) => assert_eq!(err.to_string(), "chains[1].name: chain INPUT is declared twice in table filter"),
+    other => panic!("expected a config error, got {:?}", other),
+  }
+  match config::load_yaml("chains:\n  - name: INPUT\n    rules:\n      - jump: ACCEPT\n      - protocol: tcp\n        dport: 70000\n") {
+    Err(RIPTError::Config(err)) => assert!(err.to_string().starts_with("chains[0].rules[1].dport: invalid port 70000"), "{}", err),
+    other => panic!("expected a config error, got {:?}", other),
+  }
+}
+
+#[test]
+#[ignore = "needs /etc/services listing https and domain, run with --ignored"]
+fn test_service_ports() {
+  let ruleset = config::load("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\nprotocol = \"tcp\"\ndport = \"https\"\njump = \"ACCEPT\"\n\n[[chains.rules]]\nprotocol = \"udp\"\nsport = \"domain:1024\"\njump = \"ACCEPT\"\n").unwrap();
+  let input = ruleset.table("filter").unwrap().chain("INPUT").unwrap();
+  assert_eq!(input.rules[0].dport, "443");
+  assert_eq!(input.rules[1].sport, "53:1024");
+}
+
+#[test]
+fn test_errors() {
+  // syntax error
+  assert_eq!(error_position("[[chains]]\nname = \n"), (2, 8));
+  // unknown field
+  assert_eq!(error_position("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\nsourc = \"1.2.3.4\"\n").0, 4);
+  // policy on a user-defined chain
+  assert_eq!(error_position("[[chains]]\nname = \"WEB\"\npolicy = \"DROP\"\n"), (3, 10));
+  // invalid address
+  assert_eq!(error_position("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\nsource = \"10.0.0.300\"\njump = \"DROP\"\n"), (5, 10));
+  // port without protocol
+  assert_eq!(error_position("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\ndport = 22\n"), (5, 9));
+  // inverted port range
+  assert_eq!(error_position("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\nprotocol = \"udp\"\ndport = \"90:80\"\n"), (6, 9));
+  // unknown service
+  assert_eq!(error_position("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\nprotocol = \"tcp\"\ndport = \"no-such-service\"\n"), (6, 9));
+  // goto an undeclared chain
+  assert_eq!(error_position("[[chains]]\nname = \"INPUT\"\n\n[[chains.rules]]\ngoto = \"NOPE\"\n"), (5, 8));
+  // invalid table name
+  assert_eq!(error_position("[[chains]]\ntable = \"two words\"\nname = \"INPUT\"\n"), (2, 9));
+  // chain name iptables refuses
+  assert_eq!(error_position("[[chains]]\nname = \"A_CHAIN_NAME_LONGER_THAN_28_BYTES\"\n"), (2, 8));
+
+  // custom tables have no built-in chains
+  let ruleset = config::load("[[chains]]\ntable = \"broute\"\nname = \"BROUTING\"\n").unwrap();
+  assert!(ruleset.table("broute").unwrap().chain("BROUTING").is_some());
+  assert_eq!(error_position("[[chains]]\ntable = \"broute\"\nname = \"BROUTING\"\npolicy = \"DROP\"\n"), (4, 10));
+}
+
+#[test]
+fn test_builder() {
+  assert_eq!(RuleBuilder::new().build(), "");
+  assert_eq!(RuleBuilder::new()
+    .source("192.168.0.0/16")
+    .out_interface("!eth1")
+    .protocol("udp")
+    .sport(53)
+    .matches("-m limit --limit 5/min")
+    .jump("LOG --log-prefix dns")
+    .build(), "-s 192.168.0.0/16 ! -o eth1 -p udp -m udp --sport 53 -m limit --limit 5/min -j LOG --log-prefix dns");
+  assert_eq!(RuleBuilder::new().state(&["ESTABLISHED", "NEW", "RELATED"]).jump("ACCEPT").build(),
+    "-m conntrack --ctstate NEW,RELATED,ESTABLISHED -j ACCEPT");
+}
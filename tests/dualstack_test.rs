@@ -0,0 +1,38 @@
+use riptables::dualstack::{self, DualResult, Family};
+use riptables::error::RIPTError;
+
+#[test]
+fn test_family() {
+  let family = |spec: &str| dualstack::family(spec).unwrap();
+  assert_eq!(family("-p tcp -m tcp --dport 8000:8080 -j ACCEPT"), None);
+  assert_eq!(family("-i eth0 -m mac --mac-source 00:11:22:33:44:55 -j ACCEPT"), None);
+  assert_eq!(family("-m comment --comment \"from 10.0.0.1\" -j ACCEPT"), None);
+  assert_eq!(family("-p tcp -j REJECT --reject-with tcp-reset"), None);
+  assert_eq!(family("! -s 10.0.0.0/255.0.0.0 -j DROP"), Some(Family::V4));
+  assert_eq!(family("-m iprange --src-range 10.0.0.1-10.0.0.9 -j DROP"), Some(Family::V4));
+  assert_eq!(family("-p icmp -m icmp --icmp-type 8 -j ACCEPT"), Some(Family::V4));
+  assert_eq!(family("-j REJECT --reject-with icmp-host-prohibited"), Some(Family::V4));
+  assert_eq!(family("-p ipv6-icmp -j ACCEPT"), Some(Family::V6));
+  assert_eq!(family("-d fe80::/10 -j ACCEPT"), Some(Family::V6));
+  assert_eq!(family("-p tcp -j DNAT --to-destination [2001:db8::1]:80"), Some(Family::V6));
+  assert_eq!(family("-j REJECT --reject-with icmp6-adm-prohibited"), Some(Family::V6));
+  assert!(dualstack::family("-p icmp -s ::1 -j ACCEPT").is_err());
+}
+
+#[test]
+fn test_dual_result() {
+  let result = DualResult { v4: Some(Ok(true)), v6: None };
+  assert!(result.is_ok());
+  assert!(result.all());
+  assert!(result.get(Family::V6).is_none());
+  assert_eq!(result.into_result().unwrap(), (Some(true), None));
+
+  let result: DualResult<bool> = DualResult { v4: Some(Ok(true)), v6: Some(Err(RIPTError::Stderr("no chain".to_string()))) };
+  assert!(!result.is_ok());
+  assert!(!result.all());
+  assert_eq!(result.into_result().unwrap_err().to_string(), "no chain");
+
+  let result = DualResult { v4: Some(Ok(true)), v6: Some(Ok(false)) };
+  assert!(result.is_ok());
+  assert!(!result.all());
+}
@@ -0,0 +1,55 @@
+use riptables::forward::{PortForward, FORWARD_OWNER};
+use riptables::ruleset::Ruleset;
+
+#[test]
+fn test_rules() {
+  let forward = PortForward::new("TCP", 443, "10.0.0.5".parse().unwrap(), 8443)
+    .address("203.0.113.1".parse().unwrap())
+    .local(true)
+    .hairpin("10.0.0.0/24");
+  assert_eq!(forward.id(), "tcp/203.0.113.1/443");
+  let rules: Vec<(&str, &str, String)> = forward.rules().unwrap().into_iter().map(|rule| (rule.table, rule.chain, rule.spec)).collect();
+  let comment = "-m comment --comment riptables-forward:tcp/203.0.113.1/443";
+  let dnat = format!("-d 203.0.113.1/32 -p tcp -m tcp --dport 443 {} -j DNAT --to-destination 10.0.0.5:8443", comment);
+  assert_eq!(rules, vec![
+    ("nat", "PREROUTING", dnat.clone()),
+    ("nat", "OUTPUT", dnat),
+    ("nat", "POSTROUTING", format!("-s 10.0.0.0/24 -d 10.0.0.5/32 -p tcp -m tcp --dport 8443 {} -j MASQUERADE", comment)),
+    ("filter", "FORWARD", format!("-d 10.0.0.5/32 -p tcp -m tcp --dport 8443 -m conntrack --ctstate DNAT {} -j ACCEPT", comment)),
+  ]);
+
+  let forward = PortForward::new("udp", 53, "fd00::53".parse().unwrap(), 53).interface("eth0");
+  let rules = forward.rules().unwrap();
+  assert_eq!(rules.len(), 2);
+  assert!(rules[0].spec.ends_with("-j DNAT --to-destination [fd00::53]:53"));
+  assert!(rules[1].spec.starts_with("-d fd00::53/128 -i eth0 -p udp"));
+
+  let to = "10.0.0.5".parse().unwrap();
+  assert!(PortForward::new("icmp", 80, to, 80).rules().is_err());
+  assert!(PortForward::new("tcp", 0, to, 80).rules().is_err());
+  assert!(PortForward::new("tcp", 80, to, 80).address("2001:db8::1".parse().unwrap()).rules().is_err());
+  assert!(PortForward::new("tcp", 80, to, 80).hairpin("lan").rules().is_err());
+  assert!(PortForward::new("tcp", 80, to, 80).hairpin("10.0.0.0/24").interface("eth0").rules().is_err());
+  assert!(PortForward::new("tcp", 80, to, 80).interface("eth 0").rules().is_err());
+}
+
+#[test]
+fn test_from_rules() {
+  let ruleset = Ruleset::parse(format!("*nat
+:PREROUTING ACCEPT [0:0]
+:OUTPUT ACCEPT [0:0]
+:POSTROUTING ACCEPT [0:0]
+-A PREROUTING -i eth0 -p tcp -m tcp --dport 8080 -m comment --comment {owner}:tcp/eth0/8080 -j DNAT --to-destination 192.168.1.10:80
+-A PREROUTING -p udp -m udp --dport 53 -m addrtype --dst-type LOCAL -m comment --comment {owner}:udp/53 -j DNAT --to-destination 192.168.1.2:5353
+-A PREROUTING -p tcp -m tcp --dport 25 -j DNAT --to-destination 192.168.1.3:25
+-A OUTPUT -p udp -m udp --dport 53 -m addrtype --dst-type LOCAL -m comment --comment {owner}:udp/53 -j DNAT --to-destination 192.168.1.2:5353
+-A POSTROUTING -s 192.168.1.0/24 -d 192.168.1.2/32 -p udp -m udp --dport 5353 -m comment --comment {owner}:udp/53 -j MASQUERADE
+COMMIT
+", owner = FORWARD_OWNER)).unwrap();
+  let rules: Vec<_> = ruleset.tables[0].chains.iter().flat_map(|chain| chain.rules.clone()).collect();
+  let forwards = PortForward::from_rules(&rules);
+  assert_eq!(forwards, vec![
+    PortForward::new("tcp", 8080, "192.168.1.10".parse().unwrap(), 80).interface("eth0"),
+    PortForward::new("udp", 53, "192.168.1.2".parse().unwrap(), 5353).local(true).hairpin("192.168.1.0/24"),
+  ]);
+}
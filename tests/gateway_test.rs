@@ -0,0 +1,40 @@
+use riptables::gateway::{NatGateway, GATEWAY_OWNER};
+use riptables::ruleset::Ruleset;
+
+#[test]
+fn test_rules() {
+  let gateway = NatGateway::new("10.8.0.0/24", "wan0").lan_interface("lan0").snat("203.0.113.7".parse().unwrap());
+  assert_eq!(gateway.id(), "10.8.0.0/24@wan0");
+  let rules: Vec<(&str, &str, String)> = gateway.rules().unwrap().into_iter().map(|rule| (rule.table, rule.chain, rule.spec)).collect();
+  let comment = "-m comment --comment riptables-gateway:10.8.0.0/24@wan0";
+  assert_eq!(rules, vec![
+    ("nat", "POSTROUTING", format!("-s 10.8.0.0/24 -o wan0 {} -j SNAT --to-source 203.0.113.7", comment)),
+    ("filter", "FORWARD", format!("-s 10.8.0.0/24 -i lan0 -o wan0 {} -j ACCEPT", comment)),
+    ("filter", "FORWARD", format!("-d 10.8.0.0/24 -i wan0 -o lan0 -m conntrack --ctstate RELATED,ESTABLISHED {} -j ACCEPT", comment)),
+  ]);
+
+  assert!(NatGateway::new("lan", "eth0").rules().is_err());
+  assert!(NatGateway::new("192.168.1.0/24", "").rules().is_err());
+  assert!(NatGateway::new("192.168.1.0/24", "eth 0").rules().is_err());
+  assert!(NatGateway::new("fd00::/64", "eth0").snat("203.0.113.7".parse().unwrap()).rules().is_err());
+}
+
+#[test]
+fn test_from_rules() {
+  let gateway = NatGateway::new("192.168.1.0/24", "eth0").lan_interface("br0");
+  let other = NatGateway::new("fd00::/64", "eth0").snat("2001:db8::1".parse().unwrap());
+  let mut text = String::new();
+  for table in &["nat", "filter"] {
+    text.push_str(&format!("*{}\n", table));
+    for rule in gateway.rules().unwrap().into_iter().chain(other.rules().unwrap()).filter(|rule| rule.table == *table) {
+      text.push_str(&format!("-A {} {}\n", rule.chain, rule.spec));
+    }
+    if *table == "filter" {
+      text.push_str(&format!("-A FORWARD -m comment --comment {}:10.0.0.0/8@eth1 -j ACCEPT\n", GATEWAY_OWNER));
+    }
+    text.push_str("COMMIT\n");
+  }
+  let ruleset = Ruleset::parse(text).unwrap();
+  let rules: Vec<_> = ruleset.tables.iter().flat_map(|table| table.chains.iter()).flat_map(|chain| chain.rules.clone()).collect();
+  assert_eq!(NatGateway::from_rules(&rules), vec![gateway, other]);
+}
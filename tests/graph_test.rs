@@ -0,0 +1,80 @@
+use riptables::graph::ChainGraph;
+use riptables::ruleset::Ruleset;
+
+fn graph(text: &str) -> ChainGraph {
+  let ruleset = Ruleset::parse(text).unwrap();
+  ChainGraph::from_table(&ruleset.tables[0])
+}
+
+const FILTER: &str = "*filter
+:INPUT DROP
+:FORWARD DROP
+:OUTPUT ACCEPT
+:SERVICES -
+:SSH -
+:WEB -
+:UNUSED -
+-A INPUT -i lo -j ACCEPT
+-A INPUT -j SERVICES
+-A FORWARD -g WEB
+-A SERVICES -p tcp -m tcp --dport 22 -j SSH
+-A SERVICES -p tcp -m tcp --dport 443 -j WEB
+-A SSH -s 10.0.0.0/8 -j ACCEPT
+-A WEB -j ACCEPT
+COMMIT
+";
+
+#[test]
+fn test_queries() {
+  let graph = graph(FILTER);
+  assert_eq!(graph.chains().len(), 7);
+  assert!(graph.is_builtin("INPUT"));
+  assert!(!graph.is_builtin("SSH"));
+
+  let referrers = graph.referrers("WEB");
+  assert_eq!(referrers.len(), 2);
+  assert_eq!((&referrers[0].from[..], referrers[0].position, referrers[0].goto), ("FORWARD", 1, true));
+  assert_eq!((&referrers[1].from[..], referrers[1].position, referrers[1].goto), ("SERVICES", 2, false));
+
+  assert_eq!(graph.reachable("INPUT"), vec!["SERVICES", "SSH", "WEB"]);
+  assert_eq!(graph.reachable("OUTPUT"), Vec::<String>::new());
+  assert_eq!(graph.orphans(), vec!["UNUSED"]);
+  assert!(graph.cycles().is_empty());
+}
+
+#[test]
+fn test_cycles() {
+  let graph = graph("*filter
+:INPUT ACCEPT
+:A -
+:B -
+:C -
+:SELF -
+-A INPUT -j A
+-A A -j B
+-A B -j C
+-A C -j A
+-A SELF -j SELF
+COMMIT
+");
+  assert_eq!(graph.cycles(), vec![vec!["A", "B", "C"], vec!["SELF"]]);
+  assert_eq!(graph.reachable("A"), vec!["B", "C"]);
+}
+
+#[test]
+fn test_dot() {
+  let graph = graph("*filter
+:INPUT ACCEPT
+:SSH -
+-A INPUT -p tcp -m tcp --dport 22 -j SSH
+-A INPUT -g SSH
+COMMIT
+");
+  assert_eq!(graph.to_dot(), "digraph \"filter\" {
+  \"INPUT\" [shape=box];
+  \"SSH\";
+  \"INPUT\" -> \"SSH\" [label=\"1\"];
+  \"INPUT\" -> \"SSH\" [label=\"2\", style=dashed];
+}
+");
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use riptables::builder::RuleBuilder;
+use riptables::ipset::{self, IPSet};
+use riptables::rule;
+
+#[test]
+fn test_parse_list() {
+  let sets = ipset::parse_list("Name: allow
+Type: hash:ip
+Revision: 4
+Header: family inet hashsize 1024 maxelem 65536 timeout 300
+Size in memory: 168
+References: 2
+Number of entries: 2
+Members:
+10.0.0.1 timeout 120
+10.0.0.2 timeout 250
+
+Name: empty
+Type: hash:net,port
+Revision: 7
+Header: family inet6 hashsize 1024 maxelem 65536
+Size in memory: 1240
+References: 0
+Number of entries: 0
+Members:
+");
+  assert_eq!(sets.len(), 2);
+  assert_eq!(sets[0].name, "allow");
+  assert_eq!(sets[0].header, "family inet hashsize 1024 maxelem 65536 timeout 300");
+  assert_eq!(sets[0].references, 2);
+  assert_eq!(sets[0].members, vec!["10.0.0.1 timeout 120", "10.0.0.2 timeout 250"]);
+  assert_eq!(sets[1].kind, "hash:net,port");
+  assert!(sets[1].members.is_empty());
+  assert!(ipset::parse_list("").is_empty());
+}
+
+#[test]
+fn test_replace_script() {
+  let name = "a".repeat(31);
+  let swap = format!("{}-swap1f-0", "a".repeat(21));
+  let script = ipset::replace_script(&name, &swap, "hash:net", "", &["192.0.2.0/24"]).unwrap();
+  assert_eq!(script, format!("create {name} hash:net\ncreate {swap} hash:net\nflush {swap}\nadd {swap} 192.0.2.0/24\nswap {swap} {name}\ndestroy {swap}\n", name = name, swap = swap));
+  assert!(ipset::replace_script("allow", "allow-swap", "hash:ip", "", &["10.0.0.1\ndestroy other"]).is_err());
+}
+
+#[test]
+fn test_invalid_names() {
+  // ipset is never started for invalid names
+  let ipset = IPSet { cmd: "/nonexistent/ipset" };
+  for name in &["", "-exist", "two words", &"a".repeat(32)[..]] {
+    assert!(ipset.create(*name, "hash:ip", "").is_err(), "{}", name);
+    assert!(ipset.add(*name, "10.0.0.1").is_err(), "{}", name);
+  }
+  assert!(ipset.save(&["ok", "not ok"]).is_err());
+  assert!(ipset.replace("allow", "hash:ip", "", &["10.0.0.1\r"]).is_err());
+}
+
+#[test]
+fn test_match_set() {
+  let rule = RuleBuilder::new().protocol("tcp").match_set("web", "dst,dst").jump("ACCEPT").build();
+  assert_eq!(rule, "-p tcp -m set --match-set web dst,dst -j ACCEPT");
+  assert_eq!(rule::normalize(&rule), rule::normalize("--protocol tcp --match set --match-set web dst,dst --jump ACCEPT"));
+  assert_eq!(RuleBuilder::new().match_set("!allow", "src").build(), "-m set ! --match-set allow src");
+}
+
+#[test]
+fn test_failed_replace() {
+  // a stand-in for ipset logging its arguments, failing every restore and knowing no set
+  let dir = std::env::temp_dir().join(format!("riptables-ipset-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let log = dir.join("log");
+  let cmd = dir.join("ipset");
+  fs::write(&cmd, format!("#!/bin/sh\necho \"$@\" >> {}\ncase \"$2\" in restore) cat > /dev/null; exit 1;; esac\n[ \"$1\" = destroy ]\n", log.display())).unwrap();
+  fs::set_permissions(&cmd, fs::Permissions::from_mode(0o755)).unwrap();
+
+  let ipset = IPSet { cmd: Box::leak(cmd.to_string_lossy().into_owned().into_boxed_str()) };
+  assert!(!ipset.replace("allow", "hash:ip", "", &["not an address"]).unwrap());
+  let calls = fs::read_to_string(&log).unwrap();
+  let calls: Vec<&str> = calls.lines().collect();
+  assert_eq!(calls.len(), 3, "{:?}", calls);
+  let swap = calls[0].strip_prefix("-name list ").unwrap();
+  assert!(swap.starts_with("allow-swap"));
+  assert_eq!(calls[1], "-exist restore");
+  assert_eq!(calls[2], format!("destroy {}", swap));
+  fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,74 @@
+use riptables::lint::{self, LintKind};
+use riptables::ruleset::Ruleset;
+
+const SAVED: &str = "*filter
+:INPUT DROP [0:0]
+:EMPTY - [0:0]
+:WEB - [0:0]
+-A INPUT -i lo -j ACCEPT
+-A INPUT -s 10.0.0.0/8 -p tcp -m tcp --dport 1:1024 -j ACCEPT
+-A INPUT -s 10.1.0.0/16 -p tcp -m tcp --dport 22 -m comment --comment ssh -j DROP
+-A INPUT -s 10.1.0.0/16 -p udp -m udp --dport 53 -j ACCEPT
+-A INPUT -i lo -j ACCEPT
+-A INPUT -p tcp -m tcp --dport 80 -m limit --limit 5/min -j LOG
+-A INPUT -p tcp -m tcp --dport 80 -j WEB
+-A INPUT -j EMPTY
+-A INPUT -i eth+ -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A INPUT -i eth0 -m conntrack --ctstate ESTABLISHED -j ACCEPT
+-A INPUT ! -s 192.168.0.0/16 -p tcp -j REJECT
+-A INPUT -s 10.0.0.0/8 -p tcp -m tcp --dport 8080 -j ACCEPT
+-A INPUT -s 192.168.1.0/24 -p tcp -m tcp --dport 8080 -j ACCEPT
+-A WEB -m limit --limit 5/min -j ACCEPT
+-A WEB -p tcp -m tcp --dport 80 -j ACCEPT
+COMMIT
+";
+
+#[test]
+fn test_lint() {
+  let ruleset = Ruleset::parse(SAVED).unwrap();
+  let lints = lint::lint_ruleset(&ruleset);
+  let found: Vec<(&str, usize, &LintKind)> = lints.iter().map(|lint| (&lint.chain[..], lint.position, &lint.kind)).collect();
+  assert_eq!(found, vec![
+    ("INPUT", 3, &LintKind::Shadowed { by: 2 }),
+    ("INPUT", 5, &LintKind::Duplicate { of: 1 }),
+    ("INPUT", 8, &LintKind::EmptyChain { chain: "EMPTY".to_string() }),
+    ("INPUT", 10, &LintKind::Shadowed { by: 9 }),
+    ("INPUT", 12, &LintKind::Shadowed { by: 11 }),
+  ]);
+  assert_eq!(lints[1].to_string(), "filter INPUT rule 5 (-i lo -j ACCEPT): duplicate of rule 1");
+}
+
+#[test]
+fn test_lint_chain() {
+  let ruleset = Ruleset::parse(SAVED).unwrap();
+  let filter = ruleset.table("filter").unwrap();
+  assert!(lint::lint_chain(filter, "WEB").is_empty());
+  assert!(lint::lint_chain(filter, "MISSING").is_empty());
+
+  let ruleset = Ruleset::parse("*filter
+:INPUT ACCEPT [0:0]
+-A INPUT -p all -j DROP
+-A INPUT -s 10.0.0.1/32 -j ACCEPT
+-A INPUT -s 10.0.0.1 -j ACCEPT
+COMMIT
+").unwrap();
+  let lints = lint::lint_table(ruleset.table("filter").unwrap());
+  assert_eq!(lints.len(), 2);
+  assert_eq!(lints[0].kind, LintKind::Shadowed { by: 1 });
+  // written differently, normalized the same
+  assert_eq!(lints[1].kind, LintKind::Duplicate { of: 2 });
+}
+
+#[test]
+fn test_lint_whole_values() {
+  let ruleset = Ruleset::parse("*filter
+:INPUT ACCEPT [0:0]
+-A INPUT -p tcp -m tcp --tcp-flags SYN,ACK,FIN SYN -j DROP
+-A INPUT -p tcp -m tcp --tcp-flags SYN,ACK ACK -j DROP
+-A INPUT -p tcp -m tcp --tcp-flags SYN,ACK ACK -j DROP
+COMMIT
+").unwrap();
+  let lints = lint::lint_table(ruleset.table("filter").unwrap());
+  assert_eq!(lints.len(), 1);
+  assert_eq!((lints[0].position, &lints[0].kind), (3, &LintKind::Duplicate { of: 2 }));
+}
@@ -0,0 +1,88 @@
+use riptables::lockout::{self, Management};
+use riptables::packet::Verdict;
+use riptables::ruleset::Ruleset;
+
+fn management() -> Management {
+  Management::new("198.51.100.7".parse().unwrap(), 22).interface("eth0")
+}
+
+#[test]
+fn test_safe() {
+  let ruleset = Ruleset::parse("*filter
+:INPUT DROP [0:0]
+:OUTPUT DROP [0:0]
+-A INPUT -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A INPUT -i eth0 -p tcp -m tcp --dport 22 -j ACCEPT
+-A OUTPUT -p tcp -m tcp --sport 22 -j ACCEPT
+COMMIT
+").unwrap();
+  let report = lockout::check(&ruleset, &management());
+  assert!(report.is_safe());
+  assert_eq!(report.new_connection.rule.as_ref().unwrap().position, 2);
+  assert_eq!(report.established.rule.as_ref().unwrap().position, 1);
+
+  assert!(lockout::check(&Ruleset::new(), &management()).is_safe());
+}
+
+#[test]
+fn test_locked_out() {
+  // replies are dropped by the OUTPUT policy
+  let ruleset = Ruleset::parse("*filter
+:INPUT DROP [0:0]
+:OUTPUT DROP [0:0]
+-A INPUT -p tcp -m tcp --dport 22 -j ACCEPT
+COMMIT
+").unwrap();
+  let report = lockout::check(&ruleset, &management());
+  assert!(report.is_locked_out());
+  assert_eq!(report.new_connection.verdict, Verdict::Accept);
+  assert_eq!(report.reply.verdict, Verdict::Drop);
+  assert!(report.to_string().contains("reply: DROP by policy"));
+
+  // only new connections are cut
+  let ruleset = Ruleset::parse("*filter
+:INPUT DROP [0:0]
+-A INPUT -m conntrack --ctstate ESTABLISHED -j ACCEPT
+COMMIT
+").unwrap();
+  let report = lockout::check(&ruleset, &management());
+  assert!(report.is_locked_out());
+  assert_eq!(report.established.verdict, Verdict::Accept);
+  assert_eq!(report.new_connection.verdict, Verdict::Drop);
+}
+
+#[test]
+fn test_uncertain() {
+  let ruleset = Ruleset::parse("*filter
+:INPUT ACCEPT [0:0]
+-A INPUT -p tcp -m tcp --dport 22 -m recent --update --seconds 60 --hitcount 4 -j DROP
+COMMIT
+").unwrap();
+  let report = lockout::check(&ruleset, &management());
+  assert!(!report.is_locked_out());
+  assert!(report.is_uncertain());
+  assert!(!report.is_safe());
+}
+
+#[test]
+fn test_current_policies() {
+  let current = Ruleset::parse("*filter
+:INPUT DROP [0:0]
+:OUTPUT ACCEPT [0:0]
+-A INPUT -p tcp -m tcp --dport 22 -j ACCEPT
+COMMIT
+").unwrap();
+  // the INPUT policy is left out, apply keeps DROP
+  let desired = Ruleset::parse("*filter
+-A INPUT -p tcp -m tcp --dport 80 -j ACCEPT
+COMMIT
+").unwrap();
+  assert!(lockout::check(&desired, &management()).is_safe());
+  let report = lockout::check_with(&desired, &current, &management());
+  assert!(report.is_locked_out());
+  assert_eq!(report.new_connection.verdict, Verdict::Drop);
+
+  // without a filter table, the current one stays
+  assert!(lockout::check_with(&Ruleset::new(), &current, &management()).is_safe());
+  assert!(lockout::check_with(&Ruleset::new(), &Ruleset::new(), &management()).is_safe());
+}
@@ -0,0 +1,34 @@
+use riptables::owner::OwnerTag;
+use riptables::ruleset::Ruleset;
+
+#[test]
+fn test_tag() {
+  let tag = OwnerTag::new("myservice", "web").unwrap();
+  assert_eq!(tag.to_string(), "myservice:web");
+  assert_eq!(tag.apply("-p tcp --dport 80 -j ACCEPT"), "-p tcp --dport 80 -m comment --comment myservice:web -j ACCEPT");
+  assert_eq!(tag.apply("-m comment --comment \"a b\" --goto WEB"), "-m comment --comment \"a b\" -m comment --comment myservice:web --goto WEB");
+  assert_eq!(tag.apply("-s 10.0.0.0/8"), "-s 10.0.0.0/8 -m comment --comment myservice:web");
+
+  assert_eq!(OwnerTag::parse("myservice:web:1"), Some(OwnerTag::new("myservice", "web:1").unwrap()));
+  assert_eq!(OwnerTag::parse("no tag here"), None);
+  assert!(OwnerTag::new("", "web").is_err());
+  assert!(OwnerTag::new("my:service", "web").is_err());
+  assert!(OwnerTag::new("myservice", "web front").is_err());
+}
+
+#[test]
+fn test_owner_tag() {
+  let ruleset = Ruleset::parse("*filter
+:INPUT ACCEPT [0:0]
+-A INPUT -p tcp -m tcp --dport 80 -m comment --comment myservice:web -j ACCEPT
+-A INPUT -m comment --comment \"ssh access\" -m comment --comment other:ssh -j ACCEPT
+-A INPUT -j DROP
+COMMIT
+").unwrap();
+  let rules = &ruleset.table("filter").unwrap().chain("INPUT").unwrap().rules;
+  assert_eq!(rules[0].owner_tag(), Some(OwnerTag::new("myservice", "web").unwrap()));
+  assert!(rules[0].is_owned_by("myservice"));
+  assert_eq!(rules[1].owner_tag(), Some(OwnerTag::new("other", "ssh").unwrap()));
+  assert!(!rules[1].is_owned_by("myservice"));
+  assert_eq!(rules[2].owner_tag(), None);
+}
@@ -0,0 +1,135 @@
+use riptables::packet::{self, Match, Packet, Path, Verdict};
+use riptables::ruleset::Ruleset;
+
+const SAVED: &str = "*filter
+:INPUT DROP [0:0]
+:FORWARD ACCEPT [0:0]
+:OUTPUT ACCEPT [0:0]
+:SERVICES - [0:0]
+:TRUSTED - [0:0]
+-A INPUT -i lo -j ACCEPT
+-A INPUT -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A INPUT -s 192.168.0.0/255.255.0.0 -g TRUSTED
+-A INPUT -j SERVICES
+-A INPUT -p tcp -m tcp --dport 25 -m limit --limit 5/min -j ACCEPT
+-A SERVICES -p tcp -m multiport --dports 80,443,8000:8080 -j ACCEPT
+-A SERVICES ! -s 10.0.0.0/8 -p tcp -m tcp --dport 22 -j REJECT
+-A SERVICES -p tcp -m tcp --dport 22 -j ACCEPT
+-A TRUSTED -i eth+ -j RETURN
+-A TRUSTED -j ACCEPT
+COMMIT
+";
+
+fn ip(address: &str) -> std::net::IpAddr {
+  address.parse().unwrap()
+}
+
+#[test]
+fn test_matches_spec() {
+  let packet = Packet::new("tcp").source(ip("10.1.2.3")).dport(22).in_interface("eth0").state("NEW");
+  assert_eq!(packet::matches_spec("-s 10.0.0.0/8 -p tcp -m tcp --dport 22 -j ACCEPT", &packet), Match::Yes);
+  assert_eq!(packet::matches_spec("! -s 10.0.0.0/8 -j ACCEPT", &packet), Match::No);
+  assert_eq!(packet::matches_spec("-p udp -j ACCEPT", &packet), Match::No);
+  assert_eq!(packet::matches_spec("-p 6 -j ACCEPT", &packet), Match::Yes);
+  assert_eq!(packet::matches_spec("-i eth+ --dport 1:1024 -j ACCEPT", &packet), Match::Yes);
+  assert_eq!(packet::matches_spec("-d 10.0.0.1 -j ACCEPT", &packet), Match::Maybe);
+  assert_eq!(packet::matches_spec("-m recent --rcheck -j DROP", &packet), Match::Maybe);
+  assert_eq!(packet::matches_spec("-m recent --rcheck -p udp -j DROP", &packet), Match::No);
+  assert_eq!(packet::matches_spec("-m state --state ESTABLISHED -j ACCEPT", &packet), Match::No);
+  assert_eq!(packet::matches_spec("-s fd00::/8 -j ACCEPT", &packet), Match::No);
+  assert_eq!(packet::matches_spec("-j REJECT --reject-with tcp-reset", &packet), Match::Yes);
+}
+
+#[test]
+fn test_evaluate() {
+  let ruleset = Ruleset::parse(SAVED).unwrap();
+  let filter = ruleset.table("filter").unwrap();
+  let web = Packet::new("tcp").source(ip("203.0.113.1")).dport(8080).in_interface("eth0").state("NEW");
+
+  let evaluation = packet::evaluate(filter, "INPUT", &web);
+  assert_eq!(evaluation.verdict, Verdict::Accept);
+  assert_eq!(evaluation.rule.as_ref().map(|rule| (&rule.chain[..], rule.position)), Some(("SERVICES", 1)));
+
+  let ssh = web.clone().dport(22);
+  assert_eq!(packet::evaluate(filter, "INPUT", &ssh).verdict, Verdict::Reject);
+  assert_eq!(packet::evaluate(filter, "INPUT", &ssh.clone().source(ip("10.0.0.1"))).verdict, Verdict::Accept);
+  assert_eq!(packet::evaluate(filter, "INPUT", &ssh.clone().state("ESTABLISHED")).verdict, Verdict::Accept);
+
+  // TRUSTED returns to the caller of INPUT for eth interfaces, that is the policy
+  let lan = ssh.clone().source(ip("192.168.1.1"));
+  let evaluation = packet::evaluate(filter, "INPUT", &lan);
+  assert_eq!(evaluation.verdict, Verdict::Drop);
+  assert_eq!(evaluation.rule, None);
+  assert_eq!(packet::evaluate(filter, "INPUT", &lan.in_interface("wlan0")).verdict, Verdict::Accept);
+
+  let smtp = web.dport(25);
+  let evaluation = packet::evaluate(filter, "INPUT", &smtp);
+  assert_eq!(evaluation.verdict, Verdict::Drop);
+  assert_eq!(evaluation.uncertain.len(), 1);
+  assert_eq!(evaluation.uncertain[0].position, 5);
+
+  assert_eq!(packet::evaluate(filter, "SERVICES", &smtp).verdict, Verdict::Return);
+  assert_eq!(packet::evaluate(filter, "OUTPUT", &smtp.reply()).verdict, Verdict::Accept);
+}
+
+const NAT: &str = "*raw
+:PREROUTING ACCEPT [0:0]
+:OUTPUT ACCEPT [0:0]
+-A PREROUTING -p udp -j NOTRACK
+COMMIT
+*nat
+:PREROUTING ACCEPT [0:0]
+:INPUT ACCEPT [0:0]
+:OUTPUT ACCEPT [0:0]
+:POSTROUTING ACCEPT [0:0]
+-A PREROUTING -i eth0 -p tcp -m tcp --dport 443 -j DNAT --to-destination 10.0.0.2:8443
+-A PREROUTING -i eth0 -p tcp -m tcp --dport 80 -j REDIRECT --to-ports 8080
+-A POSTROUTING -o eth0 -j MASQUERADE
+COMMIT
+*filter
+:INPUT DROP [0:0]
+:FORWARD DROP [0:0]
+:OUTPUT ACCEPT [0:0]
+-A INPUT -p tcp -m tcp --dport 8080 -j ACCEPT
+-A FORWARD -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A FORWARD -d 10.0.0.2/32 -p tcp -m tcp --dport 8443 -j LOG
+-A FORWARD -d 10.0.0.2/32 -p tcp -m tcp --dport 8443 -j ACCEPT
+COMMIT
+";
+
+#[test]
+fn test_trace() {
+  let ruleset = Ruleset::parse(NAT).unwrap();
+  let https = Packet::new("tcp").source(ip("10.0.0.5")).dport(443).in_interface("eth0").out_interface("eth1").state("NEW");
+
+  let trace = packet::trace(&ruleset, Path::Forward, &https);
+  assert_eq!(trace.verdict, Verdict::Accept);
+  let steps: Vec<(&str, &str)> = trace.steps.iter().map(|step| (&step.table[..], &step.chain[..])).collect();
+  assert_eq!(steps, vec![("raw", "PREROUTING"), ("nat", "PREROUTING"), ("filter", "FORWARD"), ("nat", "POSTROUTING")]);
+  assert_eq!(trace.packet.destination, Some(ip("10.0.0.2")));
+  assert_eq!(trace.packet.dport, Some(8443));
+  let hits: Vec<(&str, &str, usize)> = trace.hits().iter().map(|(table, rule)| (*table, &rule.chain[..], rule.position)).collect();
+  assert_eq!(hits, vec![("nat", "PREROUTING", 1), ("filter", "FORWARD", 2), ("filter", "FORWARD", 3)]);
+  assert!(!trace.is_uncertain());
+
+  // nat only sees new connections
+  let trace = packet::trace(&ruleset, Path::Forward, &https.clone().state("ESTABLISHED"));
+  assert_eq!(trace.steps.len(), 2);
+  assert_eq!(trace.packet.dport, Some(443));
+
+  let http = https.clone().dport(80);
+  let trace = packet::trace(&ruleset, Path::Input, &http);
+  assert_eq!(trace.verdict, Verdict::Accept);
+  assert_eq!(trace.packet.dport, Some(8080));
+
+  let trace = packet::trace(&ruleset, Path::Input, &https.dport(22));
+  assert_eq!(trace.verdict, Verdict::Drop);
+  assert_eq!(trace.steps.last().unwrap().table, "filter");
+  assert!(trace.to_string().ends_with("verdict: DROP"));
+
+  let out = Packet::new("tcp").destination(ip("198.51.100.1")).dport(443).out_interface("eth0").state("NEW");
+  let trace = packet::trace(&ruleset, Path::Output, &out);
+  assert_eq!(trace.verdict, Verdict::Accept);
+  assert_eq!(trace.steps.last().unwrap().evaluation.verdict, Verdict::Other("MASQUERADE".to_string()));
+  assert_eq!(trace.packet.source, None);
+}
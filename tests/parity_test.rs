@@ -0,0 +1,69 @@
+use riptables::dualstack::Family;
+use riptables::parity::{self, Difference};
+use riptables::ruleset::Ruleset;
+
+#[test]
+fn test_neutral() {
+  assert_eq!(parity::neutral("-s 10.0.0.0/8 -d 192.168.1.1 -p tcp -j ACCEPT"), "-s <address> -d <address> -p tcp -j ACCEPT");
+  assert_eq!(parity::neutral("-p icmp -m icmp --icmp-type 3 -j ACCEPT"), "-p icmp --icmp-type destination-unreachable -j ACCEPT");
+  assert_eq!(parity::neutral("-p ipv6-icmp -m icmp6 --icmpv6-type 2 -j ACCEPT"), "-p icmp --icmp-type packet-too-big -j ACCEPT");
+  assert_eq!(
+    parity::neutral("-p tcp -j REJECT --reject-with icmp-port-unreachable"),
+    parity::neutral("-p tcp -j REJECT --reject-with icmp6-port-unreachable"),
+  );
+  assert_eq!(
+    parity::neutral("-p tcp -j DNAT --to-destination 192.168.1.10:80"),
+    parity::neutral("-p tcp -j DNAT --to-destination [fd00::10]:80"),
+  );
+  assert_eq!(parity::neutral("-m comment --comment \"10.0.0.1\" -j DROP"), "-m comment --comment 10.0.0.1 -j DROP");
+}
+
+#[test]
+fn test_compare() {
+  let v4 = Ruleset::parse("*filter
+:INPUT DROP [0:0]
+:FORWARD DROP [0:0]
+:WEB - [0:0]
+-A INPUT -p icmp -m icmp --icmp-type 8 -j ACCEPT
+-A INPUT -p tcp -m tcp --dport 22 -j ACCEPT
+-A INPUT -p tcp -m tcp --dport 80 -j WEB
+-A INPUT -p tcp -m tcp --dport 80 -j WEB
+-A WEB -j ACCEPT
+COMMIT
+*nat
+:POSTROUTING ACCEPT [0:0]
+-A POSTROUTING -o eth0 -j MASQUERADE
+COMMIT
+").unwrap();
+  let v6 = Ruleset::parse("*filter
+:INPUT DROP [0:0]
+:FORWARD ACCEPT [0:0]
+:SSH - [0:0]
+-A INPUT -p tcp -m tcp --dport 80 -j WEB
+-A INPUT -p ipv6-icmp -m icmp6 --icmpv6-type 128 -j ACCEPT
+-A INPUT -p tcp -m tcp --dport 2222 -j ACCEPT
+COMMIT
+").unwrap();
+
+  let differences = parity::compare(&v4, &v6);
+  let rule = |missing_in, position: usize, spec: &str| Difference::Rule {
+    table: "filter".to_string(),
+    chain: "INPUT".to_string(),
+    missing_in,
+    position,
+    spec: spec.to_string(),
+  };
+  assert_eq!(differences, vec![
+    rule(Family::V6, 2, "-p tcp -m tcp --dport 22 -j ACCEPT"),
+    rule(Family::V6, 4, "-p tcp -m tcp --dport 80 -j WEB"),
+    rule(Family::V4, 3, "-p tcp -m tcp --dport 2222 -j ACCEPT"),
+    Difference::Policy { table: "filter".to_string(), chain: "FORWARD".to_string(), v4: Some("DROP".to_string()), v6: Some("ACCEPT".to_string()) },
+    Difference::Chain { table: "filter".to_string(), chain: "WEB".to_string(), missing_in: Family::V6 },
+    Difference::Chain { table: "filter".to_string(), chain: "SSH".to_string(), missing_in: Family::V4 },
+    Difference::Chain { table: "nat".to_string(), chain: "POSTROUTING".to_string(), missing_in: Family::V6 },
+  ]);
+  assert_eq!(differences[0].to_string(), "filter INPUT: rule 2 of IPv4 missing in IPv6: -p tcp -m tcp --dport 22 -j ACCEPT");
+  assert_eq!(differences[3].to_string(), "filter FORWARD: policy DROP in IPv4, ACCEPT in IPv6");
+  assert_eq!(differences[4].to_string(), "filter WEB: chain missing in IPv6");
+  assert!(parity::compare(&v4, &v4).is_empty());
+}
@@ -0,0 +1,63 @@
+use riptables::query::Query;
+use riptables::ruleset::Ruleset;
+
+const SAVED: &str = "*nat
+:PREROUTING ACCEPT [0:0]
+-A PREROUTING -i eth0 -p tcp -m tcp --dport 8080 -m comment --comment \"port forward\" -j DNAT --to-destination 192.168.1.10:80
+COMMIT
+*filter
+:INPUT DROP [0:0]
+:FORWARD DROP [0:0]
+:WEB - [0:0]
+-A INPUT -i lo -j ACCEPT
+-A INPUT -s 10.0.0.0/8 -p tcp -m tcp --dport 22 -m comment --comment \"admin ssh\" -j ACCEPT
+-A INPUT -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A INPUT -p tcp -m multiport --dports 80,443,8000:8100 -j WEB
+-A INPUT -p tcp -m tcp ! --dport 22 -j DROP
+-A FORWARD -i eth+ -o ppp0 -j ACCEPT
+-A FORWARD -d 2001:db8::/32 -j DROP
+-A WEB -s 192.168.0.0/16 -g DROPPED
+COMMIT
+";
+
+fn positions(query: &str) -> Vec<(String, usize)> {
+  let ruleset = Ruleset::parse(SAVED).unwrap();
+  Query::parse(query).unwrap().select(&ruleset).into_iter()
+    .map(|found| (found.rule.chain.clone(), found.position))
+    .collect()
+}
+
+#[test]
+fn test_select() {
+  let at = |chain: &str, position: usize| (chain.to_string(), position);
+  assert_eq!(positions("").len(), 9);
+  assert_eq!(positions("table=filter chain=INPUT").len(), 5);
+  assert_eq!(positions("target=ACCEPT table=filter"), vec![at("INPUT", 1), at("INPUT", 2), at("INPUT", 3), at("FORWARD", 1)]);
+  assert_eq!(positions("target=DROPPED"), vec![at("WEB", 1)]);
+  assert_eq!(positions("port=22"), vec![at("INPUT", 2)]);
+  assert_eq!(positions("port=8050"), vec![at("INPUT", 4)]);
+  assert_eq!(positions("proto=TCP port=8080"), vec![at("PREROUTING", 1), at("INPUT", 4)]);
+  assert_eq!(positions("addr=10.20.30.40"), vec![at("INPUT", 2)]);
+  assert_eq!(positions("addr=2001:db8::1"), vec![at("FORWARD", 2)]);
+  assert_eq!(positions("iface=eth1"), vec![at("FORWARD", 1)]);
+  assert_eq!(positions("iface=ppp0"), vec![at("FORWARD", 1)]);
+  assert_eq!(positions("module=conntrack"), vec![at("INPUT", 3)]);
+  assert_eq!(positions("comment=\"port forward\""), vec![at("PREROUTING", 1)]);
+  assert_eq!(positions("comment=ssh chain=FORWARD"), vec![]);
+}
+
+#[test]
+fn test_parse() {
+  let query = Query::parse("table=filter  iface=eth0 address=10.0.0.1 match=tcp").unwrap();
+  assert_eq!(query, Query::new().table("filter").interface("eth0").address("10.0.0.1".parse().unwrap()).module("tcp"));
+  assert!(Query::parse("port=no-such-service").is_err());
+  assert!(Query::parse("port=70000").is_err());
+  assert!(Query::parse("addr=10.0.0.0/8").is_err());
+  assert!(Query::parse("comment=\"open").is_err());
+  assert!(Query::parse("chain").is_err());
+  assert!(Query::parse("chain=").is_err());
+
+  let ruleset = Ruleset::parse(SAVED).unwrap();
+  let found = Query::parse("port=22").unwrap().select(&ruleset);
+  assert_eq!(found[0].to_string(), "filter INPUT 2: -s 10.0.0.0/8 -p tcp -m tcp --dport 22 -m comment --comment \"admin ssh\" -j ACCEPT");
+}
@@ -1,4 +1,5 @@
 use std::panic;
+use std::time::Duration;
 
 use riptables::RIPTables;
 use riptables::rule::Archive;
@@ -144,3 +145,125 @@ fn test_set_policy() {
 }
 
 
+
+#[test]
+fn test_delete_chain_recursive() {
+  let table = "filter";
+
+  assert!(riptables().new_chain(table, "RECPARENT").unwrap());
+  assert!(riptables().new_chain(table, "RECCHILD").unwrap());
+  assert!(riptables().new_chain(table, "RECSHARED").unwrap());
+  assert!(riptables().append(table, "FORWARD", "-j RECPARENT").unwrap());
+  assert!(riptables().append(table, "RECPARENT", "-j RECCHILD").unwrap());
+  assert!(riptables().append(table, "RECPARENT", "-j RECSHARED").unwrap());
+  assert!(riptables().append(table, "FORWARD", "-j RECSHARED").unwrap());
+
+  assert!(riptables().delete_chain_recursive(table, "RECPARENT", true).unwrap());
+  assert!(!riptables().exists_chain(table, "RECPARENT").unwrap());
+  assert!(!riptables().exists_chain(table, "RECCHILD").unwrap());
+  assert!(!riptables().exists(table, "FORWARD", "-j RECPARENT").unwrap());
+  // Still referenced from FORWARD
+  assert!(riptables().exists_chain(table, "RECSHARED").unwrap());
+
+  assert!(riptables().delete_chain_recursive(table, "RECSHARED", false).unwrap());
+  assert!(!riptables().exists_chain(table, "RECSHARED").unwrap());
+  assert!(riptables().delete_chain_recursive(table, "FORWARD", false).is_err());
+}
+
+#[test]
+fn test_ensure_chain_and_jump() {
+  let table = "filter";
+
+  assert!(riptables().ensure_chain(table, "ENSURECHAIN").unwrap());
+  assert!(riptables().ensure_chain(table, "ENSURECHAIN").unwrap());
+
+  assert!(riptables().append(table, "FORWARD", "-j ACCEPT").unwrap());
+  assert!(riptables().ensure_jump(table, "FORWARD", "ENSURECHAIN", 1, "--protocol tcp --dport 8080").unwrap());
+  assert!(riptables().ensure_jump(table, "FORWARD", "ENSURECHAIN", 1, "-p tcp -m tcp --dport 8080").unwrap());
+  let rules = riptables().list_chains(table, "FORWARD").unwrap();
+  assert_eq!(rules.iter().filter(|rule| rule.jump == "ENSURECHAIN").count(), 1);
+
+  // A misplaced jump is moved
+  assert!(riptables().ensure_jump(table, "FORWARD", "ENSURECHAIN", 2, "-p tcp --dport 8080").unwrap());
+  let rules = riptables().list_chains(table, "FORWARD").unwrap();
+  assert!(rules.iter().filter(|rule| rule.archive == Archive::Append).nth(1).unwrap().is_spec("-p tcp --dport 8080 -j ENSURECHAIN"));
+  assert_eq!(rules.iter().filter(|rule| rule.jump == "ENSURECHAIN").count(), 1);
+
+  assert!(riptables().delete(table, "FORWARD", "-j ACCEPT").unwrap());
+  assert!(riptables().delete_chain_recursive(table, "ENSURECHAIN", false).unwrap());
+}
+
+#[test]
+fn test_owned() {
+  let table = "filter";
+  let owner = "riptablestest";
+
+  assert!(riptables().new_chain(table, "OWNEDTEST").unwrap());
+  assert!(riptables().append(table, "OWNEDTEST", "-p tcp --dport 22 -j ACCEPT").unwrap());
+  assert!(riptables().append_owned(table, "OWNEDTEST", "-p tcp --dport 80 -j ACCEPT", owner, "web").unwrap());
+  assert!(riptables().append_owned(table, "OWNEDTEST", "-p tcp --dport 443 -j ACCEPT", owner, "web-tls").unwrap());
+  assert!(riptables().insert_owned(table, "OWNEDTEST", "-s 10.0.0.0/8 -j ACCEPT", 1, owner, "lan").unwrap());
+  assert!(riptables().append_owned_unique(table, "OWNEDTEST", "-s 10.0.0.0/8 -j ACCEPT", owner, "lan").unwrap());
+  assert_eq!(riptables().list_owned(table, owner).unwrap().len(), 3);
+
+  assert_eq!(riptables().gc_owned(table, owner, &["web", "lan"]).unwrap(), 1);
+  assert_eq!(riptables().delete_owned_id(table, owner, "lan").unwrap(), 1);
+  assert_eq!(riptables().list_owned(table, owner).unwrap()[0].owner_tag().unwrap().id, "web");
+  assert_eq!(riptables().delete_owned(table, owner).unwrap(), 1);
+  assert!(riptables().list_owned(table, owner).unwrap().is_empty());
+  assert!(riptables().exists(table, "OWNEDTEST", "-p tcp --dport 22 -j ACCEPT").unwrap());
+
+  assert!(riptables().flush_chain(table, "OWNEDTEST").unwrap());
+  assert!(riptables().delete_chain(table, "OWNEDTEST").unwrap());
+}
+
+#[test]
+fn test_scoped() {
+  let iptables = riptables();
+  let table = "filter";
+  let policy = iptables.get_policy(table, "FORWARD").unwrap();
+
+  let result = panic::catch_unwind(|| {
+    let _chain = iptables.new_chain_scoped(table, "SCOPEDTEST").unwrap();
+    let _rule = iptables.append_scoped(table, "SCOPEDTEST", "-j ACCEPT").unwrap();
+    let _policy = iptables.set_policy_scoped(table, "FORWARD", "DROP").unwrap();
+    assert_eq!(iptables.get_policy(table, "FORWARD").unwrap(), Some("DROP".to_string()));
+    panic!("guards must undo the changes while unwinding");
+  });
+  assert!(result.is_err());
+  assert!(!iptables.exists_chain(table, "SCOPEDTEST").unwrap());
+  assert_eq!(iptables.get_policy(table, "FORWARD").unwrap(), policy);
+
+  let chain = iptables.new_chain_scoped(table, "SCOPEDTEST").unwrap();
+  iptables.insert_scoped(table, "SCOPEDTEST", "-j ACCEPT", 1).unwrap().commit();
+  assert!(iptables.exists(table, "SCOPEDTEST", "-j ACCEPT").unwrap());
+  // the rule was there before, dropping the guard keeps it
+  drop(iptables.insert_scoped(table, "SCOPEDTEST", "-j ACCEPT", 1).unwrap());
+  drop(iptables.append_scoped(table, "SCOPEDTEST", "-j ACCEPT").unwrap());
+  assert_eq!(iptables.list_chains(table, "SCOPEDTEST").unwrap().len(), 2);
+  assert!(chain.undo().unwrap());
+  assert!(!iptables.exists_chain(table, "SCOPEDTEST").unwrap());
+}
+
+#[test]
+fn test_apply_pending() {
+  let iptables = riptables();
+  let table = "filter";
+
+  let mut desired = iptables.save(&[table]).unwrap();
+  desired.table_entry(table).chain_entry("PENDINGTEST");
+
+  let pending = iptables.apply_pending(&desired, Duration::from_millis(100)).unwrap();
+  assert!(iptables.exists_chain(table, "PENDINGTEST").unwrap());
+  assert!(!pending.wait().unwrap());
+  assert!(!iptables.exists_chain(table, "PENDINGTEST").unwrap());
+
+  let pending = iptables.apply_pending(&desired, Duration::from_secs(10)).unwrap();
+  pending.token().confirm();
+  assert!(pending.wait().unwrap());
+  assert!(iptables.exists_chain(table, "PENDINGTEST").unwrap());
+  assert!(iptables.delete_chain(table, "PENDINGTEST").unwrap());
+
+  assert!(!iptables.apply_confirmed(&desired, Duration::from_secs(10), || false).unwrap());
+  assert!(!iptables.exists_chain(table, "PENDINGTEST").unwrap());
+}
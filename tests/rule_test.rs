@@ -0,0 +1,51 @@
+use riptables::rule::{self, ExtensionOption};
+use riptables::ruleset::Ruleset;
+
+#[test]
+fn test_normalize() {
+  assert_eq!(rule::normalize("--dport 22 -p TCP -j ACCEPT"), "-p tcp --dport 22 -j ACCEPT");
+  assert_eq!(rule::normalize("--in-interface eth0 --jump ACCEPT"), "-i eth0 -j ACCEPT");
+  assert_eq!(rule::normalize("-d 192.168.0.0/16 -j DROP"), "-d 192.168.0.0/16 -j DROP");
+  assert_eq!(rule::normalize("! -s 10.0.0.1 -j DROP"), rule::normalize("-s ! 10.0.0.1 -j DROP"));
+  assert_eq!(rule::normalize("-s ::1 -j ACCEPT"), "-s ::1/128 -j ACCEPT");
+  assert_eq!(rule::normalize("-m comment --comment \"a comment\" -j ACCEPT"), "-m comment --comment \"a comment\" -j ACCEPT");
+  assert_ne!(rule::normalize("-p tcp --dport 22 -j ACCEPT"), rule::normalize("-p tcp --dport 23 -j ACCEPT"));
+}
+
+#[test]
+fn test_extensions() {
+  let ruleset = Ruleset::parse("*filter
+:INPUT ACCEPT [0:0]
+-A INPUT -p tcp -m tcp ! --dport 22 -m comment --comment \"web: front\" -j REJECT --reject-with tcp-reset
+-A INPUT -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A INPUT -p tcp -m tcp --syn -m recent --update --seconds 60 -j DROP
+-A INPUT -p ipv6-icmp -m icmp6 --icmpv6-type echo-request -j ACCEPT
+-A INPUT -m comment --comment foo-bar -j REJECT --reject-with icmp-port-unreachable
+COMMIT
+").unwrap();
+  let rules = ruleset.table("filter").unwrap().chain("INPUT").unwrap().rules.clone();
+
+  let tcp = rules[0].extension("tcp").unwrap();
+  assert_eq!(tcp.options, vec![ExtensionOption { negate: true, name: "dport".to_string(), values: vec!["22".to_string()] }]);
+  assert_eq!(rules[0].dport, "22");
+  assert_eq!(rules[0].comment(), Some("web: front"));
+  assert_eq!(rules[0].extensions.len(), 2);
+  assert_eq!(rules[0].jump, "REJECT");
+
+  let conntrack = rules[1].extension("conntrack").unwrap();
+  assert_eq!(conntrack.options[0].values, vec!["RELATED,ESTABLISHED".to_string()]);
+  assert_eq!(rules[1].comment(), None);
+
+  // options without value
+  assert_eq!(rules[2].jump, "DROP");
+  assert_eq!(rules[2].extension("tcp").unwrap().options[0].name, "syn");
+  assert!(rules[2].extension("tcp").unwrap().options[0].values.is_empty());
+  let recent: Vec<&str> = rules[2].extension("recent").unwrap().options.iter().map(|option| &option.name[..]).collect();
+  assert_eq!(recent, vec!["update", "seconds"]);
+
+  // dashes inside values
+  assert_eq!(rules[3].protocol, "ipv6-icmp");
+  assert_eq!(rules[3].extension("icmp6").unwrap().options[0].values, vec!["echo-request".to_string()]);
+  assert_eq!(rules[4].comment(), Some("foo-bar"));
+  assert_eq!(rules[4].jump, "REJECT");
+}
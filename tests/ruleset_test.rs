@@ -0,0 +1,77 @@
+use riptables::ruleset::{self, Change, Ruleset};
+
+const SAVED: &str = "*filter
+:INPUT ACCEPT [0:0]
+:FORWARD DROP [0:0]
+:SSH - [0:0]
+-A INPUT -j SSH
+-A INPUT -m comment --comment \"double-quoted comment\" -j ACCEPT
+-A SSH -p tcp -m tcp --dport 22 -j ACCEPT
+COMMIT
+";
+
+#[test]
+fn test_parse_and_display() {
+  let ruleset = Ruleset::parse(SAVED).unwrap();
+  let filter = ruleset.table("filter").unwrap();
+  assert_eq!(filter.chains.len(), 3);
+  assert_eq!(filter.chain("FORWARD").unwrap().policy, Some("DROP".to_string()));
+  assert_eq!(filter.chain("SSH").unwrap().policy, None);
+  assert_eq!(filter.chain("INPUT").unwrap().rules[1].spec(), "-m comment --comment \"double-quoted comment\" -j ACCEPT");
+  assert_eq!(ruleset.rules().count(), 3);
+
+  let ssh = &filter.chain("SSH").unwrap().rules[0];
+  assert_eq!(ssh.protocol, "tcp");
+  assert_eq!(ssh.dport, "22");
+  assert_eq!(ssh.jump, "ACCEPT");
+
+  let canonical = ruleset.to_string();
+  assert_eq!(canonical, SAVED.replace(" [0:0]", ""));
+  assert_eq!(Ruleset::parse(&canonical).unwrap(), ruleset);
+}
+
+#[test]
+fn test_parse_errors() {
+  assert!(Ruleset::parse("-A INPUT -j ACCEPT\n").is_err());
+  assert!(Ruleset::parse("*filter\n-A INPUT -j ACCEPT\n").is_err());
+  assert!(Ruleset::parse("*filter\n-X INPUT\nCOMMIT\n").is_err());
+}
+
+#[test]
+fn test_diff() {
+  let from = Ruleset::parse(SAVED).unwrap();
+  assert!(ruleset::diff(&from, &from).is_empty());
+
+  let to = Ruleset::parse("*filter
+:INPUT DROP
+:WEB -
+-A INPUT -j WEB
+-A INPUT -m comment --comment \"double-quoted comment\" -j ACCEPT
+COMMIT
+").unwrap();
+  let changes = ruleset::diff(&from, &to);
+  assert_eq!(changes, vec![
+    Change::NewChain { table: "filter".to_string(), chain: "WEB".to_string() },
+    Change::SetPolicy { table: "filter".to_string(), chain: "INPUT".to_string(), policy: "DROP".to_string() },
+    Change::Delete { table: "filter".to_string(), chain: "INPUT".to_string(), position: 1, rule: "-j SSH".to_string() },
+    Change::Insert { table: "filter".to_string(), chain: "INPUT".to_string(), position: 1, rule: "-j WEB".to_string() },
+    Change::Delete { table: "filter".to_string(), chain: "SSH".to_string(), position: 1, rule: "-p tcp -m tcp --dport 22 -j ACCEPT".to_string() },
+    Change::DeleteChain { table: "filter".to_string(), chain: "SSH".to_string() },
+  ]);
+}
+
+#[test]
+fn test_diff_normalized() {
+  let from = Ruleset::parse("*filter
+-A INPUT -s 10.0.0.1/32 -p tcp -m tcp --dport 22 -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+COMMIT
+").unwrap();
+  let to = Ruleset::parse("*filter
+-A INPUT -p tcp -s 10.0.0.1 -m tcp --dport 22 -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A INPUT -j DROP
+COMMIT
+").unwrap();
+  assert_eq!(ruleset::diff(&from, &to), vec![
+    Change::Insert { table: "filter".to_string(), chain: "INPUT".to_string(), position: 2, rule: "-j DROP".to_string() },
+  ]);
+}
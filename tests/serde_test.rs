@@ -0,0 +1,73 @@
+#![cfg(feature = "serde")]
+
+use riptables::rule::{Archive, Extension, ExtensionOption, RIPTInterface, RIPTRule};
+
+fn rule() -> RIPTRule {
+  RIPTRule {
+    origin: "-A INPUT -i eth0 -p tcp -m tcp --dport 22 -j ACCEPT".to_string(),
+    archive: Archive::Append,
+    table: "filter".to_string(),
+    chain: "INPUT".to_string(),
+    input: Some(RIPTInterface { negate: false, value: "eth0".to_string() }),
+    output: None,
+    protocol: "tcp".to_string(),
+    sport: "".to_string(),
+    dport: "22".to_string(),
+    jump: "ACCEPT".to_string(),
+    goto: "".to_string(),
+    extensions: vec![Extension {
+      name: "tcp".to_string(),
+      options: vec![ExtensionOption { negate: false, name: "dport".to_string(), values: vec!["22".to_string()] }],
+    }],
+  }
+}
+
+#[test]
+fn test_json_shape() {
+  let json = serde_json::to_value(rule()).unwrap();
+  assert_eq!(json, serde_json::json!({
+    "origin": "-A INPUT -i eth0 -p tcp -m tcp --dport 22 -j ACCEPT",
+    "archive": "append",
+    "table": "filter",
+    "chain": "INPUT",
+    "input": { "negate": false, "value": "eth0" },
+    "output": null,
+    "protocol": "tcp",
+    "sport": "",
+    "dport": "22",
+    "jump": "ACCEPT",
+    "goto": "",
+    "extensions": [
+      { "name": "tcp", "options": [{ "negate": false, "name": "dport", "values": ["22"] }] }
+    ]
+  }));
+}
+
+#[test]
+fn test_json_round_trip() {
+  let json = serde_json::to_string(&rule()).unwrap();
+  let back: RIPTRule = serde_json::from_str(&json).unwrap();
+  assert_eq!(back, rule());
+  assert_eq!(back.spec(), "-i eth0 -p tcp -m tcp --dport 22 -j ACCEPT");
+}
+
+#[test]
+fn test_json_without_goto() {
+  // the shape before `goto` was added
+  let back: RIPTRule = serde_json::from_value(serde_json::json!({
+    "origin": "-A INPUT -i eth0 -p tcp -m tcp --dport 22 -j ACCEPT",
+    "archive": "append",
+    "table": "filter",
+    "chain": "INPUT",
+    "input": { "negate": false, "value": "eth0" },
+    "output": null,
+    "protocol": "tcp",
+    "sport": "",
+    "dport": "22",
+    "jump": "ACCEPT",
+    "extensions": [
+      { "name": "tcp", "options": [{ "negate": false, "name": "dport", "values": ["22"] }] }
+    ]
+  })).unwrap();
+  assert_eq!(back, rule());
+}
@@ -0,0 +1,59 @@
+use riptables::builder::RuleBuilder;
+use riptables::rule;
+use riptables::services::Services;
+
+const SERVICES: &str = "# Network services
+ssh		22/tcp				# SSH Remote Login Protocol
+domain		53/tcp
+domain		53/udp
+http		80/tcp		www		# WorldWideWeb HTTP
+https		443/tcp
+webcache	8080/tcp
+http-alt	8080/tcp
+broken		port/tcp
+";
+
+const PROTOCOLS: &str = "# Internet protocols
+ip	0	IP		# internet protocol, pseudo protocol number
+icmp	1	ICMP		# internet control message protocol
+tcp	6	TCP		# transmission control protocol
+udp	17	UDP		# user datagram protocol
+ipv6-icmp 58	IPv6-ICMP	# ICMP for IPv6
+";
+
+#[test]
+fn test_parse() {
+  let services = Services::parse(SERVICES, PROTOCOLS);
+  assert_eq!(services.port("ssh", Some("tcp")), Some(22));
+  assert_eq!(services.port("ssh", Some("udp")), None);
+  assert_eq!(services.port("domain", None), Some(53));
+  assert_eq!(services.port("www", Some("TCP")), Some(80));
+  assert_eq!(services.port("http-alt", Some("tcp")), Some(8080));
+  assert_eq!(services.port("8443", Some("udp")), Some(8443));
+  assert_eq!(services.port("broken", None), None);
+
+  assert_eq!(services.ports("ssh,http:https", Some("tcp")), "22,80:443");
+  assert_eq!(services.ports("!https", Some("tcp")), "!443");
+  assert_eq!(services.ports("1024:", Some("tcp")), "1024:");
+  assert_eq!(services.ports("unknown", Some("tcp")), "unknown");
+
+  assert_eq!(services.protocol("TCP"), Some(6));
+  assert_eq!(services.protocol("ipv6-icmp"), Some(58));
+  assert_eq!(services.protocol_name("17"), "udp");
+  assert_eq!(services.protocol_name("IPv6-ICMP"), "ipv6-icmp");
+  assert_eq!(services.protocol_name("all"), "all");
+  assert_eq!(services.protocol_name("0"), "0");
+  assert_eq!(services.protocol_name("gre"), "gre");
+}
+
+#[test]
+#[ignore = "needs /etc/services and /etc/protocols listing https and tcp, run with --ignored"]
+fn test_system() {
+  let services = Services::system();
+  assert_eq!(services.port("https", Some("tcp")), Some(443));
+  assert_eq!(services.protocol("tcp"), Some(6));
+  assert_eq!(rule::normalize("-p 6 -m tcp --dport https -j ACCEPT"), rule::normalize("-p tcp --dport 443 -j ACCEPT"));
+  assert_eq!(rule::normalize("-p tcp -m multiport --dports ssh,https -j ACCEPT"), "-p tcp -m multiport --dports 22,443 -j ACCEPT");
+  assert_eq!(RuleBuilder::new().protocol("TCP").dport("https").jump("ACCEPT").build(), "-p tcp -m tcp --dport 443 -j ACCEPT");
+  assert_eq!(RuleBuilder::new().protocol("!6").build(), "! -p tcp");
+}
@@ -0,0 +1,105 @@
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+
+use riptables::error::RIPTError;
+use riptables::table::{self, ChainName, Policy, Table};
+use riptables::template::Variables;
+use riptables::RIPTables;
+
+#[test]
+fn test_table() {
+  for name in &["filter", "nat", "mangle", "raw", "security", "broute"] {
+    let table: Table = name.parse().unwrap();
+    assert_eq!(table.name(), *name);
+    assert_eq!(table.to_string(), *name);
+    let os: &OsStr = table.as_ref();
+    assert_eq!(os, OsStr::new(name));
+  }
+  assert_eq!(Table::try_from("filter").unwrap(), Table::Filter);
+  assert_eq!(Table::try_from("Filter").unwrap(), Table::Custom("Filter".to_string()));
+  assert!(Table::try_from("").is_err());
+  assert!(Table::try_from("my table").is_err());
+  assert_eq!(Table::Raw.builtin_chains(), Some(&["PREROUTING", "OUTPUT"][..]));
+  assert_eq!(Table::Nat.builtin_chains().unwrap().len(), 3);
+  assert_eq!(Table::Custom("broute".to_string()).builtin_chains(), None);
+}
+
+#[test]
+fn test_chain_name() {
+  let chain: ChainName = "DOCKER-USER".parse().unwrap();
+  assert_eq!(chain.as_str(), "DOCKER-USER");
+  let os: &OsStr = chain.as_ref();
+  assert_eq!(os, OsStr::new("DOCKER-USER"));
+  assert!(ChainName::try_from("A".repeat(28).as_str()).is_ok());
+  for name in &["", "-j", "!INPUT", "MY CHAIN", "a\"b", "tab\there"] {
+    assert!(ChainName::try_from(*name).is_err(), "{}", name);
+  }
+  assert!(ChainName::try_from("A".repeat(29).as_str()).is_err());
+}
+
+#[test]
+fn test_policy() {
+  assert_eq!(Policy::try_from("DROP").unwrap(), Policy::Drop);
+  assert_eq!("accept".parse::<Policy>().unwrap(), Policy::Accept);
+  assert_eq!(Policy::Drop.to_string(), "DROP");
+  assert!(Policy::try_from("REJECT").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_not_utf8() {
+  use std::os::unix::ffi::OsStrExt;
+
+  let name = OsStr::from_bytes(b"IN\xffPUT");
+  assert!(ChainName::try_from(name).is_err());
+  assert!(Table::try_from(name).is_err());
+}
+
+#[test]
+fn test_rejected_before_running() {
+  // iptables is never started for invalid arguments
+  let iptables = RIPTables { cmd: "/nonexistent/iptables", has_check: true, has_wait: true };
+  assert!(iptables.set_policy("filter", "INPUT", "JUMP").is_err());
+  assert!(iptables.append("filter", "-j", "-j ACCEPT").is_err());
+  assert!(iptables.new_chain("", "CHAIN").is_err());
+  assert!(iptables.flush_chain(Table::Filter, "A".repeat(29)).is_err());
+
+  // nor by the helpers built on top of them
+  assert!(matches!(iptables.set_policy_scoped("filter", "INPUT", "JUMP"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.append_scoped("filter", "MY CHAIN", "-j ACCEPT"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.new_chain_scoped("my table", "CHAIN"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.append_owned("filter", "-j", "-j ACCEPT", "app", "web"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.delete_owned("", "app"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.builtin_chains("my table"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.chain_graph("my table"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.lint("my table"), Err(RIPTError::Invalid(_))));
+  assert!(matches!(iptables.append_template("filter", "!INPUT", "-j ACCEPT", &Variables::new()), Err(RIPTError::Invalid(_))));
+}
+
+#[test]
+fn test_loaded() {
+  // whatever the host has loaded, reading the lists works
+  for ipv6 in &[false, true] {
+    let tables = table::loaded_tables(*ipv6).unwrap();
+    let matches = table::loaded_matches(*ipv6).unwrap();
+    let targets = table::loaded_targets(*ipv6).unwrap();
+    for names in &[tables.iter().map(|table| table.to_string()).collect(), matches, targets] {
+      assert!(names.iter().all(|name| !name.is_empty() && name.trim() == name));
+      assert!(names.iter().enumerate().all(|(index, name)| !names[..index].contains(name)));
+    }
+  }
+}
+
+#[test]
+fn test_supports() {
+  // answered without running iptables
+  let iptables = RIPTables { cmd: "iptables", has_check: true, has_wait: true };
+  assert!(iptables.supports_target("ACCEPT").unwrap());
+  assert!(!iptables.supports_match("").unwrap());
+  assert!(!iptables.supports_match("-h").unwrap());
+  assert!(!iptables.supports_target("MARK --help").unwrap());
+  for name in table::loaded_matches(false).unwrap() {
+    assert!(iptables.supports_match(&name).unwrap());
+  }
+  iptables.forget_supported();
+}
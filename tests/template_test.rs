@@ -0,0 +1,51 @@
+use riptables::builder::RuleBuilder;
+use riptables::error::RIPTError;
+use riptables::template::{self, Variables};
+
+fn variables() -> Variables {
+  let mut variables = Variables::new();
+  variables
+    .set_list("WEB_PORTS", &[80, 443])
+    .set_list("TRUSTED_NETS", &["10.0.0.0/8", "192.168.0.0/16"])
+    .set("WAN", "eth0");
+  variables
+}
+
+#[test]
+fn test_expand() {
+  assert_eq!(template::expand("-i $WAN -s $TRUSTED_NETS -p tcp --dport ${WEB_PORTS} -j ACCEPT", &variables()).unwrap(), vec![
+    "-i eth0 -s 10.0.0.0/8 -p tcp --dport 80 -j ACCEPT",
+    "-i eth0 -s 10.0.0.0/8 -p tcp --dport 443 -j ACCEPT",
+    "-i eth0 -s 192.168.0.0/16 -p tcp --dport 80 -j ACCEPT",
+    "-i eth0 -s 192.168.0.0/16 -p tcp --dport 443 -j ACCEPT",
+  ]);
+  assert_eq!(template::expand("-j ACCEPT", &variables()).unwrap(), vec!["-j ACCEPT"]);
+  assert_eq!(template::expand("-m comment --comment \"costs $$5\" -j ACCEPT", &variables()).unwrap(), vec!["-m comment --comment \"costs $5\" -j ACCEPT"]);
+  assert!(template::expand("-p tcp --dport $NOPE", &Variables::new()).unwrap_err().to_string().contains("$NOPE"));
+  assert!(template::expand("-i ${WAN", &variables()).is_err());
+  assert!(template::expand("-i $ -j ACCEPT", &variables()).is_err());
+
+  let mut empty = Variables::new();
+  empty.set_list::<&str>("NONE", &[]);
+  assert!(template::expand("-s $NONE -j DROP", &empty).unwrap().is_empty());
+}
+
+#[test]
+fn test_builder_expand() {
+  let rules = RuleBuilder::new().in_interface("$WAN").protocol("tcp").dport("$WEB_PORTS").jump("ACCEPT").expand(&variables()).unwrap();
+  assert_eq!(rules, vec![
+    "-i eth0 -p tcp -m tcp --dport 80 -j ACCEPT",
+    "-i eth0 -p tcp -m tcp --dport 443 -j ACCEPT",
+  ]);
+  let mut protocols = variables();
+  protocols.set_list("PROTO", &["tcp", "udp"]);
+  let rules = RuleBuilder::new().protocol("$PROTO").dport("53").jump("ACCEPT").expand(&protocols).unwrap();
+  assert_eq!(rules, vec![
+    "-p tcp -m tcp --dport 53 -j ACCEPT",
+    "-p udp -m udp --dport 53 -j ACCEPT",
+  ]);
+  match RuleBuilder::new().source("$UNTRUSTED").jump("DROP").expand(&variables()) {
+    Err(RIPTError::Template(message)) => assert_eq!(message, "undefined variable $UNTRUSTED"),
+    other => panic!("expected a template error, got {:?}", other),
+  }
+}
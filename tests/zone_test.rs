@@ -0,0 +1,65 @@
+use riptables::zone::{self, Service, Zone, ZoneTarget};
+
+#[test]
+fn test_service() {
+  assert_eq!("22/tcp".parse::<Service>().unwrap(), Service::tcp(22));
+  let range: Service = "60000-61000/udp".parse().unwrap();
+  assert_eq!((range.first, range.last), (60000, 61000));
+  assert_eq!(range.to_string(), "60000-61000/udp");
+  for text in &["22", "tcp/22", "0/tcp", "2000-1000/tcp", "22/icmp", "70000/tcp"] {
+    assert!(text.parse::<Service>().is_err(), "{}", text);
+  }
+}
+
+#[test]
+fn test_zone() {
+  let zone = Zone::new("dmz")
+    .interface("eth1")
+    .source("192.0.2.0/24")
+    .service(Service::tcp(443))
+    .service("8000-8100/tcp".parse().unwrap())
+    .target(ZoneTarget::Reject);
+  let chains = zone.chains().unwrap();
+  assert_eq!(chains, vec![
+    ("ZONE-IN-dmz".to_string(), vec![
+      "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT".to_string(),
+      "-p tcp -m tcp --dport 443 -j ACCEPT".to_string(),
+      "-p tcp -m tcp --dport 8000:8100 -j ACCEPT".to_string(),
+      "-j REJECT".to_string(),
+    ]),
+    ("ZONE-FWD-dmz".to_string(), vec![
+      "-m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT".to_string(),
+      "-j REJECT".to_string(),
+    ]),
+  ]);
+
+  let jumps: Vec<(&str, String)> = zone.jumps().unwrap().into_iter().map(|rule| (rule.chain, rule.spec)).collect();
+  assert_eq!(jumps, vec![
+    ("INPUT", "-s 192.0.2.0/24 -m comment --comment riptables-zone:dmz -j ZONE-IN-dmz".to_string()),
+    ("INPUT", "-i eth1 -m comment --comment riptables-zone:dmz -j ZONE-IN-dmz".to_string()),
+    ("FORWARD", "-s 192.0.2.0/24 -m comment --comment riptables-zone:dmz -j ZONE-FWD-dmz".to_string()),
+    ("FORWARD", "-i eth1 -m comment --comment riptables-zone:dmz -j ZONE-FWD-dmz".to_string()),
+  ]);
+  assert!(Zone::new("unbound").jumps().unwrap().is_empty());
+
+  let zones = [
+    Zone::new("public").interface("eth0"),
+    Zone::new("trusted").source("10.0.0.0/8"),
+    zone.clone(),
+  ];
+  let jumps: Vec<(&str, String)> = zone::jumps(&zones).unwrap().into_iter().map(|rule| (rule.chain, rule.spec)).collect();
+  assert_eq!(jumps, vec![
+    ("INPUT", "-s 10.0.0.0/8 -m comment --comment riptables-zone:trusted -j ZONE-IN-trusted".to_string()),
+    ("INPUT", "-s 192.0.2.0/24 -m comment --comment riptables-zone:dmz -j ZONE-IN-dmz".to_string()),
+    ("INPUT", "-i eth0 -m comment --comment riptables-zone:public -j ZONE-IN-public".to_string()),
+    ("INPUT", "-i eth1 -m comment --comment riptables-zone:dmz -j ZONE-IN-dmz".to_string()),
+    ("FORWARD", "-s 10.0.0.0/8 -m comment --comment riptables-zone:trusted -j ZONE-FWD-trusted".to_string()),
+    ("FORWARD", "-s 192.0.2.0/24 -m comment --comment riptables-zone:dmz -j ZONE-FWD-dmz".to_string()),
+    ("FORWARD", "-i eth0 -m comment --comment riptables-zone:public -j ZONE-FWD-public".to_string()),
+    ("FORWARD", "-i eth1 -m comment --comment riptables-zone:dmz -j ZONE-FWD-dmz".to_string()),
+  ]);
+
+  assert!(Zone::new("").chains().is_err());
+  assert!(Zone::new("my zone").chains().is_err());
+  assert!(Zone::new("a-very-long-zone-name").chains().is_err());
+}